@@ -0,0 +1,72 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tracing::warn;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOp {
+    Grant,
+    Revoke,
+}
+
+/// One delivered grant or revoke, as written to the audit log.
+///
+/// Only metadata is recorded: cookie values never reach this file.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuditRecord {
+    pub ts: DateTime<Utc>,
+    pub op: AuditOp,
+    pub op_id: String,
+    pub tab_id: String,
+    pub url: String,
+    pub peer: String,
+    pub cookie_count: usize,
+}
+
+pub fn log_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("sharekaro").join("audit.jsonl"))
+}
+
+/// Id shared by every delivery of a single Share or Revoke click, so the
+/// per-peer audit lines can be grouped back together.
+pub fn new_op_id() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    format!(
+        "{:x}-{:x}",
+        Utc::now().timestamp_millis(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+pub fn append(record: &AuditRecord) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let path = log_path().ok_or("no data directory for the audit log")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+pub fn read_all() -> Result<Vec<AuditRecord>, Box<dyn Error>> {
+    let Some(path) = log_path().filter(|p| p.exists()) else {
+        return Ok(Vec::new());
+    };
+    let mut records = Vec::new();
+    for (n, line) in BufReader::new(fs::File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(e) => warn!("Skipping malformed audit line {}: {}", n + 1, e),
+        }
+    }
+    Ok(records)
+}
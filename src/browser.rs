@@ -0,0 +1,348 @@
+use crate::chrome::{
+    self, CdpEndpoint, ChromeTab, Cookie, CookieReport, IndexedDbEntry, match_cookies_to_url,
+    normalize_same_site, normalize_url,
+};
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::error::Error;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+
+/// The operations a share needs from a browser, whatever protocol it speaks.
+/// Tabs and cookies use Chrome's shapes, since those are what goes over the
+/// wire to peers.
+#[async_trait]
+pub trait Browser: Send + Sync {
+    async fn fetch_tabs(&self) -> Result<Vec<ChromeTab>, Box<dyn Error + Send + Sync>>;
+    async fn get_cookies_for_tab(
+        &self,
+        tab: &ChromeTab,
+    ) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>>;
+    /// Open `url` in a new tab carrying `cookies`, returning the tab's id.
+    async fn import_and_open_with_cookies(
+        &self,
+        cookies: &[Cookie],
+        url: &str,
+        background: bool,
+    ) -> Result<(String, CookieReport), Box<dyn Error + Send + Sync>>;
+    async fn capture_indexed_db(
+        &self,
+        _tab: &ChromeTab,
+    ) -> Result<Vec<IndexedDbEntry>, Box<dyn Error + Send + Sync>> {
+        Err("IndexedDB capture is only supported with Chrome".into())
+    }
+    async fn capture_local_storage(
+        &self,
+        _tab: &ChromeTab,
+    ) -> Result<HashMap<String, String>, Box<dyn Error + Send + Sync>> {
+        Err("localStorage capture is only supported with Chrome".into())
+    }
+}
+
+/// Chrome (or anything else speaking CDP) at `cdp`.
+pub struct ChromeBrowser {
+    pub cdp: CdpEndpoint,
+}
+
+impl ChromeBrowser {
+    pub fn new(cdp: CdpEndpoint) -> Self {
+        Self { cdp }
+    }
+}
+
+#[async_trait]
+impl Browser for ChromeBrowser {
+    async fn fetch_tabs(&self) -> Result<Vec<ChromeTab>, Box<dyn Error + Send + Sync>> {
+        chrome::fetch_tabs(&self.cdp).await
+    }
+
+    async fn get_cookies_for_tab(
+        &self,
+        tab: &ChromeTab,
+    ) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>> {
+        chrome::get_cookies_for_tab(&self.cdp, tab).await
+    }
+
+    async fn import_and_open_with_cookies(
+        &self,
+        cookies: &[Cookie],
+        url: &str,
+        background: bool,
+    ) -> Result<(String, CookieReport), Box<dyn Error + Send + Sync>> {
+        chrome::import_and_open_with_cookies_from_memory(&self.cdp, cookies, url, background).await
+    }
+
+    async fn capture_indexed_db(
+        &self,
+        tab: &ChromeTab,
+    ) -> Result<Vec<IndexedDbEntry>, Box<dyn Error + Send + Sync>> {
+        chrome::capture_indexed_db(&self.cdp, tab).await
+    }
+
+    async fn capture_local_storage(
+        &self,
+        tab: &ChromeTab,
+    ) -> Result<HashMap<String, String>, Box<dyn Error + Send + Sync>> {
+        chrome::capture_local_storage(&self.cdp, tab).await
+    }
+}
+
+/// Firefox started with `--remote-debugging-port`, driven over WebDriver
+/// BiDi at `ws://<endpoint>/session`. Firefox allows one BiDi session at a
+/// time, so each call opens its own and ends it when done.
+pub struct FirefoxBrowser {
+    pub endpoint: CdpEndpoint,
+}
+
+impl FirefoxBrowser {
+    pub fn new(endpoint: CdpEndpoint) -> Self {
+        Self { endpoint }
+    }
+}
+
+#[async_trait]
+impl Browser for FirefoxBrowser {
+    async fn fetch_tabs(&self) -> Result<Vec<ChromeTab>, Box<dyn Error + Send + Sync>> {
+        let mut bidi = BidiSession::open(&self.endpoint).await?;
+        let tree = bidi
+            .call("browsingContext.getTree", json!({ "maxDepth": 0 }))
+            .await?;
+        let contexts = tree["contexts"].as_array().cloned().unwrap_or_default();
+        let mut tabs = Vec::new();
+        for context in contexts {
+            let id = context["context"].as_str().ok_or("missing context id")?;
+            // getTree has no titles; ask each page for its own.
+            let title = bidi
+                .call(
+                    "script.evaluate",
+                    json!({
+                        "expression": "document.title",
+                        "target": { "context": id },
+                        "awaitPromise": false,
+                    }),
+                )
+                .await
+                .ok()
+                .and_then(|r| r["result"]["value"].as_str().map(str::to_string))
+                .unwrap_or_default();
+            tabs.push(ChromeTab {
+                id: id.to_string(),
+                title,
+                url: context["url"].as_str().unwrap_or_default().to_string(),
+                webSocketDebuggerUrl: None,
+                target_type: Some("page".into()),
+                browserContextId: None,
+            });
+        }
+        bidi.end().await;
+        Ok(tabs)
+    }
+
+    async fn get_cookies_for_tab(
+        &self,
+        tab: &ChromeTab,
+    ) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>> {
+        let mut bidi = BidiSession::open(&self.endpoint).await?;
+        let result = bidi
+            .call(
+                "storage.getCookies",
+                json!({ "partition": { "type": "context", "context": tab.id } }),
+            )
+            .await?;
+        bidi.end().await;
+        let cookies = cookies_from_bidi(&result)?;
+        // The partition holds every site's cookies; keep what the tab's URL
+        // would be sent, as Network.getCookies does for Chrome.
+        Ok(match_cookies_to_url(&cookies, &tab.url)?
+            .into_iter()
+            .filter(|(_, applies)| applies.is_ok())
+            .map(|(c, _)| c.clone())
+            .collect())
+    }
+
+    async fn import_and_open_with_cookies(
+        &self,
+        cookies: &[Cookie],
+        url: &str,
+        background: bool,
+    ) -> Result<(String, CookieReport), Box<dyn Error + Send + Sync>> {
+        let to_open = normalize_url(url)?;
+        let mut bidi = BidiSession::open(&self.endpoint).await?;
+        let created = bidi
+            .call(
+                "browsingContext.create",
+                json!({ "type": "tab", "background": background }),
+            )
+            .await?;
+        let context = created["context"]
+            .as_str()
+            .ok_or("missing new tab context")?
+            .to_string();
+
+        let mut rejected = HashMap::new();
+        for cookie in cookies {
+            let params = json!({ "cookie": cookie_to_bidi(cookie) });
+            if let Err(e) = bidi.call("storage.setCookie", params).await {
+                rejected.insert(chrome::cookie_label(cookie), e.to_string());
+            }
+        }
+        let stored = cookies_from_bidi(&bidi.call("storage.getCookies", json!({})).await?)?;
+        let report = chrome::verify_cookies(cookies, &stored, rejected);
+
+        bidi.call(
+            "browsingContext.navigate",
+            json!({ "context": context, "url": to_open, "wait": "none" }),
+        )
+        .await?;
+        bidi.end().await;
+        Ok((context, report))
+    }
+}
+
+type BidiSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// Ended with `end`; one dropped without it ends when its socket closes,
+// which Firefox treats as the end of the session.
+struct BidiSession {
+    socket: BidiSocket,
+    next_id: u64,
+}
+
+impl BidiSession {
+    async fn open(endpoint: &CdpEndpoint) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let url = format!("ws://{}/session", endpoint.authority());
+        let (socket, _) = connect_async(&url)
+            .await
+            .map_err(|e| format!("could not reach Firefox's remote agent at {}: {}", url, e))?;
+        let mut session = Self { socket, next_id: 0 };
+        session
+            .call("session.new", json!({ "capabilities": {} }))
+            .await?;
+        Ok(session)
+    }
+
+    // Send one command and read until its reply arrives, skipping events.
+    async fn call(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let msg = json!({ "id": id, "method": method, "params": params });
+        self.socket
+            .send(Message::Text(msg.to_string().into()))
+            .await?;
+        loop {
+            let frame = self.socket.next().await.ok_or_else(|| {
+                format!("Firefox closed the socket before answering {}", method)
+            })??;
+            let reply: Value = serde_json::from_str(frame.to_text()?)?;
+            if reply["id"] != id {
+                continue;
+            }
+            if reply["type"] == "error" {
+                return Err(format!(
+                    "{} failed: {} ({})",
+                    method,
+                    reply["message"].as_str().unwrap_or_default(),
+                    reply["error"].as_str().unwrap_or("unknown error")
+                )
+                .into());
+            }
+            return Ok(reply["result"].clone());
+        }
+    }
+
+    async fn end(mut self) {
+        let _ = self.call("session.end", json!({})).await;
+        let _ = self.socket.close(None).await;
+    }
+}
+
+fn cookies_from_bidi(result: &Value) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>> {
+    result["cookies"]
+        .as_array()
+        .map(|a| a.iter().map(cookie_from_bidi).collect())
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+// BiDi cookies carry their value as a typed `{type, value}` and spell
+// sameSite in lower case; everything else maps across by name.
+fn cookie_from_bidi(c: &Value) -> Result<Cookie, Box<dyn Error + Send + Sync>> {
+    let same_site = match c["sameSite"].as_str() {
+        Some("strict") => json!("Strict"),
+        Some("lax") => json!("Lax"),
+        Some("none") => json!("None"),
+        _ => Value::Null,
+    };
+    let expiry = c["expiry"].as_f64();
+    Ok(serde_json::from_value(json!({
+        "name": c["name"],
+        "value": c["value"]["value"],
+        "domain": c["domain"],
+        "path": c["path"],
+        "expires": expiry,
+        "session": expiry.is_none(),
+        "httpOnly": c["httpOnly"],
+        "secure": c["secure"],
+        "sameSite": same_site,
+        "size": c["size"],
+    }))?)
+}
+
+fn cookie_to_bidi(cookie: &Cookie) -> Value {
+    let mut c = json!({
+        "name": cookie.name,
+        "value": { "type": "string", "value": cookie.value },
+        "domain": cookie.domain,
+        "path": cookie.path,
+        "httpOnly": cookie.httpOnly.unwrap_or(false),
+        "secure": cookie.secure.unwrap_or(false),
+    });
+    if let Some(same_site) = cookie.sameSite.as_deref().and_then(normalize_same_site) {
+        c["sameSite"] = json!(same_site.to_ascii_lowercase());
+        if same_site == "None" {
+            c["secure"] = json!(true);
+        }
+    }
+    if let Some(expires) = cookie.expires
+        && cookie.session != Some(true)
+        && expires > 0.0
+    {
+        c["expiry"] = json!(expires as u64);
+    }
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bidi_cookies_round_trip_through_chrome_shape() {
+        let bidi = json!({
+            "name": "sid",
+            "value": { "type": "string", "value": "abc" },
+            "domain": ".example.com",
+            "path": "/",
+            "httpOnly": true,
+            "secure": true,
+            "sameSite": "lax",
+            "expiry": 1_900_000_000u64,
+            "size": 6,
+        });
+        let cookie = cookie_from_bidi(&bidi).unwrap();
+        assert_eq!(cookie.value, "abc");
+        assert_eq!(cookie.sameSite.as_deref(), Some("Lax"));
+        assert_eq!(cookie.session, Some(false));
+
+        let back = cookie_to_bidi(&cookie);
+        assert_eq!(back["value"], json!({ "type": "string", "value": "abc" }));
+        assert_eq!(back["sameSite"], "lax");
+        assert_eq!(back["expiry"], 1_900_000_000u64);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::chrome::{self, CdpEndpoint, ChromeTab, Cookie, CookieFormat, CookieReport};
+use std::error::Error;
+
+/// Cookie export and import for use as a library: no window, no share
+/// server, no files written and nothing printed. Each call opens its own
+/// DevTools connection, so a `Cdp` can be kept around while tabs come and go.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let cdp = sharekaro::cdp::Cdp::connect(9222).await?;
+/// for tab in cdp.list_tabs().await? {
+///     println!("{}: {} cookies", tab.url, cdp.get_cookies(&tab).await?.len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cdp {
+    endpoint: CdpEndpoint,
+}
+
+impl Cdp {
+    /// The browser debugging on `localhost:port`.
+    pub async fn connect(port: u16) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::connect_to(CdpEndpoint::new("localhost", port)).await
+    }
+
+    /// Like `connect`, for any endpoint, e.g. one behind an SSH tunnel.
+    /// Fails unless the endpoint answers `/json/version`.
+    pub async fn connect_to(endpoint: CdpEndpoint) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        chrome::cdp_get_json(&endpoint, "/json/version").await?;
+        Ok(Self { endpoint })
+    }
+
+    pub fn endpoint(&self) -> &CdpEndpoint {
+        &self.endpoint
+    }
+
+    /// The user's tabs; see `chrome::fetch_tabs`.
+    pub async fn list_tabs(&self) -> Result<Vec<ChromeTab>, Box<dyn Error + Send + Sync>> {
+        chrome::fetch_tabs(&self.endpoint).await
+    }
+
+    /// The cookies the browser would send to `tab`'s URL.
+    pub async fn get_cookies(
+        &self,
+        tab: &ChromeTab,
+    ) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>> {
+        chrome::get_cookies_for_tab(&self.endpoint, tab).await
+    }
+
+    /// `tab`'s cookies as an export file would hold them, returned rather
+    /// than written; see `chrome::export_cookies`.
+    pub async fn export_cookies(
+        &self,
+        tab: &ChromeTab,
+        format: CookieFormat,
+        annotate_expiry: bool,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        chrome::export_cookies(&self.endpoint, tab, format, annotate_expiry).await
+    }
+
+    /// Put `cookies` in the browser's cookie jar without opening a tab, and
+    /// report which of them it kept; see `chrome::set_cookies`.
+    pub async fn set_cookies(
+        &self,
+        url: &str,
+        cookies: &[Cookie],
+    ) -> Result<CookieReport, Box<dyn Error + Send + Sync>> {
+        chrome::set_cookies(&self.endpoint, url, cookies).await
+    }
+}
@@ -1,112 +1,722 @@
-use reqwest;
-use reqwest::blocking::Response as _;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tempfile::TempDir;
-use tungstenite::{Message, connect};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+use tracing::{debug, error, info, trace, warn};
 use url::Url;
 
-pub fn launch_chrome_with_cdp(use_real_profile: Option<String>) -> (Child, TempDir) {
-    let temp_profile = tempfile::TempDir::new().unwrap();
-    let chrome_path = chrome_path();
-    let profile_path = if use_real_profile
-        .map(|a| a.to_lowercase().eq("default"))
-        .unwrap_or(false)
+/// Where Chrome's DevTools HTTP endpoint lives.
+///
+/// CDP advertises `webSocketDebuggerUrl`s using whatever host Chrome thinks it
+/// is bound to (usually `localhost` or `127.0.0.1`). When the endpoint is
+/// reached through an SSH tunnel or on another machine those URLs point at the
+/// wrong place, so every one we receive goes through `rewrite_ws_url`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CdpEndpoint {
+    pub host: String,
+    pub port: u16,
+    /// List every target `/json` reports, not just the user's own tabs; see
+    /// `is_user_tab`.
+    pub all_targets: bool,
+}
+
+impl Default for CdpEndpoint {
+    fn default() -> Self {
+        Self {
+            host: "localhost".into(),
+            port: 9222,
+            all_targets: false,
+        }
+    }
+}
+
+impl CdpEndpoint {
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            all_targets: false,
+        }
+    }
+
+    pub(crate) fn authority(&self) -> String {
+        if self.host.contains(':') && !self.host.starts_with('[') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    pub fn http_url(&self, path: &str) -> String {
+        format!("http://{}{}", self.authority(), path)
+    }
+
+    pub fn rewrite_ws_url(&self, ws_url: &str) -> String {
+        match Url::parse(ws_url) {
+            Ok(url) => {
+                let rest = &url[url::Position::BeforePath..];
+                format!("{}://{}{}", url.scheme(), self.authority(), rest)
+            }
+            Err(_) => ws_url.to_string(),
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(
+            self.host.as_str(),
+            "localhost" | "127.0.0.1" | "::1" | "[::1]"
+        )
+    }
+}
+
+/// Environment variable naming the browser executable to launch instead of
+/// the discovered Chrome, e.g. a stub that serves a canned CDP endpoint in CI.
+pub const BROWSER_CMD_ENV: &str = "SHAREKARO_BROWSER_CMD";
+
+/// The browser behind a `CdpEndpoint`: one we started, which is ours to
+/// stop, or one that was already listening, which is left alone.
+pub enum ChromeHandle {
+    /// Our process and the throwaway profile it runs on.
+    Spawned(Child, TempDir),
+    Attached,
+}
+
+impl ChromeHandle {
+    /// Kill a browser we spawned and remove its temporary profile. An
+    /// attached browser keeps running.
+    pub fn stop(self) {
+        let ChromeHandle::Spawned(mut child, profile) = self else {
+            return;
+        };
+        if let Err(e) = child.kill() {
+            error!("Failed to stop Chrome: {}", e);
+        }
+        let _ = child.wait();
+        if let Err(e) = profile.close() {
+            warn!("Failed to remove temporary profile: {}", e);
+        }
+    }
+}
+
+/// Attach to a browser already serving DevTools on `cdp`, or start Chrome
+/// for it with `extra_args` after the built-in flags.
+pub async fn launch_chrome_with_cdp(
+    use_real_profile: Option<String>,
+    cdp: &CdpEndpoint,
+    extra_args: &[String],
+) -> Result<ChromeHandle, Box<dyn Error + Send + Sync>> {
+    if devtools_listening(cdp).await {
+        info!(
+            "Chrome is already serving DevTools at {}; attaching to it",
+            cdp.http_url("")
+        );
+        return Ok(ChromeHandle::Attached);
+    }
+    let kind = use_real_profile
+        .as_deref()
+        .and_then(BrowserKind::from_profile)
+        .unwrap_or(BrowserKind::Chrome);
+    if let Some(dir) = real_profile_dir(use_real_profile.as_deref())
+        && let Some(holder) = profile_lock_holder(&dir)
     {
+        // A second Chrome on a locked profile hands off to the running one
+        // and exits, ignoring our --remote-debugging-port.
+        return Err(format!(
+            "{} is already running on {} ({}) without DevTools on port {}. Close it \
+             and start ShareKaro again, or run without --profile to use a throwaway \
+             profile",
+            kind.name(),
+            dir.display(),
+            holder,
+            cdp.port
+        )
+        .into());
+    }
+    let browser = match std::env::var_os(BROWSER_CMD_ENV) {
+        Some(cmd) => PathBuf::from(cmd),
+        None => chrome_path(kind)?,
+    };
+    let (child, profile) = launch_browser(&browser, use_real_profile, cdp, extra_args)?;
+    Ok(ChromeHandle::Spawned(child, profile))
+}
+
+// One quick look at `/json/version`, without `cdp_get_json`'s retries:
+// nothing listening is the usual case at startup.
+async fn devtools_listening(cdp: &CdpEndpoint) -> bool {
+    let Ok(client) = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .build()
+    else {
+        return false;
+    };
+    let Ok(resp) = client
+        .get(cdp.http_url("/json/version"))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+    else {
+        return false;
+    };
+    resp.json::<Value>()
+        .await
+        .is_ok_and(|v| v.get("webSocketDebuggerUrl").is_some())
+}
+
+/// The Chromium-based browsers ShareKaro knows how to find and whose own
+/// profiles `--profile` can point at.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrowserKind {
+    Chrome,
+    Chromium,
+    Brave,
+    Edge,
+}
+
+impl BrowserKind {
+    /// The browser whose existing profile a `--profile` value asks for:
+    /// `default` is Chrome's, `brave-default`, `edge-default`, ... the
+    /// others'. Anything else means a throwaway profile.
+    pub fn from_profile(spec: &str) -> Option<Self> {
+        let spec = spec.to_ascii_lowercase();
+        let browser = match spec.strip_suffix("default")? {
+            "" | "chrome-" => Self::Chrome,
+            "chromium-" => Self::Chromium,
+            "brave-" => Self::Brave,
+            "edge-" => Self::Edge,
+            _ => return None,
+        };
+        Some(browser)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Chrome => "Chrome",
+            Self::Chromium => "Chromium",
+            Self::Brave => "Brave",
+            Self::Edge => "Edge",
+        }
+    }
+
+    // The user data directory, relative to the home directory.
+    fn user_data_dir(self) -> &'static str {
         #[cfg(target_os = "macos")]
-        let path = dirs::home_dir()
-            .unwrap()
-            .join("Library/Application Support/Google/Chrome/Default");
+        let dir = match self {
+            Self::Chrome => "Library/Application Support/Google/Chrome",
+            Self::Chromium => "Library/Application Support/Chromium",
+            Self::Brave => "Library/Application Support/BraveSoftware/Brave-Browser",
+            Self::Edge => "Library/Application Support/Microsoft Edge",
+        };
         #[cfg(target_os = "windows")]
-        let path = dirs::home_dir()
-            .unwrap()
-            .join("AppData/Local/Google/Chrome/User Data/Default");
-        #[cfg(target_os = "linux")]
-        let path = dirs::home_dir()
-            .unwrap()
-            .join(".config/google-chrome/Default");
-        path
+        let dir = match self {
+            Self::Chrome => "AppData/Local/Google/Chrome/User Data",
+            Self::Chromium => "AppData/Local/Chromium/User Data",
+            Self::Brave => "AppData/Local/BraveSoftware/Brave-Browser/User Data",
+            Self::Edge => "AppData/Local/Microsoft/Edge/User Data",
+        };
+        #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+        let dir = match self {
+            Self::Chrome => ".config/google-chrome",
+            Self::Chromium => ".config/chromium",
+            Self::Brave => ".config/BraveSoftware/Brave-Browser",
+            Self::Edge => ".config/microsoft-edge",
+        };
+        dir
+    }
+}
+
+/// The directory of `browser`'s profile called `name` (e.g. `Default`).
+pub fn resolve_profile(browser: BrowserKind, name: &str) -> Option<PathBuf> {
+    Some(dirs::home_dir()?.join(browser.user_data_dir()).join(name))
+}
+
+fn real_profile_dir(use_real_profile: Option<&str>) -> Option<PathBuf> {
+    resolve_profile(BrowserKind::from_profile(use_real_profile?)?, "Default")
+}
+
+/// Who holds Chrome's singleton lock on `profile`, if anyone. Chrome keeps
+/// it in the user data dir, which is `profile` itself or its parent
+/// depending on how it was started. On Unix the lock is a symlink to
+/// `host-pid`; a lock left by a dead process on this host is ignored, as
+/// Chrome would take it over.
+fn profile_lock_holder(profile: &Path) -> Option<String> {
+    let this_host = gethostname::gethostname().to_string_lossy().into_owned();
+    [Some(profile), profile.parent()]
+        .into_iter()
+        .flatten()
+        .find_map(|dir| {
+            let lock = dir.join("SingletonLock");
+            if let Ok(target) = fs::read_link(&lock) {
+                let target = target.to_string_lossy().into_owned();
+                let (host, pid) = target.rsplit_once('-')?;
+                if host == this_host && !process_alive(pid) {
+                    return None;
+                }
+                return Some(format!("pid {} on {}", pid, host));
+            }
+            let lockfile = dir.join("lockfile");
+            (cfg!(windows) && lockfile.exists()).then(|| "lockfile present".to_string())
+        })
+}
+
+fn process_alive(pid: &str) -> bool {
+    if cfg!(target_os = "linux") {
+        Path::new("/proc").join(pid).exists()
     } else {
-        temp_profile.path().to_path_buf()
-    };
-    let child = Command::new(chrome_path)
-        .arg(format!("--remote-debugging-port=9222"))
+        // Without /proc, trust the lock.
+        true
+    }
+}
+
+/// Start `browser` with remote debugging on `cdp.port`. The returned
+/// `TempDir` holds the throwaway profile and must outlive the browser.
+/// `extra_args` are passed through as given, after our own flags, so a later
+/// duplicate wins; making sense of them is left to the browser.
+pub fn launch_browser(
+    browser: &Path,
+    use_real_profile: Option<String>,
+    cdp: &CdpEndpoint,
+    extra_args: &[String],
+) -> Result<(Child, TempDir), Box<dyn Error + Send + Sync>> {
+    let temp_profile = tempfile::TempDir::new()?;
+    let profile_path = real_profile_dir(use_real_profile.as_deref())
+        .unwrap_or_else(|| temp_profile.path().to_path_buf());
+    let child = Command::new(browser)
+        .arg(format!("--remote-debugging-port={}", cdp.port))
         .arg(format!("--user-data-dir={}", profile_path.display()))
+        .args(extra_args)
         .spawn()
-        .expect("Failed to launch Chrome");
-    (child, temp_profile)
+        .map_err(|e| format!("failed to launch {}: {}", browser.display(), e))?;
+    Ok((child, temp_profile))
 }
 
-fn chrome_path() -> String {
+/// Environment variables naming the browser to start, checked in this order
+/// before looking anywhere else. Unlike `BROWSER_CMD_ENV` the path must exist.
+pub const CHROME_PATH_ENVS: [&str; 2] = ["SHAREKARO_CHROME", "CHROME_PATH"];
+
+/// Find `browser`'s executable: `CHROME_PATH_ENVS` first, then `PATH`, then
+/// where the platform records installs and the usual locations. Asking for
+/// Chrome settles for Chromium.
+fn chrome_path(browser: BrowserKind) -> Result<PathBuf, String> {
+    for var in CHROME_PATH_ENVS {
+        if let Some(path) = std::env::var_os(var).filter(|p| !p.is_empty()) {
+            let path = PathBuf::from(path);
+            if path.exists() {
+                return Ok(path);
+            }
+            return Err(format!(
+                "{} is set to {}, which does not exist",
+                var,
+                path.display()
+            ));
+        }
+    }
+    let candidates: &[BrowserKind] = match browser {
+        BrowserKind::Chrome => &[BrowserKind::Chrome, BrowserKind::Chromium],
+        _ => std::slice::from_ref(&browser),
+    };
+    let path_var = std::env::var_os("PATH").unwrap_or_default();
+    let on_path = candidates.iter().find_map(|kind| {
+        exe_names(*kind)
+            .iter()
+            .find_map(|name| find_in_path(name, &path_var))
+    });
+    on_path
+        .or_else(|| candidates.iter().find_map(|kind| installed_browser(*kind)))
+        .ok_or_else(|| {
+            format!(
+                "could not find a {} install; set {} to its executable, or start one \
+                 yourself with --remote-debugging-port and point --cdp-host/--cdp-port at it",
+                browser.name(),
+                CHROME_PATH_ENVS[0]
+            )
+        })
+}
+
+// Executable names to look for on `PATH`.
+fn exe_names(browser: BrowserKind) -> &'static [&'static str] {
+    #[cfg(target_os = "windows")]
+    let names: &[&str] = match browser {
+        BrowserKind::Chrome | BrowserKind::Chromium => &["chrome.exe"],
+        BrowserKind::Brave => &["brave.exe"],
+        BrowserKind::Edge => &["msedge.exe"],
+    };
+    #[cfg(not(target_os = "windows"))]
+    let names: &[&str] = match browser {
+        BrowserKind::Chrome => &["google-chrome-stable", "google-chrome"],
+        BrowserKind::Chromium => &["chromium-browser", "chromium"],
+        BrowserKind::Brave => &["brave-browser", "brave"],
+        BrowserKind::Edge => &["microsoft-edge-stable", "microsoft-edge"],
+    };
+    names
+}
+
+// The first `dir/name` that exists, for each dir in a `PATH`-style list.
+fn find_in_path(name: &str, path_var: &std::ffi::OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path_var)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+fn installed_browser(browser: BrowserKind) -> Option<PathBuf> {
     #[cfg(target_os = "macos")]
     {
-        "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome".to_string()
+        // The bundle id Spotlight knows the app by, and the apps to try in
+        // the usual places, stable channel first.
+        let (bundle_id, apps): (&str, &[&str]) = match browser {
+            BrowserKind::Chrome => (
+                "com.google.Chrome",
+                &[
+                    "Google Chrome.app/Contents/MacOS/Google Chrome",
+                    "Google Chrome Beta.app/Contents/MacOS/Google Chrome Beta",
+                    "Google Chrome Dev.app/Contents/MacOS/Google Chrome Dev",
+                    "Google Chrome Canary.app/Contents/MacOS/Google Chrome Canary",
+                ],
+            ),
+            BrowserKind::Chromium => (
+                "org.chromium.Chromium",
+                &["Chromium.app/Contents/MacOS/Chromium"],
+            ),
+            BrowserKind::Brave => (
+                "com.brave.Browser",
+                &["Brave Browser.app/Contents/MacOS/Brave Browser"],
+            ),
+            BrowserKind::Edge => (
+                "com.microsoft.edgemac",
+                &["Microsoft Edge.app/Contents/MacOS/Microsoft Edge"],
+            ),
+        };
+        let exe = apps[0].split_once('/').map_or("", |(_, exe)| exe);
+        let from_bundle_id = Command::new("mdfind")
+            .arg(format!("kMDItemCFBundleIdentifier == '{}'", bundle_id))
+            .output()
+            .ok()
+            .and_then(|o| {
+                String::from_utf8_lossy(&o.stdout)
+                    .lines()
+                    .map(|app| Path::new(app).join(exe))
+                    .find(|p| p.exists())
+            });
+        let roots: Vec<PathBuf> = dirs::home_dir()
+            .map(|h| h.join("Applications"))
+            .into_iter()
+            .chain([PathBuf::from("/Applications")])
+            .collect();
+        from_bundle_id.or_else(|| {
+            apps.iter()
+                .flat_map(|app| roots.iter().map(move |root| root.join(app)))
+                .find(|p| p.exists())
+        })
     }
     #[cfg(target_os = "windows")]
     {
-        r"C:\Program Files\Google\Chrome\Application\chrome.exe".to_string()
+        // The App Paths key the installer registers, then where per-user
+        // (%LOCALAPPDATA%) and machine-wide (Program Files) installs land.
+        let (app_key, installs): (&str, &[&str]) = match browser {
+            BrowserKind::Chrome => (
+                "chrome.exe",
+                &[
+                    r"Google\Chrome\Application\chrome.exe",
+                    r"Google\Chrome Beta\Application\chrome.exe",
+                ],
+            ),
+            BrowserKind::Chromium => ("", &[r"Chromium\Application\chrome.exe"]),
+            BrowserKind::Brave => (
+                "brave.exe",
+                &[r"BraveSoftware\Brave-Browser\Application\brave.exe"],
+            ),
+            BrowserKind::Edge => ("msedge.exe", &[r"Microsoft\Edge\Application\msedge.exe"]),
+        };
+        const APP_PATHS: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths";
+        let from_registry = ["HKCU", "HKLM"]
+            .iter()
+            .filter(|_| !app_key.is_empty())
+            .find_map(|hive| {
+                let out = Command::new("reg")
+                    .args([
+                        "query",
+                        &format!(r"{}\{}\{}", hive, APP_PATHS, app_key),
+                        "/ve",
+                    ])
+                    .output()
+                    .ok()?;
+                // The default value prints as `    (Default)    REG_SZ    C:\...\chrome.exe`.
+                String::from_utf8_lossy(&out.stdout)
+                    .lines()
+                    .find_map(|l| l.split_once("REG_SZ"))
+                    .map(|(_, path)| PathBuf::from(path.trim().trim_matches('"')))
+                    .filter(|p| p.exists())
+            });
+        from_registry.or_else(|| {
+            ["LOCALAPPDATA", "ProgramFiles", "ProgramFiles(x86)"]
+                .iter()
+                .filter_map(|var| std::env::var_os(var))
+                .flat_map(|dir| {
+                    installs
+                        .iter()
+                        .map(move |app| PathBuf::from(&dir).join(app))
+                })
+                .find(|p| p.exists())
+        })
     }
     #[cfg(target_os = "linux")]
     {
-        use std::process::Command;
-        let candidates = [
-            "google-chrome-stable",
-            "google-chrome",
-            "chromium-browser",
-            "chromium",
-        ];
-        for candidate in &candidates {
-            if Command::new("which")
-                .arg(candidate)
-                .output()
-                .map(|o| o.status.success())
-                .unwrap_or(false)
-            {
-                return candidate.to_string();
+        let (flatpak, snap) = match browser {
+            BrowserKind::Chrome => ("com.google.Chrome", "google-chrome"),
+            BrowserKind::Chromium => ("org.chromium.Chromium", "chromium"),
+            BrowserKind::Brave => ("com.brave.Browser", "brave"),
+            BrowserKind::Edge => ("com.microsoft.Edge", "microsoft-edge"),
+        };
+        let user_flatpak = dirs::data_dir().map(|d| d.join("flatpak/exports/bin"));
+        user_flatpak
+            .into_iter()
+            .chain([PathBuf::from("/var/lib/flatpak/exports/bin")])
+            .map(|dir| dir.join(flatpak))
+            .chain([Path::new("/snap/bin").join(snap)])
+            .find(|p| p.exists())
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        let _ = browser;
+        None
+    }
+}
+
+pub async fn listen_tabs_ws(cdp: &CdpEndpoint) -> Result<(), Box<dyn Error + Send + Sync>> {
+    println!("Listening for tab events (press Ctrl+C to quit)...");
+    // A burst of events between two listings costs one more listing.
+    let changed = tokio::sync::Notify::new();
+    let print = async {
+        loop {
+            changed.notified().await;
+            if let Err(e) = print_tabs_once(cdp).await {
+                warn!("Could not list tabs: {}", e);
             }
         }
-        "google-chrome".to_string()
+    };
+    let (policy, never) = (ReconnectPolicy::default(), AtomicBool::new(false));
+    let watch = watch_targets(
+        cdp,
+        &policy,
+        &never,
+        || changed.notify_one(),
+        |health| {
+            if let EventSocketHealth::Reconnecting { attempt, error } = health {
+                warn!("Tab event socket lost ({}); retry #{}", error, attempt);
+            }
+        },
+    );
+    tokio::select! {
+        () = watch => {}
+        () = print => {}
     }
+    Ok(())
 }
 
-pub fn listen_tabs_ws() -> Result<(), Box<dyn std::error::Error>> {
-    let version_info: Value =
-        reqwest::blocking::get("http://localhost:9222/json/version")?.json()?;
-    let ws_url = version_info["webSocketDebuggerUrl"].as_str().unwrap();
-    let (mut socket, _response) = connect(ws_url)?;
-    let enable_msg = json!({
-        "id": 1,
-        "method": "Target.setDiscoverTargets",
-        "params": { "discover": true }
-    });
-    socket.write_message(Message::Text(enable_msg.to_string().into()))?;
-    print_tabs_once();
-    println!("Listening for tab events (press Ctrl+C to quit)...");
+/// State of the browser-level CDP socket that tab events arrive on.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventSocketHealth {
+    Connecting,
+    Live,
+    Reconnecting { attempt: u32, error: String },
+}
+
+/// How `watch_targets` keeps its socket alive and how it backs off when the
+/// socket drops.
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Quiet period after which a ping is sent. A socket that stays silent
+    /// for another period after the ping is treated as dead.
+    pub keepalive: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            keepalive: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before reconnect attempt number `attempt` (starting at 1).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// How often a waiting `watch_targets` looks at its stop flag.
+const STOP_CHECK: Duration = Duration::from_millis(200);
+
+/// Follow target events on the browser-level CDP socket until `stop` is
+/// set, calling `on_change` after every (re)connect and whenever a target is
+/// created, destroyed or changes. Drops are retried with backoff per
+/// `policy`, and every health transition is reported through `on_health`.
+pub async fn watch_targets(
+    cdp: &CdpEndpoint,
+    policy: &ReconnectPolicy,
+    stop: &AtomicBool,
+    mut on_change: impl FnMut(),
+    mut on_health: impl FnMut(EventSocketHealth),
+) {
+    on_health(EventSocketHealth::Connecting);
+    let mut attempt = 0;
+    while !stop.load(Ordering::Relaxed) {
+        let followed = follow_targets(cdp, policy, stop, &mut on_change, &mut || {
+            attempt = 0;
+            on_health(EventSocketHealth::Live);
+        })
+        .await;
+        let error = match followed {
+            Ok(()) => return,
+            Err(e) => e.to_string(),
+        };
+        attempt += 1;
+        on_health(EventSocketHealth::Reconnecting { attempt, error });
+        let retry_at = Instant::now() + policy.backoff(attempt);
+        while !stop.load(Ordering::Relaxed) && Instant::now() < retry_at {
+            tokio::time::sleep(STOP_CHECK.min(retry_at.saturating_duration_since(Instant::now())))
+                .await;
+        }
+    }
+}
+
+// Returns Ok only once `stop` is set.
+async fn follow_targets(
+    cdp: &CdpEndpoint,
+    policy: &ReconnectPolicy,
+    stop: &AtomicBool,
+    on_change: &mut impl FnMut(),
+    on_live: &mut impl FnMut(),
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut browser = CdpSession::browser(cdp).await?;
+    browser
+        .send_raw("Target.setDiscoverTargets", json!({ "discover": true }))
+        .await?;
+    on_live();
+    on_change();
+
+    let mut awaiting_pong = false;
+    let mut heard_at = Instant::now();
     loop {
-        let msg = socket.read_message()?;
-        if msg.is_text() {
-            let text = msg.to_text()?;
-            if let Ok(event) = serde_json::from_str::<Value>(text) {
-                if let Some(method) = event.get("method") {
-                    if method == "Target.targetCreated"
-                        || method == "Target.targetDestroyed"
-                        || method == "Target.targetInfoChanged"
-                    {
-                        print_tabs_once();
+        if stop.load(Ordering::Relaxed) {
+            let _ = browser.socket.close(None).await;
+            return Ok(());
+        }
+        let msg =
+            match tokio::time::timeout(policy.keepalive.min(STOP_CHECK), browser.socket.next())
+                .await
+            {
+                Ok(Some(msg)) => msg?,
+                Ok(None) => return Err("the browser closed the socket".into()),
+                Err(_) => {
+                    if heard_at.elapsed() < policy.keepalive {
+                        continue;
+                    }
+                    heard_at = Instant::now();
+                    if awaiting_pong {
+                        return Err("no reply to keepalive".into());
                     }
+                    // Replies aren't waited for here; any traffic counts.
+                    let ping = json!({ "id": browser.next_id(), "method": "Browser.getVersion" });
+                    browser
+                        .socket
+                        .send(Message::Text(ping.to_string().into()))
+                        .await?;
+                    awaiting_pong = true;
+                    continue;
+                }
+            };
+        awaiting_pong = false;
+        heard_at = Instant::now();
+        if msg.is_text()
+            && let Ok(event) = serde_json::from_str::<Value>(msg.to_text()?)
+            && let Some(method) = event.get("method")
+            && (method == "Target.targetCreated"
+                || method == "Target.targetDestroyed"
+                || method == "Target.targetInfoChanged")
+        {
+            on_change();
+        }
+    }
+}
+
+/// The URLs one tab's main frame moves to, from `follow_navigations`.
+pub struct Navigations {
+    page: CdpSession,
+    main_frame: Option<String>,
+}
+
+/// Start following `tab`'s main frame, including same-document history
+/// changes. Dropping the result stops following it.
+pub async fn follow_navigations(
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+) -> Result<Navigations, Box<dyn Error + Send + Sync>> {
+    let mut page = CdpSession::page(cdp, tab).await?;
+    page.send_raw("Page.enable", json!({})).await?;
+    let tree = page.send_raw("Page.getFrameTree", json!({})).await?;
+    let main_frame = tree["frameTree"]["frame"]["id"]
+        .as_str()
+        .map(str::to_string);
+    Ok(Navigations { page, main_frame })
+}
+
+impl Navigations {
+    /// The next URL the main frame moves to. Fails once the tab's socket
+    /// does, e.g. because the tab closed.
+    pub async fn next(&mut self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        loop {
+            let msg = self
+                .page
+                .socket
+                .next()
+                .await
+                .ok_or("the tab closed its socket")??;
+            let Ok(v) = serde_json::from_str::<Value>(msg.to_text().unwrap_or_default()) else {
+                continue;
+            };
+            let frame = &v["params"]["frame"];
+            if v["method"] == "Page.frameNavigated" && frame["parentId"].is_null() {
+                self.main_frame = frame["id"].as_str().map(str::to_string);
+                if let Some(url) = frame["url"].as_str() {
+                    return Ok(url.to_string());
                 }
+            } else if v["method"] == "Page.navigatedWithinDocument"
+                && v["params"]["frameId"].as_str() == self.main_frame.as_deref()
+                && let Some(url) = v["params"]["url"].as_str()
+            {
+                return Ok(url.to_string());
             }
         }
     }
 }
 
-pub fn print_tabs_once() {
-    let tabs: Vec<Value> = reqwest::blocking::get("http://localhost:9222/json")
-        .and_then(|resp| resp.json())
-        .unwrap_or_default();
+pub async fn print_tabs_once(cdp: &CdpEndpoint) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let tabs = cdp_get_json(cdp, "/json").await?;
+    let tabs = tabs
+        .as_array()
+        .ok_or("/json did not return a list of tabs")?;
     println!("\x1b[2J\x1b[1;1H");
     println!("Current Chrome tabs:");
     for (i, tab) in tabs.iter().enumerate() {
@@ -115,65 +725,403 @@ pub fn print_tabs_once() {
         println!("[{}] \"{}\"\n    {}", i, title, url);
     }
     println!("--- (event-driven; updates instantly) ---");
+    Ok(())
+}
+
+/// How many times `cdp_get_json` tries to reach a DevTools endpoint that
+/// refuses connections, e.g. a Chrome that is still starting up.
+const CDP_CONNECT_ATTEMPTS: u32 = 3;
+
+/// GET `path` from the DevTools HTTP endpoint and parse the reply as JSON.
+/// Connection failures are retried briefly; errors name the URL involved.
+pub async fn cdp_get_json(
+    cdp: &CdpEndpoint,
+    path: &str,
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    let url = cdp.http_url(path);
+    let mut attempt = 1;
+    let resp = loop {
+        match reqwest::get(&url).await {
+            Ok(resp) => break resp,
+            Err(e) if e.is_connect() && attempt < CDP_CONNECT_ATTEMPTS => {
+                tokio::time::sleep(Duration::from_millis(200 * u64::from(attempt))).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(format!("could not reach Chrome at {}: {}", url, e).into()),
+        }
+    };
+    resp.json()
+        .await
+        .map_err(|e| format!("{} did not return JSON: {}", url, e).into())
+}
+
+// The browser-level socket from `/json/version`, used for `Target.*`.
+async fn browser_ws_url(cdp: &CdpEndpoint) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let version_info = cdp_get_json(cdp, "/json/version").await?;
+    let ws_url = version_info["webSocketDebuggerUrl"]
+        .as_str()
+        .ok_or_else(|| {
+            format!(
+                "{} has no webSocketDebuggerUrl; is Chrome still starting?",
+                cdp.http_url("/json/version")
+            )
+        })?;
+    Ok(cdp.rewrite_ws_url(ws_url))
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
 pub struct ChromeTab {
     pub id: String,
     pub title: String,
     pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub webSocketDebuggerUrl: Option<String>,
+    /// `page`, `background_page`, `service_worker`, ... Missing from some
+    /// CDP implementations, which only list pages anyway.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub target_type: Option<String>,
+    /// Which profile (or incognito window) the tab belongs to, when the
+    /// listing says.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub browserContextId: Option<String>,
 }
 
-pub fn fetch_tabs() -> Result<Vec<ChromeTab>, Box<dyn std::error::Error>> {
-    let tabs: Vec<ChromeTab> = reqwest::blocking::get("http://localhost:9222/json")?.json()?;
-    Ok(tabs)
+/// Whether a `/json` target is a tab the user opened, as opposed to an
+/// extension's background page, a service worker or a DevTools window.
+pub fn is_user_tab(tab: &ChromeTab) -> bool {
+    matches!(tab.target_type.as_deref(), None | Some("page"))
+        && !tab.url.starts_with("devtools://")
+        && !tab.url.starts_with("chrome-extension://")
 }
 
-pub fn export_cookies_for_tab(tab: &ChromeTab) -> Result<String, Box<dyn std::error::Error>> {
-    use std::fs::File;
-    use std::io::Write;
+pub async fn fetch_tabs(cdp: &CdpEndpoint) -> Result<Vec<ChromeTab>, Box<dyn Error + Send + Sync>> {
+    let tabs: Vec<ChromeTab> = serde_json::from_value(cdp_get_json(cdp, "/json").await?)?;
+    let mut seen = HashSet::new();
+    Ok(tabs
+        .into_iter()
+        .filter(|t| cdp.all_targets || is_user_tab(t))
+        .filter(|t| seen.insert(t.id.clone()))
+        .map(|mut t| {
+            t.webSocketDebuggerUrl = t.webSocketDebuggerUrl.map(|ws| cdp.rewrite_ws_url(&ws));
+            t
+        })
+        .collect())
+}
 
-    let ws_url = if let Some(ws) = &tab.webSocketDebuggerUrl {
-        ws.clone()
-    } else {
-        get_ws_url_for_tab(&tab.id)?
-    };
+/// The tab the user is looking at in Chrome. `/json` lists pages most
+/// recently focused first, so this is the first web page in the listing.
+pub async fn get_active_tab(cdp: &CdpEndpoint) -> Result<ChromeTab, Box<dyn Error + Send + Sync>> {
+    fetch_tabs(cdp)
+        .await?
+        .into_iter()
+        .find(|t| normalize_url(&t.url).is_ok())
+        .ok_or_else(|| "no web page is open in Chrome".into())
+}
 
-    let (mut socket, _) = connect(ws_url)?;
+/// Look a tab up again by id against a fresh `/json` listing.
+///
+/// The GUI acts on a snapshot that can be a few hundred milliseconds old, so
+/// any CDP action should go through this first rather than trusting the
+/// snapshot's `webSocketDebuggerUrl`.
+pub async fn resolve_tab(
+    cdp: &CdpEndpoint,
+    tab_id: &str,
+) -> Result<ChromeTab, Box<dyn Error + Send + Sync>> {
+    fetch_tabs(cdp)
+        .await?
+        .into_iter()
+        .find(|t| t.id == tab_id)
+        .ok_or_else(|| "tab closed".into())
+}
 
-    let msg = json!({
-        "id": 1,
-        "method": "Network.getCookies",
-        "params": { "urls": [ &tab.url ] }
-    });
+pub const DEFAULT_EXPORT_TEMPLATE: &str = "cookies_{title}.{ext}";
+
+/// File format for cookie exports.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CookieFormat {
+    /// CDP's cookie objects, which import reads back.
+    #[default]
+    Json,
+    /// The tab-separated `cookies.txt` that curl, wget and yt-dlp read.
+    Netscape,
+}
+
+impl CookieFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            CookieFormat::Json => "json",
+            CookieFormat::Netscape => "txt",
+        }
+    }
+}
+
+/// Expand an export filename template. Supported tokens are `{title}`,
+/// `{domain}`, `{id}`, `{date}` (YYYY-MM-DD) and `{ext}` (from `format`); each
+/// substituted value is made safe to use as part of a filename.
+pub fn export_filename(
+    template: &str,
+    tab: &ChromeTab,
+    format: CookieFormat,
+    now: chrono::DateTime<chrono::Utc>,
+) -> String {
+    let domain = Url::parse(&tab.url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_default();
+    let tokens = [
+        ("{title}", tab.title.clone()),
+        ("{domain}", domain),
+        ("{id}", tab.id.clone()),
+        ("{date}", now.format("%Y-%m-%d").to_string()),
+        ("{ext}", format.extension().to_string()),
+    ];
+    tokens
+        .iter()
+        .fold(template.to_string(), |name, (token, value)| {
+            name.replace(token, &sanitize_filename(value))
+        })
+}
+
+// A leading dot would hide the file, or with `..` climb out of the export
+// folder, so those are dropped.
+fn sanitize_filename(s: &str) -> String {
+    s.trim_start_matches('.')
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' | ' ' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Write the tab's cookies, as `export_cookies` renders them, to a file in
+/// `dir` named from `filename_template`, and return its path.
+pub async fn export_cookies_for_tab(
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+    dir: &Path,
+    filename_template: &str,
+    format: CookieFormat,
+    annotate_expiry: bool,
+) -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
+    let contents = export_cookies(cdp, tab, format, annotate_expiry).await?;
+    let path = dir.join(export_filename(
+        filename_template,
+        tab,
+        format,
+        chrono::Utc::now(),
+    ));
+    write_atomically(&path, contents.as_bytes())?;
+    Ok(path)
+}
+
+/// Where exports go until the user picks a folder: the OS downloads
+/// directory, or the working directory if there is none.
+pub fn default_export_dir() -> PathBuf {
+    dirs::download_dir().unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// The tab's cookies as an export file holds them. With `annotate_expiry`,
+/// each cookie in a JSON export also gets an `expiresReadable` field next to
+/// the raw `expires` epoch; import never reads it back. `cookies.txt` has no
+/// room for it.
+pub async fn export_cookies(
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+    format: CookieFormat,
+    annotate_expiry: bool,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut page = CdpSession::page(cdp, tab).await?;
+    let mut cookies = page
+        .send_raw("Network.getCookies", json!({ "urls": [&tab.url] }))
+        .await?["cookies"]
+        .take();
+    let now = chrono::Utc::now();
+    if annotate_expiry && let Some(list) = cookies.as_array_mut() {
+        for cookie in list {
+            let readable =
+                describe_expiry(cookie["expires"].as_f64(), cookie["session"].as_bool(), now);
+            cookie["expiresReadable"] = json!(readable);
+        }
+    }
 
-    socket.write_message(Message::Text(msg.to_string().into()))?;
+    Ok(match format {
+        CookieFormat::Json => serde_json::to_string_pretty(&cookies)?,
+        CookieFormat::Netscape => to_netscape(&serde_json::from_value::<Vec<Cookie>>(cookies)?),
+    })
+}
 
-    let reply = socket.read_message()?.into_text()?;
-    let value: serde_json::Value = serde_json::from_str(&reply)?;
-    let cookies = value["result"]["cookies"].clone();
+/// Read a Netscape `cookies.txt`, the reverse of `to_netscape`. Comments,
+/// blank lines and lines without all seven columns are skipped; expiry 0
+/// means a session cookie.
+pub fn parse_netscape(content: &str) -> Vec<Cookie> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (http_only, line) = match line.strip_prefix("#HttpOnly_") {
+                Some(rest) => (true, rest),
+                None if line.starts_with('#') => return None,
+                None => (false, line),
+            };
+            let cols: Vec<&str> = line.trim_end_matches('\r').splitn(7, '\t').collect();
+            let [domain, _subdomains, path, secure, expiry, name, value] = cols[..] else {
+                return None;
+            };
+            let expiry: f64 = expiry.trim().parse().ok()?;
+            let session = expiry <= 0.0;
+            serde_json::from_value(json!({
+                "domain": domain,
+                "path": path,
+                "secure": secure.eq_ignore_ascii_case("TRUE"),
+                "expires": if session { -1.0 } else { expiry },
+                "session": session,
+                "httpOnly": http_only,
+                "name": name,
+                "value": value,
+            }))
+            .ok()
+        })
+        .collect()
+}
 
-    let filename = format!(
-        "cookies_{}.json",
-        tab.title.replace(' ', "_").replace('/', "_")
+/// Render cookies as a Netscape `cookies.txt`: one tab-separated line of
+/// domain, include-subdomains, path, secure, expiry, name and value each.
+/// Domain cookies keep their leading dot, session cookies get expiry 0 and
+/// HttpOnly ones are written under curl's `#HttpOnly_` prefix.
+pub fn to_netscape(cookies: &[Cookie]) -> String {
+    let flag = |b: bool| if b { "TRUE" } else { "FALSE" };
+    let mut out = String::from(
+        "# Netscape HTTP Cookie File\n# Exported by ShareKaro; edit at your own risk.\n\n",
     );
-    let mut file = File::create(&filename)?;
-    file.write_all(serde_json::to_string_pretty(&cookies)?.as_bytes())?;
-    Ok(filename)
+    for c in cookies {
+        let expiry = match c.expires {
+            Some(e) if e > 0.0 && c.session != Some(true) => e as i64,
+            _ => 0,
+        };
+        out.push_str(&format!(
+            "{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            if c.httpOnly == Some(true) {
+                "#HttpOnly_"
+            } else {
+                ""
+            },
+            c.domain,
+            flag(c.domain.starts_with('.')),
+            c.path,
+            flag(c.secure == Some(true)),
+            expiry,
+            c.name,
+            c.value
+        ));
+    }
+    out
 }
 
-pub fn get_ws_url_for_tab(tab_id: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let tabs: Value = reqwest::blocking::get("http://localhost:9222/json")?.json()?;
-    for tab in tabs.as_array().unwrap() {
-        if tab["id"] == tab_id {
-            return Ok(tab["webSocketDebuggerUrl"].as_str().unwrap().to_string());
+/// How far off a cookie's expiry is, e.g. `in 3 days`, `expired 2 hours ago`
+/// or `session`. Chrome reports session cookies with `expires` at -1.
+pub fn relative_expiry(
+    expires: Option<f64>,
+    session: Option<bool>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> String {
+    let Some(expires) = expires.filter(|e| *e > 0.0 && session != Some(true)) else {
+        return "session".into();
+    };
+    let delta = expires - now.timestamp() as f64;
+    let secs = delta.abs() as u64;
+    let span = match secs {
+        0..60 => {
+            return if delta >= 0.0 {
+                "in under a minute"
+            } else {
+                "just expired"
+            }
+            .into();
         }
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    let span = format!(
+        "{} {}{}",
+        span.0,
+        span.1,
+        if span.0 == 1 { "" } else { "s" }
+    );
+    if delta >= 0.0 {
+        format!("in {}", span)
+    } else {
+        format!("expired {} ago", span)
+    }
+}
+
+// `2024-05-09T07:08:09Z (in 3 days)`, or just the relative part for session
+// cookies, which have no date to print.
+fn describe_expiry(
+    expires: Option<f64>,
+    session: Option<bool>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> String {
+    let relative = relative_expiry(expires, session, now);
+    match expires
+        .filter(|_| relative != "session")
+        .and_then(|e| chrono::DateTime::from_timestamp(e as i64, 0))
+    {
+        Some(at) => format!(
+            "{} ({})",
+            at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            relative
+        ),
+        None => relative,
+    }
+}
+
+/// Write `contents` to a temp file next to `path` and rename it into place,
+/// so a failed or interrupted export never leaves a truncated file behind or
+/// clobbers the previous good one.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(contents)?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+pub async fn get_ws_url_for_tab(
+    cdp: &CdpEndpoint,
+    tab_id: &str,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let tabs = cdp_get_json(cdp, "/json").await?;
+    let entry = tabs
+        .as_array()
+        .ok_or("/json did not return a list of tabs")?
+        .iter()
+        .find(|t| t["id"] == tab_id)
+        .ok_or("tab closed")?;
+    entry["webSocketDebuggerUrl"]
+        .as_str()
+        .map(|ws| cdp.rewrite_ws_url(ws))
+        .ok_or_else(|| "WebSocketDebuggerUrl not found for tab".into())
+}
+
+async fn tab_ws_url(
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    match &tab.webSocketDebuggerUrl {
+        Some(ws) => Ok(cdp.rewrite_ws_url(ws)),
+        None => get_ws_url_for_tab(cdp, &tab.id).await,
     }
-    Err("WebSocketDebuggerUrl not found for tab".into())
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[allow(non_snake_case)]
 pub struct Cookie {
     pub domain: String,
     pub expires: Option<f64>,
@@ -193,76 +1141,39 @@ pub struct Cookie {
     pub extra: std::collections::HashMap<String, serde_json::Value>,
 }
 
-pub fn import_and_open_with_cookies(
+pub async fn import_and_open_with_cookies(
+    cdp: &CdpEndpoint,
     cookie_path: &std::path::Path,
     url: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+    background: bool,
+) -> Result<CookieReport, Box<dyn Error + Send + Sync>> {
     let cookies = match universal_cookie_loader(cookie_path) {
         Ok(c) => c,
         Err(e) => {
-            eprintln!("JSON decode error: {}", e);
+            error!("JSON decode error: {}", e);
             return Err(e);
         }
     };
-
-    let to_open = normalize_url(&url);
-
-    let resp = reqwest::blocking::Client::new()
-        .put(&format!("http://localhost:9222/json/new?{}", to_open))
-        .send()?;
-    let body = resp.text()?;
-    let new_tab: Value = serde_json::from_str(&body)?;
-
-    let ws_url = new_tab["webSocketDebuggerUrl"].as_str().unwrap();
-    let (mut socket, _) = connect(ws_url)?;
-
-    let enable_msg = json!({
-        "id": 1, "method": "Network.enable"
-    });
-
-    socket.write_message(Message::Text(enable_msg.to_string().into()))?;
-
-    for (i, cookie) in cookies.iter().enumerate() {
-        let mut params = serde_json::Map::new();
-        params.insert("name".to_string(), json!(cookie.name));
-        params.insert("value".to_string(), json!(cookie.value));
-        params.insert("domain".to_string(), json!(cookie.domain));
-        params.insert("path".to_string(), json!(cookie.path));
-        if let Some(exp) = cookie.expires {
-            params.insert("expires".to_string(), json!(exp));
-        }
-        if let Some(secure) = cookie.secure {
-            params.insert("secure".to_string(), json!(secure));
-        }
-        if let Some(http_only) = cookie.httpOnly {
-            params.insert("httpOnly".to_string(), json!(http_only));
-        }
-        if let Some(ref samesite) = cookie.sameSite {
-            params.insert("sameSite".to_string(), json!(samesite));
-        }
-
-        let msg = json!({
-            "id": 2 + i as u64,
-            "method": "Network.setCookie",
-            "params": params
-        });
-        socket.write_message(Message::Text(msg.to_string().into()))?;
-    }
-    let nav_msg = json!({
-        "id": 10000,
-        "method": "Page.navigate",
-        "params": {"url": to_open}
-    });
-    socket.write_message(Message::Text(nav_msg.to_string().into()))?;
-    Ok(())
+    let (_, report) =
+        import_and_open_with_cookies_from_memory(cdp, &cookies, url, background).await?;
+    Ok(report)
 }
 pub fn universal_cookie_loader(
     path: &std::path::Path,
-) -> Result<Vec<Cookie>, Box<dyn std::error::Error>> {
+) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>> {
     let content = fs::read_to_string(path)?;
-    let value: serde_json::Value = serde_json::from_str(&content)?;
+    let value: serde_json::Value = match serde_json::from_str(&content) {
+        Ok(value) => value,
+        Err(e) => {
+            let cookies = parse_netscape(&content);
+            if cookies.is_empty() {
+                return Err(format!("neither cookie JSON ({}) nor a cookies.txt", e).into());
+            }
+            return Ok(cookies);
+        }
+    };
 
-    if let Some(arr) = value.as_array() {
+    if value.is_array() {
         let cookies: Vec<Cookie> = serde_json::from_value(value)?;
         return Ok(cookies);
     }
@@ -274,173 +1185,1885 @@ pub fn universal_cookie_loader(
 
     Err("Unknown cookie JSON format".into())
 }
-fn normalize_url(raw: &str) -> String {
-    if raw.starts_with("http://") || raw.starts_with("https://") {
-        raw.to_owned()
-    } else {
-        format!("https://{}", raw)
-    }
+/// Open a new page target on `url` and return CDP's description of it.
+pub async fn open_tab(cdp: &CdpEndpoint, url: &str) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    let resp = reqwest::Client::new()
+        .put(cdp.http_url(&format!("/json/new?{}", url)))
+        .send()
+        .await?;
+    let body = resp.text().await?;
+    Ok(serde_json::from_str(&body)?)
 }
-pub fn import_and_open_with_cookies_from_memory(
-    cookies: &[Cookie],
-    url: &str,
-) -> Result<String, Box<dyn Error>> {
-    let to_open = if url.starts_with("http://") || url.starts_with("https://") {
-        url.to_string()
-    } else {
-        format!("https://{}", url)
-    };
-    let cdp_url = format!("http://localhost:9222/json/new?{}", to_open);
-    let resp = reqwest::blocking::Client::new().put(&cdp_url).send()?;
-    let body = resp.text()?;
-    let new_tab: serde_json::Value = serde_json::from_str(&body)?;
-    let local_tab_id = new_tab["id"]
-        .as_str()
-        .ok_or("missing new tab ID")?
-        .to_string();
 
-    let ws_url = new_tab["webSocketDebuggerUrl"]
-        .as_str()
-        .ok_or("missing webSocketDebuggerUrl")?;
+/// Like `open_tab`, but with `background` the tab is created through
+/// `Target.createTarget` so whatever the user is looking at stays in front.
+/// `/json/new` always focuses the new tab.
+pub async fn open_import_tab(
+    cdp: &CdpEndpoint,
+    url: &str,
+    background: bool,
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    if !background {
+        return open_tab(cdp, url).await;
+    }
+    let created = CdpSession::browser(cdp)
+        .await?
+        .send_raw(
+            "Target.createTarget",
+            json!({ "url": url, "background": true }),
+        )
+        .await?;
+    let id = created["targetId"].as_str().ok_or("missing targetId")?;
+    // The socket path is Chrome's to choose; take it from the listing.
+    let ws_url = get_ws_url_for_tab(cdp, id).await?;
+    Ok(json!({ "id": id, "webSocketDebuggerUrl": ws_url }))
+}
 
-    let (mut socket, _) = connect(ws_url)?;
-    let enable = json!({ "id": 1, "method": "Network.enable" });
-    socket.write_message(Message::Text(enable.to_string().into()))?;
+/// The id in a `/json/new` (or `open_import_tab`) response. Some Chrome
+/// versions have sent it as a number rather than a string.
+pub fn new_tab_id(tab: &Value) -> Result<String, Box<dyn Error + Send + Sync>> {
+    match &tab["id"] {
+        Value::String(id) if !id.is_empty() => Ok(id.clone()),
+        Value::Number(id) => Ok(id.to_string()),
+        _ => Err("missing new tab ID".into()),
+    }
+}
 
-    for (i, cookie) in cookies.iter().enumerate() {
-        let mut params = serde_json::Map::new();
-        params.insert("name".into(), json!(cookie.name));
-        params.insert("value".into(), json!(cookie.value));
-        params.insert("domain".into(), json!(cookie.domain));
-        params.insert("path".into(), json!(cookie.path));
-        if let Some(ex) = cookie.expires {
-            params.insert("expires".into(), json!(ex));
-        }
-        if let Some(true) = cookie.secure {
-            params.insert("secure".into(), json!(true));
-        }
-        if let Some(true) = cookie.httpOnly {
-            params.insert("httpOnly".into(), json!(true));
-        }
-        if let Some(ss) = &cookie.sameSite {
-            params.insert("sameSite".into(), json!(ss));
-        }
+/// Schemes that name browser-internal pages or scripts rather than a web
+/// origin. Cookies mean nothing there, so imports refuse them outright instead
+/// of prefixing `https://` and navigating somewhere surprising.
+const NON_WEB_SCHEMES: &[&str] = &[
+    "about",
+    "blob",
+    "chrome",
+    "chrome-extension",
+    "chrome-untrusted",
+    "data",
+    "devtools",
+    "edge",
+    "file",
+    "filesystem",
+    "javascript",
+    "view-source",
+];
 
-        let msg = json!({
-            "id": 2 + i as u64,
-            "method": "Network.setCookie",
-            "params": params,
-        });
-        socket.write_message(Message::Text(msg.to_string().into()))?;
+/// Normalise a tab or manifest URL for navigation, defaulting to `https://`
+/// when no scheme is given and rejecting non-web URLs.
+pub fn normalize_url(raw: &str) -> Result<String, String> {
+    let raw = raw.trim();
+    if raw.starts_with("http://") || raw.starts_with("https://") {
+        return Ok(raw.to_owned());
     }
+    if let Some((scheme, _)) = raw.split_once(':')
+        && NON_WEB_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str())
+    {
+        return Err(format!(
+            "refusing to open {}: cookies only apply to http(s) pages",
+            raw
+        ));
+    }
+    Ok(format!("https://{}", raw))
+}
+/// Keep the cookies whose domain matches one of `patterns`, globs where `*`
+/// stands for any run of characters, compared case-insensitively and without
+/// the leading dot. `*.example.com` also matches `example.com` itself. No
+/// patterns keeps everything.
+pub fn filter_cookies(cookies: Vec<Cookie>, patterns: &[String]) -> Vec<Cookie> {
+    if patterns.is_empty() {
+        return cookies;
+    }
+    cookies
+        .into_iter()
+        .filter(|c| {
+            let domain = c.domain.trim_start_matches('.').to_ascii_lowercase();
+            patterns.iter().any(|p| {
+                let p = p.trim().trim_start_matches('.').to_ascii_lowercase();
+                glob_match(&p, &domain) || p.strip_prefix("*.") == Some(domain.as_str())
+            })
+        })
+        .collect()
+}
 
-    let nav = json!({
-        "id": 10000,
-        "method": "Page.navigate",
-        "params": { "url": to_open }
-    });
-    socket.write_message(Message::Text(nav.to_string().into()))?;
-
-    Ok(local_tab_id)
+/// Split a comma- or space-separated list of domain globs.
+pub fn parse_domain_patterns(input: &str) -> Vec<String> {
+    input
+        .split([',', ' ', '\n'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
 }
 
-/// Revoke (delete) cookies in a live tab, based on name/domain/path.
-///
-/// You must have a running tab (identified by its `tab_id`) on localhost:9222.
-pub fn revoke_cookies(
-    tab_id: &str,
-    cookies: &[(&str, &str, &str)], // (name, domain, path)
-) -> Result<(), Box<dyn Error>> {
-    // 1) Find the WS URL for this tab
-    let ws_url = {
-        let tabs: serde_json::Value =
-            reqwest::blocking::get("http://localhost:9222/json")?.json()?;
-        let list = tabs.as_array().ok_or("tabs not array")?;
-        let entry = list
-            .iter()
-            .find(|t| t["id"] == tab_id)
-            .ok_or("tab not found")?;
-        entry["webSocketDebuggerUrl"]
-            .as_str()
-            .ok_or("missing webSocketDebuggerUrl")?
-            .to_string()
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let Some((first, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(mut text) = text.strip_prefix(first) else {
+        return false;
     };
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        match text.find(part) {
+            Some(i) => text = &text[i + part.len()..],
+            None => return false,
+        }
+    }
+    text.len() >= last.len() && text.ends_with(last)
+}
 
-    let (mut socket, _) = connect(ws_url)?;
+pub type CookieMatch<'a> = (&'a Cookie, Result<(), String>);
 
-    for (i, &(name, domain, path)) in cookies.iter().enumerate() {
-        let params = json!({
-            "name": name,
-            "domain": domain,
-            "path": path,
-        });
-        let msg = json!({
-            "id": 10_000 + i as u64,
-            "method": "Network.deleteCookies",
-            "params": params,
-        });
-        socket.write_message(Message::Text(msg.to_string().into()))?;
+/// Check every cookie against `url` using the browser's matching rules and
+/// pair it with `Ok(())` if it would be sent, or the reason it would not.
+pub fn match_cookies_to_url<'a>(
+    cookies: &'a [Cookie],
+    url: &str,
+) -> Result<Vec<CookieMatch<'a>>, Box<dyn Error + Send + Sync>> {
+    let target = Url::parse(&normalize_url(url)?)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default();
+    Ok(cookies
+        .iter()
+        .map(|c| (c, cookie_applies_to(c, &target, now)))
+        .collect())
+}
+
+fn cookie_applies_to(cookie: &Cookie, url: &Url, now: f64) -> Result<(), String> {
+    let scheme = url.scheme();
+    if scheme != "http" && scheme != "https" {
+        return Err(format!("{} URLs do not carry cookies", scheme));
     }
+    let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+    let domain = cookie.domain.to_ascii_lowercase();
+    let secure = cookie.secure.unwrap_or(false);
 
-    Ok(())
-}
+    if let Some(parent) = domain.strip_prefix('.') {
+        if host != parent && !host.ends_with(&domain) {
+            return Err(format!("domain {} does not cover {}", cookie.domain, host));
+        }
+    } else if host != domain {
+        return Err(format!(
+            "host-only cookie for {} does not match {}",
+            cookie.domain, host
+        ));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
+    let path = url.path();
+    let cookie_path = if cookie.path.is_empty() {
+        "/"
+    } else {
+        cookie.path.as_str()
+    };
+    let path_matches = path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')));
+    if !path_matches {
+        return Err(format!("path {} does not cover {}", cookie_path, path));
+    }
 
-    #[test]
-    fn test_cookie_json_deserialization() {
-        let path = "/Users/manavkdubey/Downloads/cookies_ChatGPT.json";
-        let data = fs::read_to_string(path).expect("File not found");
-        let try_array = serde_json::from_str::<Vec<Cookie>>(&data);
+    if secure && scheme != "https" {
+        return Err("Secure cookie is not sent over http".into());
+    }
+    if cookie
+        .sameSite
+        .as_deref()
+        .is_some_and(|s| s.eq_ignore_ascii_case("none"))
+        && !secure
+    {
+        return Err("SameSite=None requires Secure".into());
+    }
+    if cookie.name.starts_with("__Secure-") && !secure {
+        return Err("__Secure- prefix requires Secure".into());
+    }
+    if cookie.name.starts_with("__Host-")
+        && (!secure || cookie_path != "/" || domain.starts_with('.'))
+    {
+        return Err("__Host- prefix requires Secure, path / and no Domain".into());
+    }
+    if cookie.session != Some(true)
+        && let Some(expires) = cookie.expires
+        && expires > 0.0
+        && expires < now
+    {
+        return Err("cookie has expired".into());
+    }
+    Ok(())
+}
+
+/// Check that a hand-edited cookie is one Chrome would accept from
+/// `Network.setCookie`, independent of any particular URL.
+pub fn validate_cookie(cookie: &Cookie) -> Result<(), String> {
+    let bad_char = |c: char| c.is_control() || c == ';';
+    if cookie.name.is_empty()
+        || cookie
+            .name
+            .contains(|c: char| bad_char(c) || c == '=' || c == ' ')
+    {
+        return Err(format!("invalid name {:?}", cookie.name));
+    }
+    if cookie.value.contains(bad_char) {
+        return Err(format!(
+            "value of {} contains ';' or control characters",
+            cookie.name
+        ));
+    }
+    let host = cookie.domain.strip_prefix('.').unwrap_or(&cookie.domain);
+    let label_ok = |l: &str| {
+        !l.is_empty()
+            && l.len() <= 63
+            && !l.starts_with('-')
+            && !l.ends_with('-')
+            && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if host.is_empty() || host.len() > 253 || !host.split('.').all(label_ok) {
+        return Err(format!("invalid domain {:?}", cookie.domain));
+    }
+    if !cookie.path.is_empty() && !cookie.path.starts_with('/') {
+        return Err(format!("path {:?} must start with /", cookie.path));
+    }
+    let secure = cookie.secure.unwrap_or(false);
+    match cookie.sameSite.as_deref() {
+        None | Some("Strict") | Some("Lax") => {}
+        Some("None") if secure => {}
+        Some("None") => return Err("SameSite=None requires Secure".into()),
+        Some(other) => {
+            return Err(format!(
+                "sameSite must be Strict, Lax or None, not {:?}",
+                other
+            ));
+        }
+    }
+    Ok(())
+}
 
-        match try_array {
-            Ok(cookies) => {
-                println!("Parsed as Vec<Cookie>. First entry:\n{:#?}", cookies.get(0));
+pub async fn import_and_open_with_cookies_from_memory(
+    cdp: &CdpEndpoint,
+    cookies: &[Cookie],
+    url: &str,
+    background: bool,
+) -> Result<(String, CookieReport), Box<dyn Error + Send + Sync>> {
+    let to_open = normalize_url(url)?;
+    let new_tab = open_import_tab(cdp, &to_open, background).await?;
+    let local_tab_id = new_tab_id(&new_tab)?;
+
+    let mut target = AttachedTarget::attach(cdp, &local_tab_id).await?;
+    target.send("Network.enable", json!({})).await?;
+    let report = apply_cookies(&mut target, cookies).await?;
+    target
+        .send("Page.navigate", json!({ "url": to_open }))
+        .await?;
+    target.detach().await;
+
+    Ok((local_tab_id, report))
+}
+
+/// Put `cookies` in the browser's cookie jar without opening or attaching to
+/// a tab, then read the jar back to check them as an import does. Cookies
+/// with no domain of their own are set for `url`'s host.
+pub async fn set_cookies(
+    cdp: &CdpEndpoint,
+    url: &str,
+    cookies: &[Cookie],
+) -> Result<CookieReport, Box<dyn Error + Send + Sync>> {
+    let url = Url::parse(&normalize_url(url)?)?;
+    let host = url.host_str().ok_or("URL has no host")?;
+    let cookies: Vec<Cookie> = cookies
+        .iter()
+        .cloned()
+        .map(|mut cookie| {
+            if cookie.domain.is_empty() {
+                cookie.domain = host.to_string();
+            }
+            cookie
+        })
+        .collect();
+
+    // The browser target has no Network domain; Storage takes the same
+    // cookie params but reports a refusal as an error, not `success: false`.
+    let mut browser = CdpSession::browser(cdp).await?;
+    let mut rejected = HashMap::new();
+    for cookie in &cookies {
+        let params = json!({ "cookies": [set_cookie_params(cookie)] });
+        if let Err(e) = browser.send_raw("Storage.setCookies", params).await {
+            rejected.insert(cookie_label(cookie), e.to_string());
+        }
+    }
+    let mut stored = browser.send_raw("Storage.getCookies", json!({})).await?;
+    let stored: Vec<Cookie> = serde_json::from_value(stored["cookies"].take())?;
+    Ok(verify_cookies(&cookies, &stored, rejected))
+}
+
+/// Open `count` tabs on `url` that all carry `cookies`, returning their ids.
+///
+/// Tabs in one browser profile share a cookie jar, so the cookies are set
+/// (and verified) once through the first tab; the rest only need opening.
+pub async fn import_to_n(
+    cdp: &CdpEndpoint,
+    cookies: &[Cookie],
+    url: &str,
+    count: usize,
+    background: bool,
+) -> Result<(Vec<String>, CookieReport), Box<dyn Error + Send + Sync>> {
+    if count == 0 {
+        return Err("tab count must be at least 1".into());
+    }
+    let (first, report) =
+        import_and_open_with_cookies_from_memory(cdp, cookies, url, background).await?;
+    let to_open = normalize_url(url)?;
+    let mut ids = vec![first];
+    for _ in 1..count {
+        let tab = open_import_tab(cdp, &to_open, background).await?;
+        ids.push(new_tab_id(&tab)?);
+    }
+    Ok((ids, report))
+}
+
+/// What happened to each cookie an import tried to set.
+#[derive(Debug, Default)]
+pub struct CookieReport {
+    pub verified: usize,
+    /// `name (domain path)` and why it is not in the browser.
+    pub failed: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for CookieReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} cookies verified",
+            self.verified,
+            self.verified + self.failed.len()
+        )?;
+        for (cookie, reason) in &self.failed {
+            write!(f, "\n  ✘ {}: {}", cookie, reason)?;
+        }
+        Ok(())
+    }
+}
+
+// Set every cookie, then read the store back and compare, since Chrome can
+// accept a setCookie and still not keep the cookie. This runs before the
+// navigation so the page's own Set-Cookie headers aren't reported as drift.
+async fn apply_cookies(
+    target: &mut AttachedTarget,
+    cookies: &[Cookie],
+) -> Result<CookieReport, Box<dyn Error + Send + Sync>> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default();
+    let mut rejected = HashMap::new();
+    for cookie in cookies {
+        let outcome = target
+            .send("Network.setCookie", set_cookie_params(cookie))
+            .await;
+        match outcome {
+            Ok(result) if result["success"] == false => {
+                rejected.insert(cookie_label(cookie), rejection_hint(cookie, now));
             }
             Err(e) => {
-                println!(" Failed to parse as Vec<Cookie>: {e}");
-
-                let as_value: serde_json::Value = serde_json::from_str(&data).unwrap();
-                if let Some(arr) = as_value.get("cookies") {
-                    let cookies_json = arr.to_string();
-                    let try_key = serde_json::from_str::<Vec<Cookie>>(&cookies_json);
-                    match try_key {
-                        Ok(cookies) => println!(
-                            "Parsed as {{ cookies: [...] }}. First entry:\n{:#?}",
-                            cookies.get(0)
-                        ),
-                        Err(e) => println!(
-                            "❌ Failed to parse cookies array in {{ cookies: [...] }}: {e}"
-                        ),
-                    }
-                }
+                rejected.insert(cookie_label(cookie), e.to_string());
             }
+            Ok(_) => {}
         }
     }
+
+    let stored = match target.send("Storage.getCookies", json!({})).await {
+        Ok(result) => result,
+        Err(_) => target.send("Network.getAllCookies", json!({})).await?,
+    };
+    let stored: Vec<Cookie> = serde_json::from_value(stored["cookies"].clone())?;
+    Ok(verify_cookies(cookies, &stored, rejected))
 }
-pub fn get_cookies_for_tab(tab: &ChromeTab) -> Result<Vec<Cookie>, Box<dyn Error>> {
-    let ws_url = if let Some(ws) = &tab.webSocketDebuggerUrl {
-        ws.clone()
+
+// setCookie only says `success: false`, so name the likeliest of the usual
+// causes, checked against the cookie as it was actually sent.
+fn rejection_hint(cookie: &Cookie, now: f64) -> String {
+    let cookie = normalize_for_import(cookie);
+    let guess = if let Err(e) = validate_cookie(&cookie) {
+        e
+    } else if cookie.name.len() + cookie.value.len() > 4096 {
+        "name and value exceed 4096 bytes".into()
+    } else if cookie.name.starts_with("__Host-") && cookie.path != "/" {
+        "__Host- cookies must have path /".into()
+    } else if cookie.expires.is_some_and(|ex| ex < now) {
+        "already expired".into()
     } else {
-        get_ws_url_for_tab(&tab.id)?
+        "domain mismatch?".into()
     };
+    format!("rejected by Chrome ({})", guess)
+}
 
-    let (mut socket, _) = connect(ws_url)?;
+pub(crate) fn cookie_label(cookie: &Cookie) -> String {
+    format!("{} ({} {})", cookie.name, cookie.domain, cookie.path)
+}
 
-    let cmd = serde_json::json!({
-        "id": 1,
-        "method": "Network.getCookies",
-        "params": { "urls": [ tab.url ] }
-    });
-    socket.write_message(Message::Text(cmd.to_string().into()))?;
+pub(crate) fn verify_cookies(
+    intended: &[Cookie],
+    stored: &[Cookie],
+    mut rejected: HashMap<String, String>,
+) -> CookieReport {
+    let same_domain = |a: &str, b: &str| normalize_domain(a, true) == normalize_domain(b, true);
+    let mut report = CookieReport::default();
+    for cookie in intended {
+        let label = cookie_label(cookie);
+        if let Some(reason) = rejected.remove(&label) {
+            report.failed.push((label, reason));
+            continue;
+        }
+        match stored.iter().find(|s| {
+            s.name == cookie.name && s.path == cookie.path && same_domain(&s.domain, &cookie.domain)
+        }) {
+            Some(s) if s.value == cookie.value => report.verified += 1,
+            Some(_) => report
+                .failed
+                .push((label, "stored with a different value".into())),
+            None => report.failed.push((label, "not stored".into())),
+        }
+    }
+    report
+}
 
-    let txt = socket.read_message()?.into_text()?;
-    let v: Value = serde_json::from_str(&txt)?;
+/// Map the `sameSite` spellings other exporters use (`lax`,
+/// `no_restriction`, ...) to one of the three values CDP accepts. Anything
+/// else, `unspecified` included, is dropped so the browser default applies.
+pub fn normalize_same_site(raw: &str) -> Option<&'static str> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "strict" => Some("Strict"),
+        "lax" => Some("Lax"),
+        "none" | "no_restriction" => Some("None"),
+        _ => None,
+    }
+}
 
-    let arr = v["result"]["cookies"].clone();
-    let cookies: Vec<Cookie> = serde_json::from_value(arr)?;
+/// Tidy a cookie domain for `Network.setCookie`: trimmed, lowercased, and a
+/// wildcard or repeated leading dots (`*.github.com`, `..github.com`) turned
+/// into the usual `.github.com`. When the call also carries a `url`, the
+/// leading dot is dropped as well, since CDP can refuse a dotted domain that
+/// doesn't match the URL's host; passing a domain at all already makes it a
+/// domain cookie.
+pub fn normalize_domain(domain: &str, with_url: bool) -> String {
+    let domain = domain.trim().to_ascii_lowercase();
+    let host = domain.trim_start_matches("*.").trim_start_matches('.');
+    if host.len() < domain.len() && !with_url {
+        format!(".{}", host)
+    } else {
+        host.to_string()
+    }
+}
+
+/// `cookie` as the import functions set it: `sameSite` normalized,
+/// SameSite=None and `__Host-`/`__Secure-` cookies made Secure, and session
+/// cookies without an expiry (exports mark them `expires: -1`, which CDP
+/// would take as already expired).
+pub fn normalize_for_import(cookie: &Cookie) -> Cookie {
+    let mut cookie = cookie.clone();
+    cookie.domain = normalize_domain(&cookie.domain, false);
+    cookie.sameSite = cookie
+        .sameSite
+        .as_deref()
+        .and_then(normalize_same_site)
+        .map(str::to_string);
+    if cookie.sameSite.as_deref() == Some("None")
+        || cookie.name.starts_with("__Host-")
+        || cookie.name.starts_with("__Secure-")
+    {
+        cookie.secure = Some(true);
+    }
+    if cookie.session == Some(true) || cookie.expires.is_some_and(|ex| ex <= 0.0) {
+        cookie.expires = None;
+    }
+    cookie
+}
+
+/// Load a cookie file and normalize it as an import would, without touching
+/// the browser, to check an export before using it.
+pub fn preview_import(cookie_path: &Path) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>> {
+    Ok(universal_cookie_loader(cookie_path)?
+        .iter()
+        .map(normalize_for_import)
+        .collect())
+}
+
+// `__Host-` and `__Secure-` cookies are only accepted from a secure URL, and
+// `__Host-` ones must not carry a Domain at all, so those are set through a
+// `url` and Chrome derives the rest. Everything else is set field by field.
+fn set_cookie_params(cookie: &Cookie) -> Value {
+    let cookie = &normalize_for_import(cookie);
+    let mut params = serde_json::Map::new();
+    params.insert("name".into(), json!(cookie.name));
+    params.insert("value".into(), json!(cookie.value));
+    let host_only = cookie.name.starts_with("__Host-");
+    let with_url = host_only || cookie.name.starts_with("__Secure-");
+    if with_url {
+        let host = normalize_domain(&cookie.domain, true);
+        params.insert(
+            "url".into(),
+            json!(format!("https://{}{}", host, cookie.path)),
+        );
+    }
+    if !host_only {
+        params.insert(
+            "domain".into(),
+            json!(normalize_domain(&cookie.domain, with_url)),
+        );
+    }
+    params.insert("path".into(), json!(cookie.path));
+    if let Some(ex) = cookie.expires {
+        params.insert("expires".into(), json!(ex));
+    }
+    if let Some(true) = cookie.secure {
+        params.insert("secure".into(), json!(true));
+    }
+    if let Some(true) = cookie.httpOnly {
+        params.insert("httpOnly".into(), json!(true));
+    }
+    if let Some(ss) = &cookie.sameSite {
+        params.insert("sameSite".into(), json!(ss));
+    }
+    // CHIPS: a partitioned cookie only exists under its top-level site, so
+    // setting it without the key writes a different, unpartitioned cookie.
+    if let Some(key) = cookie.extra.get("partitionKey").filter(|k| !k.is_null()) {
+        params.insert("partitionKey".into(), key.clone());
+    }
+    Value::Object(params)
+}
+
+/// Navigate an existing tab to `url`, setting `cookies` first. Like an
+/// import, a cookie Chrome rejects is skipped and listed in the report
+/// rather than stopping the navigation.
+pub async fn navigate_tab(
+    cdp: &CdpEndpoint,
+    tab_id: &str,
+    url: &str,
+    cookies: &[Cookie],
+) -> Result<CookieReport, Box<dyn Error + Send + Sync>> {
+    let to_open = normalize_url(url)?;
+    let mut target = AttachedTarget::attach(cdp, tab_id).await?;
+    let report = if cookies.is_empty() {
+        CookieReport::default()
+    } else {
+        apply_cookies(&mut target, cookies).await?
+    };
+    target
+        .send("Page.navigate", json!({ "url": to_open }))
+        .await?;
+    target.detach().await;
+    Ok(report)
+}
+
+/// Revoke (delete) cookies in a live tab, based on name/domain/path, and
+/// return how many deletes Chrome confirmed. A rejected delete is logged
+/// and skipped rather than abandoning the rest.
+///
+/// You must have a running tab (identified by its `tab_id`) reachable through `cdp`.
+pub async fn revoke_cookies(
+    cdp: &CdpEndpoint,
+    tab_id: &str,
+    cookies: &[(&str, &str, &str)], // (name, domain, path)
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let mut page = CdpSession::page_by_id(cdp, tab_id).await?;
+    delete_cookies(&mut page, cookies).await
+}
+
+/// Revoke every cookie Chrome would send to `url`, as read through the tab
+/// `tab_id`, and return how many deletes Chrome confirmed. With
+/// `clear_storage`, the origin's localStorage, IndexedDB, caches and service
+/// workers go too.
+pub async fn revoke_all_for_url(
+    cdp: &CdpEndpoint,
+    tab_id: &str,
+    url: &str,
+    clear_storage: bool,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let url = normalize_url(url)?;
+    let mut page = CdpSession::page_by_id(cdp, tab_id).await?;
+    let result = page
+        .send_raw("Network.getCookies", json!({ "urls": [&url] }))
+        .await?;
+    let cookies: Vec<Cookie> = serde_json::from_value(result["cookies"].clone())?;
+    let triples: Vec<(&str, &str, &str)> = cookies
+        .iter()
+        .map(|c| (c.name.as_str(), c.domain.as_str(), c.path.as_str()))
+        .collect();
+    let deleted = delete_cookies(&mut page, &triples).await?;
+
+    if clear_storage {
+        let origin = Url::parse(&url)?.origin().ascii_serialization();
+        let params = json!({
+            "origin": origin,
+            "storageTypes": "local_storage,indexeddb,cache_storage,service_workers",
+        });
+        let mut browser = CdpSession::browser(cdp).await?;
+        match browser.send_raw("Storage.clearDataForOrigin", params).await {
+            Ok(_) => info!("Cleared site data for {}", origin),
+            Err(e) => warn!("Could not clear site data for {}: {}", origin, e),
+        }
+    }
+    Ok(deleted)
+}
+
+async fn delete_cookies(
+    page: &mut CdpSession,
+    cookies: &[(&str, &str, &str)],
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let mut deleted = 0;
+    for &(name, domain, path) in cookies {
+        let params = json!({
+            "name": name,
+            "domain": domain,
+            "path": path,
+        });
+        match page.send_raw("Network.deleteCookies", params).await {
+            Ok(_) => deleted += 1,
+            Err(e) if e.is::<CdpError>() => {
+                warn!("Could not delete cookie {} for {}: {}", name, domain, e)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(deleted)
+}
+
+/// Close the tab `tab_id`.
+pub async fn close_tab(
+    cdp: &CdpEndpoint,
+    tab_id: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    CdpSession::browser(cdp)
+        .await?
+        .send_raw("Target.closeTarget", json!({ "targetId": tab_id }))
+        .await?;
+    Ok(())
+}
+
+pub async fn get_cookies_for_tab(
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>> {
+    get_cookies_for_urls(cdp, tab, &[]).await
+}
+
+/// Like `get_cookies_for_tab`, but also collects the cookies Chrome would send
+/// to each of `extra_urls`, e.g. an SSO provider the tab's login depends on.
+pub async fn get_cookies_for_urls(
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+    extra_urls: &[String],
+) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>> {
+    let mut target = AttachedTarget::attach(cdp, &tab.id).await?;
+
+    let urls: Vec<&str> = std::iter::once(tab.url.as_str())
+        .chain(extra_urls.iter().map(String::as_str))
+        .collect();
+    let result = target
+        .send("Network.getCookies", json!({ "urls": urls }))
+        .await?;
+    target.detach().await;
+    let cookies: Vec<Cookie> = serde_json::from_value(result["cookies"].clone())?;
+    Ok(cookies)
+}
+
+/// Short-lived cache of captured cookies keyed by tab id.
+///
+/// Several GUI actions on the same tab each need its cookies; this keeps them
+/// from reconnecting to the tab for every click. Entries expire after `ttl` and
+/// are dropped as soon as the tab's URL changes, so a share never goes out with
+/// auth state from a page the tab has since navigated away from.
+pub struct CookieCache {
+    ttl: Duration,
+    entries: HashMap<String, CachedCookies>,
+}
+
+struct CachedCookies {
+    url: String,
+    fetched_at: Instant,
+    cookies: Vec<Cookie>,
+}
+
+impl CookieCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// `tab`'s cookies, if they were read less than `ttl` ago at the URL it
+    /// is on now.
+    pub fn get(&self, tab: &ChromeTab) -> Option<Vec<Cookie>> {
+        self.entries
+            .get(&tab.id)
+            .filter(|entry| entry.url == tab.url && entry.fetched_at.elapsed() < self.ttl)
+            .map(|entry| entry.cookies.clone())
+    }
+
+    pub fn insert(&mut self, tab: &ChromeTab, cookies: Vec<Cookie>) {
+        self.entries.insert(
+            tab.id.clone(),
+            CachedCookies {
+                url: tab.url.clone(),
+                fetched_at: Instant::now(),
+                cookies,
+            },
+        );
+    }
+
+    pub fn invalidate(&mut self, tab_id: &str) {
+        self.entries.remove(tab_id);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// `tab`'s cookies from `cache`, or from the browser when the cache has none
+/// that are fresh, in which case they are cached. The cache isn't locked
+/// while the browser is asked.
+pub async fn cached_cookies(
+    cache: &std::sync::Mutex<CookieCache>,
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>> {
+    let cached = cache.lock().unwrap().get(tab);
+    if let Some(cookies) = cached {
+        return Ok(cookies);
+    }
+    let cookies = get_cookies_for_tab(cdp, tab).await?;
+    cache.lock().unwrap().insert(tab, cookies.clone());
     Ok(cookies)
 }
+
+/// An error reply from Chrome to a CDP command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CdpError {
+    pub method: String,
+    pub code: i64,
+    pub message: String,
+}
+
+impl std::fmt::Display for CdpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} failed: {}", self.method, self.message)
+    }
+}
+
+impl Error for CdpError {}
+
+/// A DevTools connection for sending arbitrary CDP commands, for anything
+/// the specific helpers in this module don't cover (screenshots, emulation,
+/// storage, ...). Ids are allocated per connection and events arriving
+/// between a command and its reply are skipped.
+///
+/// Generic over the stream so tests can drive it without a browser.
+pub struct CdpSession<S = MaybeTlsStream<TcpStream>> {
+    socket: WebSocketStream<S>,
+    next_id: u64,
+}
+
+impl CdpSession {
+    /// Connect to one page's target.
+    pub async fn page(
+        cdp: &CdpEndpoint,
+        tab: &ChromeTab,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::connect(&tab_ws_url(cdp, tab).await?).await
+    }
+
+    /// Like `page`, looking the tab up by id in a fresh `/json` listing.
+    pub async fn page_by_id(
+        cdp: &CdpEndpoint,
+        tab_id: &str,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::connect(&get_ws_url_for_tab(cdp, tab_id).await?).await
+    }
+
+    /// Connect to the browser target, for `Target.*`, `Browser.*` and
+    /// commands routed to a page with `send_to`.
+    pub async fn browser(cdp: &CdpEndpoint) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::connect(&browser_ws_url(cdp).await?).await
+    }
+
+    // A target Chrome has only just listed can refuse connections for a
+    // moment, so a failed connect is retried per `CONNECT_RETRY_DELAYS`.
+    async fn connect(ws_url: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (socket, _) = with_retries(&CONNECT_RETRY_DELAYS, || connect_async(ws_url))
+            .await
+            .map_err(|e| {
+                format!(
+                    "could not reach DevTools at {} after {} attempts: {}",
+                    ws_url,
+                    CONNECT_RETRY_DELAYS.len() + 1,
+                    e
+                )
+            })?;
+        Ok(Self::from_socket(socket))
+    }
+}
+
+/// Pauses between attempts to open a DevTools socket.
+const CONNECT_RETRY_DELAYS: [Duration; 2] =
+    [Duration::from_millis(100), Duration::from_millis(400)];
+
+// Run `f`, and again after each delay while it fails; the last error wins.
+async fn with_retries<T, E: std::fmt::Display, F: Future<Output = Result<T, E>>>(
+    delays: &[Duration],
+    mut f: impl FnMut() -> F,
+) -> Result<T, E> {
+    for delay in delays {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                debug!("Retrying in {:?}: {}", delay, e);
+                tokio::time::sleep(*delay).await;
+            }
+        }
+    }
+    f().await
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> CdpSession<S> {
+    /// Drive an already-open DevTools socket. Ids start at 1.
+    pub fn from_socket(socket: WebSocketStream<S>) -> Self {
+        Self { socket, next_id: 1 }
+    }
+
+    /// The id for the next command on this connection. Never repeats, so
+    /// every reply can be matched to the command it answers.
+    pub fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Send `method` and return its `result`. A protocol-level failure comes
+    /// back as a boxed `CdpError`.
+    pub async fn send_raw(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        self.send(None, method, params).await
+    }
+
+    /// Attach to `target_id` over this browser connection and return the
+    /// session id to pass to `send_to`.
+    pub async fn attach(
+        &mut self,
+        target_id: &str,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let attached = self
+            .send_raw(
+                "Target.attachToTarget",
+                json!({ "targetId": target_id, "flatten": true }),
+            )
+            .await?;
+        Ok(attached["sessionId"]
+            .as_str()
+            .ok_or("missing sessionId")?
+            .to_string())
+    }
+
+    /// Like `send_raw`, but addressed to a session from `attach`.
+    pub async fn send_to(
+        &mut self,
+        session_id: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        self.send(Some(session_id), method, params).await
+    }
+
+    async fn send(
+        &mut self,
+        session_id: Option<&str>,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let id = self.next_id();
+        cdp_command(&mut self.socket, id, session_id, method, params).await
+    }
+
+    // Evaluate `expression` in the page, awaiting it if it is a promise,
+    // and return its value. Script exceptions are reported as errors.
+    async fn evaluate(&mut self, expression: &str) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let result = self
+            .send_raw(
+                "Runtime.evaluate",
+                json!({ "expression": expression, "awaitPromise": true, "returnByValue": true }),
+            )
+            .await?;
+        if let Some(details) = result.get("exceptionDetails") {
+            let text = details["exception"]["description"]
+                .as_str()
+                .or(details["text"].as_str())
+                .unwrap_or("script threw");
+            return Err(text.to_string().into());
+        }
+        Ok(result["result"]["value"].clone())
+    }
+}
+
+/// One page driven through the browser connection: attached with
+/// `Target.attachToTarget { flatten: true }` and commands routed by session
+/// id. Unlike the page's own socket this also reaches tabs Chrome has
+/// backgrounded or discarded. Finish with `detach`; a target dropped without
+/// it is detached by Chrome when the browser connection closes.
+pub struct AttachedTarget {
+    browser: CdpSession,
+    session_id: String,
+}
+
+impl AttachedTarget {
+    pub async fn attach(
+        cdp: &CdpEndpoint,
+        target_id: &str,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut browser = CdpSession::browser(cdp).await?;
+        let session_id = browser.attach(target_id).await?;
+        Ok(Self {
+            browser,
+            session_id,
+        })
+    }
+
+    pub async fn send(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        self.browser.send_to(&self.session_id, method, params).await
+    }
+
+    pub async fn detach(mut self) {
+        let detach = json!({ "sessionId": self.session_id });
+        if let Err(e) = self
+            .browser
+            .send_raw("Target.detachFromTarget", detach)
+            .await
+        {
+            warn!("Could not detach from {}: {}", self.session_id, e);
+        }
+    }
+}
+
+// Send one command and read until the reply with the same id arrives,
+// skipping any events in between.
+async fn cdp_command<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut WebSocketStream<S>,
+    id: u64,
+    session_id: Option<&str>,
+    method: &str,
+    params: Value,
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    let mut msg = json!({ "id": id, "method": method, "params": params });
+    if let Some(session_id) = session_id {
+        msg["sessionId"] = json!(session_id);
+    }
+    // Params can carry cookie values, so they only show at trace level.
+    debug!(id, session_id, "CDP {}", method);
+    trace!("CDP request: {}", msg);
+    socket.send(Message::Text(msg.to_string().into())).await?;
+    loop {
+        let frame = socket
+            .next()
+            .await
+            .ok_or_else(|| format!("DevTools closed the socket before answering {}", method))??;
+        let reply: Value = serde_json::from_str(frame.to_text()?)?;
+        if reply["id"] != id || reply["sessionId"].as_str() != session_id {
+            continue;
+        }
+        if let Some(err) = reply.get("error") {
+            return Err(Box::new(CdpError {
+                method: method.to_string(),
+                code: err["code"].as_i64().unwrap_or_default(),
+                message: err["message"].as_str().unwrap_or_default().to_string(),
+            }));
+        }
+        return Ok(reply["result"].clone());
+    }
+}
+
+/// One IndexedDB object store and its records, as captured from a page.
+///
+/// Records are carried as JSON, so values that don't survive `JSON.stringify`
+/// (Blobs, Dates, Maps) are lost or flattened in transit.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedDbEntry {
+    pub database: String,
+    pub version: u64,
+    pub store: String,
+    pub key_path: Value,
+    pub auto_increment: bool,
+    pub records: Vec<IndexedDbRecord>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct IndexedDbRecord {
+    pub key: Value,
+    pub value: Value,
+}
+
+const CAPTURE_INDEXED_DB_JS: &str = r#"(async () => {
+  const wrap = (r) => new Promise((res, rej) => { r.onsuccess = () => res(r.result); r.onerror = () => rej(r.error); });
+  const out = [];
+  const infos = indexedDB.databases ? await indexedDB.databases() : [];
+  for (const info of infos) {
+    const db = await wrap(indexedDB.open(info.name));
+    for (const store of Array.from(db.objectStoreNames)) {
+      const os = db.transaction(store, 'readonly').objectStore(store);
+      const [keys, values] = await Promise.all([wrap(os.getAllKeys()), wrap(os.getAll())]);
+      out.push({
+        database: db.name, version: db.version, store,
+        keyPath: os.keyPath, autoIncrement: os.autoIncrement,
+        records: keys.map((key, i) => ({ key, value: values[i] })),
+      });
+    }
+    db.close();
+  }
+  return JSON.stringify(out);
+})()"#;
+
+const REPLAY_INDEXED_DB_JS: &str = r#"(async (entries) => {
+  const wrap = (r) => new Promise((res, rej) => { r.onsuccess = () => res(r.result); r.onerror = () => rej(r.error); });
+  const byDb = {};
+  for (const e of entries) (byDb[e.database] = byDb[e.database] || []).push(e);
+  let written = 0;
+  for (const [name, stores] of Object.entries(byDb)) {
+    const open = (version) => {
+      const req = version ? indexedDB.open(name, version) : indexedDB.open(name);
+      req.onupgradeneeded = () => {
+        for (const s of stores) {
+          if (!req.result.objectStoreNames.contains(s.store)) {
+            req.result.createObjectStore(s.store, { keyPath: s.keyPath, autoIncrement: s.autoIncrement });
+          }
+        }
+      };
+      return wrap(req);
+    };
+    let db = await open(Math.max(...stores.map((s) => s.version)));
+    if (stores.some((s) => !db.objectStoreNames.contains(s.store))) {
+      const next = db.version + 1;
+      db.close();
+      db = await open(next);
+    }
+    for (const s of stores) {
+      const tx = db.transaction(s.store, 'readwrite');
+      const os = tx.objectStore(s.store);
+      for (const r of s.records) {
+        if (os.keyPath === null) os.put(r.value, r.key); else os.put(r.value);
+        written++;
+      }
+      await new Promise((res, rej) => { tx.oncomplete = res; tx.onerror = () => rej(tx.error); });
+    }
+    db.close();
+  }
+  return written;
+})"#;
+
+/// What `tab` currently shows, as PNG bytes.
+pub async fn capture_screenshot(
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut target = AttachedTarget::attach(cdp, &tab.id).await?;
+    let shot = target
+        .send("Page.captureScreenshot", json!({ "format": "png" }))
+        .await?;
+    target.detach().await;
+    let data = shot["data"]
+        .as_str()
+        .ok_or("Page.captureScreenshot returned no image")?;
+    Ok(BASE64.decode(data)?)
+}
+
+/// Dump every IndexedDB database visible to the tab's origin.
+pub async fn capture_indexed_db(
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+) -> Result<Vec<IndexedDbEntry>, Box<dyn Error + Send + Sync>> {
+    let dump = CdpSession::page(cdp, tab)
+        .await?
+        .evaluate(CAPTURE_INDEXED_DB_JS)
+        .await?;
+    let dump = dump.as_str().ok_or("IndexedDB capture returned no data")?;
+    Ok(serde_json::from_str(dump)?)
+}
+
+/// Write captured IndexedDB stores into a local tab once it has loaded the
+/// origin they belong to. Returns the number of records written.
+pub async fn replay_indexed_db(
+    cdp: &CdpEndpoint,
+    tab_id: &str,
+    entries: &[IndexedDbEntry],
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let mut page = CdpSession::page_by_id(cdp, tab_id).await?;
+    wait_for_web_page(&mut page, "IndexedDB replay").await?;
+    let script = format!(
+        "{}({})",
+        REPLAY_INDEXED_DB_JS,
+        serde_json::to_string(entries)?
+    );
+    let written = page.evaluate(&script).await?;
+    Ok(written.as_u64().unwrap_or(0))
+}
+
+/// Every `localStorage` item of the tab's origin.
+pub async fn capture_local_storage(
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+) -> Result<HashMap<String, String>, Box<dyn Error + Send + Sync>> {
+    let dump = CdpSession::page(cdp, tab)
+        .await?
+        .evaluate("JSON.stringify(localStorage)")
+        .await?;
+    let dump = dump
+        .as_str()
+        .ok_or("localStorage capture returned no data")?;
+    Ok(serde_json::from_str(dump)?)
+}
+
+/// Set captured `localStorage` items in a local tab once it has loaded their
+/// origin, then reload it so the app starts up with them, as apps usually
+/// read their tokens only at startup. Returns the number of items set.
+pub async fn replay_local_storage(
+    cdp: &CdpEndpoint,
+    tab_id: &str,
+    items: &HashMap<String, String>,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let mut page = CdpSession::page_by_id(cdp, tab_id).await?;
+    wait_for_web_page(&mut page, "localStorage replay").await?;
+    let script = format!(
+        "(items => {{ for (const [k, v] of Object.entries(items)) localStorage.setItem(k, v); }})({})",
+        serde_json::to_string(items)?
+    );
+    page.evaluate(&script).await?;
+    page.send_raw("Page.reload", json!({})).await?;
+    Ok(items.len())
+}
+
+// Web storage is per-origin, so writing before navigation finishes would
+// land in about:blank's storage. `before` names what is waiting, for the
+// timeout error.
+async fn wait_for_web_page(
+    page: &mut CdpSession,
+    before: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let deadline = Instant::now() + Duration::from_secs(15);
+    loop {
+        let state = page
+            .evaluate("location.protocol.startsWith('http') && document.readyState === 'complete'")
+            .await?;
+        if state == json!(true) {
+            return Ok(());
+        }
+        if Instant::now() > deadline {
+            return Err(format!("timed out waiting for page load before {}", before).into());
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tokio_tungstenite::tungstenite::protocol::Role;
+
+    #[test]
+    fn test_cookie_json_deserialization() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/cookies.json");
+        let data = fs::read_to_string(path).expect("File not found");
+        let cookies: Vec<Cookie> = serde_json::from_str(&data).unwrap();
+        assert_eq!(cookies.len(), 2);
+
+        let session = &cookies[0];
+        assert_eq!(session.name, "__Secure-next-auth.session-token");
+        assert_eq!(session.value, "placeholder-session-token");
+        assert_eq!(session.domain, ".chatgpt.com");
+        assert_eq!(session.path, "/");
+        assert_eq!(session.expires, Some(2000000000.5));
+        assert_eq!(session.httpOnly, Some(true));
+        assert_eq!(session.secure, Some(true));
+        assert_eq!(session.sameSite.as_deref(), Some("Lax"));
+        assert_eq!(session.sourcePort, Some(443));
+
+        let device = &cookies[1];
+        assert_eq!(device.name, "oai-did");
+        assert_eq!(device.domain, "chatgpt.com");
+        assert_eq!(device.expires, Some(-1.0));
+        assert_eq!(device.session, Some(true));
+        assert_eq!(device.sameSite, None);
+        assert!(device.extra.is_empty());
+    }
+
+    fn cookie(name: &str) -> Cookie {
+        serde_json::from_value(json!({
+            "domain": "example.com",
+            "name": name,
+            "path": "/",
+            "value": "v",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn cookie_cache_expires_and_tracks_url() {
+        let mut tab = ChromeTab {
+            id: "A".into(),
+            title: "Example".into(),
+            url: "https://example.com/".into(),
+            webSocketDebuggerUrl: None,
+            target_type: None,
+            browserContextId: None,
+        };
+        let mut cache = CookieCache::new(Duration::from_secs(60));
+        assert!(cache.get(&tab).is_none());
+        cache.insert(&tab, vec![cookie("sid")]);
+        assert_eq!(cache.get(&tab).map(|c| c.len()), Some(1));
+        tab.url = "https://example.com/other".into();
+        assert!(cache.get(&tab).is_none());
+        cache.insert(&tab, vec![cookie("sid")]);
+        cache.invalidate("A");
+        assert!(cache.get(&tab).is_none());
+
+        let mut stale = CookieCache::new(Duration::ZERO);
+        stale.insert(&tab, vec![cookie("sid")]);
+        assert!(stale.get(&tab).is_none());
+    }
+
+    #[test]
+    fn cookie_url_matching_reasons() {
+        let mut c = cookie("sid");
+        c.domain = ".example.com".into();
+        let url = Url::parse("https://app.example.com/dashboard").unwrap();
+        assert!(cookie_applies_to(&c, &url, 0.0).is_ok());
+
+        c.domain = "example.com".into();
+        assert!(cookie_applies_to(&c, &url, 0.0).is_err());
+
+        c.domain = "app.example.com".into();
+        c.path = "/dash".into();
+        assert!(cookie_applies_to(&c, &url, 0.0).is_err());
+
+        c.path = "/".into();
+        c.secure = Some(true);
+        let http = Url::parse("http://app.example.com/").unwrap();
+        assert!(cookie_applies_to(&c, &http, 0.0).is_err());
+
+        c.secure = Some(false);
+        c.sameSite = Some("None".into());
+        assert!(cookie_applies_to(&c, &url, 0.0).is_err());
+
+        c.sameSite = None;
+        c.expires = Some(100.0);
+        assert!(cookie_applies_to(&c, &url, 200.0).is_err());
+        c.expires = Some(-1.0);
+        assert!(cookie_applies_to(&c, &url, 200.0).is_ok());
+    }
+
+    #[test]
+    fn ws_urls_are_rewritten_to_the_configured_host() {
+        let cdp = CdpEndpoint::new("10.0.0.5", 9333);
+        assert_eq!(
+            cdp.rewrite_ws_url("ws://127.0.0.1:9222/devtools/page/ABC"),
+            "ws://10.0.0.5:9333/devtools/page/ABC"
+        );
+        assert_eq!(cdp.http_url("/json"), "http://10.0.0.5:9333/json");
+        assert_eq!(
+            CdpEndpoint::new("::1", 9222).http_url("/json"),
+            "http://[::1]:9222/json"
+        );
+    }
+
+    #[test]
+    fn non_web_urls_are_refused() {
+        assert_eq!(
+            normalize_url("example.com/a").unwrap(),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            normalize_url("localhost:3000").unwrap(),
+            "https://localhost:3000"
+        );
+        assert!(normalize_url("about:blank").is_err());
+        assert!(normalize_url("chrome://settings").is_err());
+        assert!(normalize_url("JavaScript:alert(1)").is_err());
+    }
+
+    #[test]
+    fn read_back_cookies_are_diffed() {
+        let intended = vec![cookie("a"), cookie("b"), cookie("c"), cookie("d")];
+        let mut stored_a = cookie("a");
+        stored_a.domain = ".example.com".into();
+        let mut stored_b = cookie("b");
+        stored_b.value = "changed".into();
+        let rejected = HashMap::from([(cookie_label(&cookie("d")), "bad domain".to_string())]);
+
+        let report = verify_cookies(&intended, &[stored_a, stored_b], rejected);
+        assert_eq!(report.verified, 1);
+        let reasons: Vec<&str> = report.failed.iter().map(|(_, r)| r.as_str()).collect();
+        assert_eq!(
+            reasons,
+            ["stored with a different value", "not stored", "bad domain"]
+        );
+    }
+
+    #[test]
+    fn edited_cookies_are_validated() {
+        let mut c = cookie("sid");
+        assert!(validate_cookie(&c).is_ok());
+        c.domain = ".staging.example.com".into();
+        c.sameSite = Some("Lax".into());
+        assert!(validate_cookie(&c).is_ok());
+
+        let invalid = |edit: fn(&mut Cookie)| {
+            let mut c = cookie("sid");
+            edit(&mut c);
+            validate_cookie(&c).is_err()
+        };
+        assert!(invalid(|c| c.domain = "exa mple.com".into()));
+        assert!(invalid(|c| c.domain = ".".into()));
+        assert!(invalid(|c| c.path = "admin".into()));
+        assert!(invalid(|c| c.value = "a;b".into()));
+        assert!(invalid(|c| c.name = String::new()));
+        assert!(invalid(|c| c.sameSite = Some("lax-ish".into())));
+        assert!(invalid(|c| c.sameSite = Some("None".into())));
+    }
+
+    #[test]
+    fn expiry_is_described_relative_to_now() {
+        let now = chrono::DateTime::parse_from_rfc3339("2024-05-06T07:08:09Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let at = |offset: i64| Some((now.timestamp() + offset) as f64);
+
+        assert_eq!(relative_expiry(None, None, now), "session");
+        assert_eq!(relative_expiry(Some(-1.0), Some(true), now), "session");
+        assert_eq!(relative_expiry(at(3 * 86400 + 5), None, now), "in 3 days");
+        assert_eq!(relative_expiry(at(3600), None, now), "in 1 hour");
+        assert_eq!(relative_expiry(at(30), None, now), "in under a minute");
+        assert_eq!(
+            relative_expiry(at(-120), None, now),
+            "expired 2 minutes ago"
+        );
+
+        assert_eq!(describe_expiry(Some(-1.0), Some(true), now), "session");
+        assert_eq!(
+            describe_expiry(at(-2 * 86400), Some(false), now),
+            "2024-05-04T07:08:09Z (expired 2 days ago)"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn singleton_lock_is_detected_unless_stale() {
+        let data_dir = tempfile::TempDir::new().unwrap();
+        let profile = data_dir.path().join("Default");
+        fs::create_dir(&profile).unwrap();
+        assert_eq!(profile_lock_holder(&profile), None);
+
+        let lock = data_dir.path().join("SingletonLock");
+        std::os::unix::fs::symlink("otherhost-4242", &lock).unwrap();
+        assert_eq!(
+            profile_lock_holder(&profile).as_deref(),
+            Some("pid 4242 on otherhost")
+        );
+
+        if cfg!(target_os = "linux") {
+            let host = gethostname::gethostname().to_string_lossy().into_owned();
+            fs::remove_file(&lock).unwrap();
+            std::os::unix::fs::symlink(format!("{}-{}", host, std::process::id()), &lock).unwrap();
+            assert!(profile_lock_holder(&profile).is_some());
+            fs::remove_file(&lock).unwrap();
+            std::os::unix::fs::symlink(format!("{}-{}", host, u32::MAX), &lock).unwrap();
+            assert_eq!(profile_lock_holder(&profile), None);
+        }
+    }
+
+    #[test]
+    fn profile_names_pick_the_browser_and_its_profile_dir() {
+        assert_eq!(
+            BrowserKind::from_profile("default"),
+            Some(BrowserKind::Chrome)
+        );
+        assert_eq!(
+            BrowserKind::from_profile("Brave-Default"),
+            Some(BrowserKind::Brave)
+        );
+        assert_eq!(
+            BrowserKind::from_profile("edge-default"),
+            Some(BrowserKind::Edge)
+        );
+        assert_eq!(BrowserKind::from_profile("work"), None);
+        assert_eq!(BrowserKind::from_profile("opera-default"), None);
+
+        let brave = real_profile_dir(Some("brave-default")).unwrap();
+        assert!(brave.ends_with(Path::new(BrowserKind::Brave.user_data_dir()).join("Default")));
+        assert!(real_profile_dir(Some("work")).is_none());
+        assert!(real_profile_dir(None).is_none());
+    }
+
+    #[test]
+    fn chrome_is_found_in_the_first_path_entry_that_has_it() {
+        let (a, b) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        fs::create_dir(a.path().join("chromium")).unwrap();
+        fs::write(b.path().join("chromium"), "").unwrap();
+        let path_var = std::env::join_paths([a.path(), b.path()]).unwrap();
+        assert_eq!(
+            find_in_path("chromium", &path_var),
+            Some(b.path().join("chromium"))
+        );
+        assert_eq!(find_in_path("google-chrome", &path_var), None);
+    }
+
+    #[test]
+    fn only_user_tabs_are_listed() {
+        let target = |kind: Option<&str>, url: &str| -> ChromeTab {
+            serde_json::from_value(json!({
+                "id": "T", "title": "", "url": url, "type": kind,
+            }))
+            .unwrap()
+        };
+        assert!(is_user_tab(&target(Some("page"), "https://example.com/")));
+        assert!(is_user_tab(&target(None, "about:blank")));
+        assert!(!is_user_tab(&target(
+            Some("service_worker"),
+            "https://example.com/sw.js"
+        )));
+        assert!(!is_user_tab(&target(
+            Some("background_page"),
+            "chrome-extension://abc/bg.html"
+        )));
+        assert!(!is_user_tab(&target(
+            Some("page"),
+            "chrome-extension://abc/options.html"
+        )));
+        assert!(!is_user_tab(&target(
+            Some("page"),
+            "devtools://devtools/bundled/inspector.html"
+        )));
+    }
+
+    #[test]
+    fn tabs_serialize_in_the_json_listing_shape() {
+        let listed = json!({
+            "id": "T1",
+            "title": "Inbox",
+            "url": "https://mail.example.com/",
+            "type": "page",
+        });
+        let tab: ChromeTab = serde_json::from_value(listed.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&tab).unwrap(), listed);
+    }
+
+    #[test]
+    fn new_tab_ids_may_be_strings_or_numbers() {
+        assert_eq!(new_tab_id(&json!({ "id": "6A1F" })).unwrap(), "6A1F");
+        assert_eq!(new_tab_id(&json!({ "id": 42 })).unwrap(), "42");
+        assert!(new_tab_id(&json!({ "id": "" })).is_err());
+        assert!(new_tab_id(&json!({ "url": "about:blank" })).is_err());
+    }
+
+    #[test]
+    fn export_filename_tokens() {
+        let tab = ChromeTab {
+            id: "ABC123".into(),
+            title: "My Inbox: 3/10".into(),
+            url: "https://mail.example.com/u/0".into(),
+            webSocketDebuggerUrl: None,
+            target_type: None,
+            browserContextId: None,
+        };
+        let now = chrono::DateTime::parse_from_rfc3339("2024-05-06T07:08:09Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let expand = |t| export_filename(t, &tab, CookieFormat::Json, now);
+
+        assert_eq!(
+            expand(DEFAULT_EXPORT_TEMPLATE),
+            "cookies_My_Inbox__3_10.json"
+        );
+        assert_eq!(expand("{domain}"), "mail.example.com");
+        assert_eq!(expand("{id}"), "ABC123");
+        assert_eq!(expand("{date}"), "2024-05-06");
+        assert_eq!(expand("{ext}"), "json");
+        assert_eq!(expand("{nope}_{id}"), "{nope}_ABC123");
+        assert_eq!(
+            export_filename(DEFAULT_EXPORT_TEMPLATE, &tab, CookieFormat::Netscape, now),
+            "cookies_My_Inbox__3_10.txt"
+        );
+
+        let hidden = ChromeTab {
+            title: "../.env *?".into(),
+            ..tab.clone()
+        };
+        assert_eq!(
+            export_filename("{title}", &hidden, CookieFormat::Json, now),
+            "_.env___"
+        );
+    }
+
+    #[test]
+    fn prefixed_cookies_are_set_through_a_secure_url() {
+        let cookie = |name: &str, domain: &str| -> Cookie {
+            serde_json::from_value(json!({
+                "name": name, "value": "v", "domain": domain, "path": "/",
+            }))
+            .unwrap()
+        };
+        let host = set_cookie_params(&cookie("__Host-sid", "app.example.com"));
+        assert_eq!(host["url"], "https://app.example.com/");
+        assert_eq!(host["secure"], true);
+        assert!(host.get("domain").is_none());
+
+        let secure = set_cookie_params(&cookie("__Secure-id", ".example.com"));
+        assert_eq!(secure["url"], "https://example.com/");
+        assert_eq!(secure["domain"], "example.com");
+
+        let plain = set_cookie_params(&cookie("sid", ".example.com"));
+        assert!(plain.get("url").is_none());
+        assert!(plain.get("secure").is_none());
+        assert_eq!(plain["domain"], ".example.com");
+    }
+
+    #[test]
+    fn leading_dots_are_kept_only_without_a_url() {
+        for (domain, bare, alongside_url) in [
+            (".github.com", ".github.com", "github.com"),
+            ("github.com", "github.com", "github.com"),
+            ("www.github.com", "www.github.com", "www.github.com"),
+            ("*.github.com", ".github.com", "github.com"),
+            (" ..GitHub.com", ".github.com", "github.com"),
+        ] {
+            assert_eq!(normalize_domain(domain, false), bare, "{}", domain);
+            assert_eq!(normalize_domain(domain, true), alongside_url, "{}", domain);
+        }
+
+        let wildcard: Cookie = serde_json::from_value(json!({
+            "name": "sid", "value": "v", "domain": "*.github.com", "path": "/",
+        }))
+        .unwrap();
+        assert_eq!(set_cookie_params(&wildcard)["domain"], ".github.com");
+        assert_eq!(validate_cookie(&normalize_for_import(&wildcard)), Ok(()));
+    }
+
+    // An in-memory socket: reads come from `input`, writes pile up in
+    // `output`.
+    #[derive(Default)]
+    struct MockStream {
+        input: std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl AsyncRead for MockStream {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            let n = std::io::Read::read(&mut self.input, buf.initialize_unfilled())?;
+            buf.advance(n);
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncWrite for MockStream {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.output.extend_from_slice(buf);
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn socket(stream: MockStream, role: Role) -> WebSocketStream<MockStream> {
+        WebSocketStream::from_raw_socket(stream, role, None).await
+    }
+
+    // Messages framed as the browser end would send them, or decoded from
+    // what the client end wrote.
+    async fn browser_frames(messages: &[Value]) -> Vec<u8> {
+        let mut browser = socket(MockStream::default(), Role::Server).await;
+        for message in messages {
+            browser
+                .send(Message::Text(message.to_string().into()))
+                .await
+                .unwrap();
+        }
+        std::mem::take(&mut browser.get_mut().output)
+    }
+
+    async fn sent_commands(bytes: Vec<u8>) -> Vec<Value> {
+        let stream = MockStream {
+            input: std::io::Cursor::new(bytes),
+            output: Vec::new(),
+        };
+        socket(stream, Role::Server)
+            .await
+            .filter_map(|msg| async { msg.ok() })
+            .map(|msg| serde_json::from_str(msg.to_text().unwrap()).unwrap())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn session_ids_increase_and_replies_are_matched_by_id() {
+        let replies = browser_frames(&[
+            json!({ "method": "Target.targetCreated", "params": {} }),
+            json!({ "id": 9, "result": { "late": true } }),
+            json!({ "id": 1, "result": { "first": true } }),
+            json!({ "id": 2, "sessionId": "S-other", "result": {} }),
+            json!({ "id": 2, "sessionId": "S-T1", "result": { "second": true } }),
+            json!({ "id": 3, "error": { "code": -32000, "message": "nope" } }),
+        ])
+        .await;
+        let stream = MockStream {
+            input: std::io::Cursor::new(replies),
+            output: Vec::new(),
+        };
+        let mut session = CdpSession::from_socket(socket(stream, Role::Client).await);
+
+        let first = session.send_raw("Test.one", json!({})).await.unwrap();
+        assert_eq!(first, json!({ "first": true }));
+        let second = session
+            .send_to("S-T1", "Test.two", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(second, json!({ "second": true }));
+        let err = session.send_raw("Test.three", json!({})).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<CdpError>().unwrap().message, "nope");
+        assert_eq!(session.next_id(), 4);
+        // Out of replies: an error, not a hang.
+        assert!(session.send_raw("Test.five", json!({})).await.is_err());
+
+        let sent = sent_commands(std::mem::take(&mut session.socket.get_mut().output)).await;
+        let ids: Vec<_> = sent.iter().map(|cmd| cmd["id"].clone()).collect();
+        assert_eq!(ids, [json!(1), json!(2), json!(3), json!(5)]);
+        assert_eq!(sent[1]["sessionId"], "S-T1");
+        assert_eq!(sent[2]["method"], "Test.three");
+    }
+
+    #[test]
+    fn rejected_cookies_get_a_likely_reason() {
+        let hint = |edit: fn(&mut Cookie)| {
+            let mut c = cookie("sid");
+            edit(&mut c);
+            rejection_hint(&c, 1_000.0)
+        };
+        assert_eq!(hint(|_| {}), "rejected by Chrome (domain mismatch?)");
+        assert!(hint(|c| c.domain = "exa mple.com".into()).contains("invalid domain"));
+        assert!(hint(|c| c.value = "x".repeat(5000)).contains("4096"));
+        assert!(
+            hint(|c| {
+                c.name = "__Host-sid".into();
+                c.path = "/app".into();
+            })
+            .contains("path /")
+        );
+        assert!(hint(|c| c.expires = Some(10.0)).contains("expired"));
+    }
+
+    #[test]
+    fn session_cookies_round_trip_without_an_expiry() {
+        let exported: Cookie = serde_json::from_value(json!({
+            "name": "sid", "value": "v", "domain": ".example.com", "path": "/",
+            "expires": -1, "session": true,
+        }))
+        .unwrap();
+        let reloaded: Cookie =
+            serde_json::from_str(&serde_json::to_string(&exported).unwrap()).unwrap();
+        assert!(set_cookie_params(&reloaded).get("expires").is_none());
+
+        let mut persistent = reloaded.clone();
+        persistent.session = Some(false);
+        persistent.expires = Some(1_900_000_000.0);
+        assert_eq!(set_cookie_params(&persistent)["expires"], 1_900_000_000.0);
+        persistent.session = Some(true);
+        assert!(set_cookie_params(&persistent).get("expires").is_none());
+    }
+
+    #[test]
+    fn same_site_is_normalized_for_set_cookie() {
+        assert_eq!(normalize_same_site("lax"), Some("Lax"));
+        assert_eq!(normalize_same_site(" STRICT "), Some("Strict"));
+        assert_eq!(normalize_same_site("no_restriction"), Some("None"));
+        assert_eq!(normalize_same_site("unspecified"), None);
+        assert_eq!(normalize_same_site("lax-ish"), None);
+
+        let params = |same_site: &str| {
+            set_cookie_params(
+                &serde_json::from_value(json!({
+                    "name": "sid", "value": "v", "domain": ".example.com", "path": "/",
+                    "secure": false, "sameSite": same_site,
+                }))
+                .unwrap(),
+            )
+        };
+        assert_eq!(params("lax")["sameSite"], "Lax");
+        assert!(params("lax").get("secure").is_none());
+        assert!(params("unspecified").get("sameSite").is_none());
+        let none = params("no_restriction");
+        assert_eq!(none["sameSite"], "None");
+        assert_eq!(none["secure"], true);
+    }
+
+    #[test]
+    fn cookies_are_filtered_by_domain_glob() {
+        let cookies: Vec<Cookie> = serde_json::from_value(json!([
+            { "name": "a", "value": "", "domain": ".openai.com", "path": "/" },
+            { "name": "b", "value": "", "domain": "chat.openai.com", "path": "/" },
+            { "name": "c", "value": "", "domain": ".doubleclick.net", "path": "/" },
+            { "name": "d", "value": "", "domain": "ads-openai.example", "path": "/" },
+        ]))
+        .unwrap();
+        let names = |patterns: &str| -> Vec<String> {
+            filter_cookies(cookies.clone(), &parse_domain_patterns(patterns))
+                .into_iter()
+                .map(|c| c.name)
+                .collect()
+        };
+        assert_eq!(names(""), ["a", "b", "c", "d"]);
+        assert_eq!(names("*.openai.com"), ["a", "b"]);
+        assert_eq!(names("CHAT.openai.com"), ["b"]);
+        assert_eq!(names("*openai*"), ["a", "b", "d"]);
+        assert_eq!(names("openai.com, *.net"), ["a", "c"]);
+        assert_eq!(names("*.com.evil"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn netscape_files_parse_back_into_cookies() {
+        let txt = "# Netscape HTTP Cookie File\n\
+                   \n\
+                   #HttpOnly_.example.com\tTRUE\t/\tTRUE\t1900000000\tsid\tabc\n\
+                   # a comment\n\
+                   www.example.com\tFALSE\t/app\tFALSE\t0\tpref\tdark mode\r\n\
+                   not a cookie line\n";
+        let cookies = parse_netscape(txt);
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[0].domain, ".example.com");
+        assert_eq!(cookies[0].httpOnly, Some(true));
+        assert_eq!(cookies[0].secure, Some(true));
+        assert_eq!(cookies[0].expires, Some(1_900_000_000.0));
+        assert_eq!(cookies[1].value, "dark mode");
+        assert_eq!(cookies[1].httpOnly, Some(false));
+        assert_eq!(cookies[1].session, Some(true));
+        assert_eq!(
+            to_netscape(&cookies).lines().nth(4),
+            txt.lines().nth(4).map(|l| l.trim_end())
+        );
+    }
+
+    #[test]
+    fn netscape_export_flags_domains_sessions_and_http_only() {
+        let cookies: Vec<Cookie> = serde_json::from_value(json!([
+            { "name": "sid", "value": "abc", "domain": ".example.com", "path": "/",
+              "expires": 1_900_000_000.5, "secure": true, "httpOnly": true, "session": false },
+            { "name": "pref", "value": "dark", "domain": "www.example.com", "path": "/app",
+              "expires": -1.0, "session": true },
+        ]))
+        .unwrap();
+        let txt = to_netscape(&cookies);
+        let lines: Vec<_> = txt.lines().collect();
+        assert_eq!(lines[0], "# Netscape HTTP Cookie File");
+        assert_eq!(
+            lines[3],
+            "#HttpOnly_.example.com\tTRUE\t/\tTRUE\t1900000000\tsid\tabc"
+        );
+        assert_eq!(
+            lines[4],
+            "www.example.com\tFALSE\t/app\tFALSE\t0\tpref\tdark"
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_the_first_success_or_the_last_delay() {
+        let delays = [Duration::ZERO; 2];
+        let mut calls = 0;
+        let result: Result<u32, String> = with_retries(&delays, || {
+            calls += 1;
+            let call = calls;
+            async move {
+                if call < 2 {
+                    Err("refused".into())
+                } else {
+                    Ok(call)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result, Ok(2));
+
+        calls = 0;
+        let result: Result<u32, String> = with_retries(&delays, || {
+            calls += 1;
+            let call = calls;
+            async move { Err(format!("refused #{}", call)) }
+        })
+        .await;
+        assert_eq!(result, Err("refused #3".to_string()));
+    }
+
+    #[test]
+    fn reconnect_backoff_doubles_up_to_the_cap() {
+        let policy = ReconnectPolicy {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(3),
+            keepalive: Duration::from_secs(10),
+        };
+        let delays: Vec<u128> = (1..=5).map(|n| policy.backoff(n).as_millis()).collect();
+        assert_eq!(delays, [500, 1000, 2000, 3000, 3000]);
+        assert_eq!(policy.backoff(200), Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn stopping_interrupts_a_reconnect_backoff() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let cdp = CdpEndpoint::new("127.0.0.1", port);
+        let policy = ReconnectPolicy {
+            initial_backoff: Duration::from_secs(60),
+            ..ReconnectPolicy::default()
+        };
+        let stop = AtomicBool::new(false);
+        let started = Instant::now();
+        watch_targets(
+            &cdp,
+            &policy,
+            &stop,
+            || {},
+            |health| {
+                if matches!(health, EventSocketHealth::Reconnecting { .. }) {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            },
+        )
+        .await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Defaults for command-line flags, read from a hand-written TOML file such
+/// as
+///
+/// ```toml
+/// cdp_port = 9333
+/// listen = "0.0.0.0:9234"
+/// auth_token = "hunter2"
+/// browser = "chrome"
+/// ```
+///
+/// Every key is optional, and a flag given on the command line (or through
+/// its environment variable) wins over the file.
+#[derive(Deserialize, Default, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Like `--cdp-port`.
+    pub cdp_port: Option<u16>,
+    /// Like `--serve`: `NAME=ADDR` or just `ADDR`.
+    pub listen: Option<String>,
+    /// Like `--auth-token`.
+    pub auth_token: Option<String>,
+    /// Like `--secret`.
+    pub secret: Option<String>,
+    /// Like `--browser`: `chrome` or `firefox`.
+    pub browser: Option<String>,
+    /// Like `--close-on-revoke`.
+    pub close_on_revoke: Option<bool>,
+}
+
+impl Config {
+    /// `config.toml` under the OS config directory, next to the GUI settings.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("sharekaro").join("config.toml"))
+    }
+
+    /// Read `path`, or the default location when `None`. A missing file at
+    /// the default location means no overrides; one named explicitly must
+    /// exist.
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e).into()),
+            Err(e) if e.kind() == ErrorKind::NotFound && !explicit => Ok(Self::default()),
+            Err(e) => Err(format!("could not read {}: {}", path.display(), e).into()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_key_is_optional_and_typos_are_errors() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        let config = Config::parse("cdp_port = 9333\nbrowser = \"firefox\"\n").unwrap();
+        assert_eq!(config.cdp_port, Some(9333));
+        assert_eq!(config.browser.as_deref(), Some("firefox"));
+        assert_eq!(config.listen, None);
+
+        assert!(Config::parse("cdp-port = 9333").is_err());
+        assert!(Config::parse("cdp_port = \"9333\"").is_err());
+    }
+
+    #[test]
+    fn only_the_default_location_may_be_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("config.toml");
+        assert!(Config::load(Some(&missing)).is_err());
+
+        fs::write(&missing, "auth_token = \"t\"").unwrap();
+        let config = Config::load(Some(&missing)).unwrap();
+        assert_eq!(config.auth_token.as_deref(), Some("t"));
+    }
+}
@@ -0,0 +1,179 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::error::Error;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const KEY_INFO: &[u8] = b"sharekaro grant payload v1";
+
+/// The least estimated strength `check_secret` accepts, in bits.
+pub const MIN_SECRET_BITS: f64 = 80.0;
+const MIN_SECRET_DISTINCT: usize = 8;
+
+/// Refuse a pre-shared secret that could be guessed.
+///
+/// The secret goes through HKDF unstretched, so a man in the middle who
+/// records one session can test guesses offline as fast as they can hash.
+/// Only a secret with enough entropy of its own holds up to that. Its
+/// strength is estimated as length times the bits per character of the
+/// character classes used, which gives e.g. 5 random words or 16 random
+/// characters from a mixed alphabet.
+pub fn check_secret(secret: &str) -> Result<(), String> {
+    let chars: Vec<char> = secret.chars().collect();
+    let uses = |class: fn(&char) -> bool, size: f64| {
+        if chars.iter().any(class) { size } else { 0.0 }
+    };
+    let alphabet = uses(char::is_ascii_lowercase, 26.0)
+        + uses(char::is_ascii_uppercase, 26.0)
+        + uses(char::is_ascii_digit, 10.0)
+        + uses(|c| !c.is_ascii_alphanumeric(), 33.0);
+    let bits = chars.len() as f64 * alphabet.max(1.0).log2();
+    let distinct = chars.iter().collect::<std::collections::HashSet<_>>().len();
+    if bits < MIN_SECRET_BITS || distinct < MIN_SECRET_DISTINCT {
+        return Err(format!(
+            "the secret is too easy to guess (about {:.0} bits, at least {:.0} needed); \
+             use 5 or more random words, or e.g. the output of `openssl rand -base64 24`",
+            bits, MIN_SECRET_BITS
+        ));
+    }
+    Ok(())
+}
+
+/// One side of the per-connection key exchange.
+///
+/// Each peer sends its public half in a `Hello` frame and derives the same
+/// session key from the other's. The exchange itself is unauthenticated, so on
+/// its own it protects against passive observers and logs, not against an
+/// active man in the middle. Mixing in a secret both sides were given out of
+/// band closes that gap: without it a man in the middle derives a different
+/// key and every payload fails to open. The secret must be strong enough to
+/// survive offline guessing; see `check_secret`.
+pub struct Handshake {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl Handshake {
+    pub fn new() -> Self {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    pub fn public_key(&self) -> String {
+        BASE64.encode(self.public.as_bytes())
+    }
+
+    pub fn finish(
+        &self,
+        peer_public: &str,
+        secret: Option<&str>,
+    ) -> Result<SessionKey, Box<dyn Error + Send + Sync>> {
+        let bytes: [u8; 32] = BASE64
+            .decode(peer_public)?
+            .try_into()
+            .map_err(|_| "peer public key must be 32 bytes")?;
+        let shared = self.secret.diffie_hellman(&PublicKey::from(bytes));
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(secret.map(str::as_bytes), shared.as_bytes())
+            .expand(KEY_INFO, &mut key)
+            .map_err(|_| "key derivation failed")?;
+        Ok(SessionKey(key))
+    }
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone)]
+pub struct SessionKey([u8; 32]);
+
+impl SessionKey {
+    /// Encrypt `plaintext`, returning base64 `(nonce, ciphertext)`.
+    pub fn seal(&self, plaintext: &[u8]) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.0));
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| "encryption failed")?;
+        Ok((BASE64.encode(nonce), BASE64.encode(ciphertext)))
+    }
+
+    pub fn open(
+        &self,
+        nonce: &str,
+        ciphertext: &str,
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let nonce = BASE64.decode(nonce)?;
+        if nonce.len() != 12 {
+            return Err("nonce must be 12 bytes".into());
+        }
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.0));
+        cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                BASE64.decode(ciphertext)?.as_slice(),
+            )
+            .map_err(|_| "decryption failed".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_sides_derive_the_same_key() {
+        let server = Handshake::new();
+        let client = Handshake::new();
+        let server_key = server.finish(&client.public_key(), None).unwrap();
+        let client_key = client.finish(&server.public_key(), None).unwrap();
+
+        let (nonce, ciphertext) = server_key.seal(b"cookies").unwrap();
+        assert_eq!(client_key.open(&nonce, &ciphertext).unwrap(), b"cookies");
+
+        let stranger = Handshake::new().finish(&server.public_key(), None).unwrap();
+        assert!(stranger.open(&nonce, &ciphertext).is_err());
+    }
+
+    #[test]
+    fn a_different_secret_means_a_different_key() {
+        let server = Handshake::new();
+        let client = Handshake::new();
+        let server_key = server
+            .finish(&client.public_key(), Some("hunter2"))
+            .unwrap();
+        let (nonce, ciphertext) = server_key.seal(b"cookies").unwrap();
+
+        let same = client
+            .finish(&server.public_key(), Some("hunter2"))
+            .unwrap();
+        assert_eq!(same.open(&nonce, &ciphertext).unwrap(), b"cookies");
+        for other in [Some("hunter3"), None] {
+            let key = client.finish(&server.public_key(), other).unwrap();
+            let err = key.open(&nonce, &ciphertext).unwrap_err();
+            assert_eq!(err.to_string(), "decryption failed");
+        }
+    }
+
+    #[test]
+    fn guessable_secrets_are_refused() {
+        for weak in [
+            "",
+            "hunter2",
+            "password1234",
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        ] {
+            assert!(check_secret(weak).is_err(), "{:?}", weak);
+        }
+        for strong in ["correct horse battery staple", "q8ZxN2vLw0TfR7kPb3YdHs9m"] {
+            assert_eq!(check_secret(strong), Ok(()), "{:?}", strong);
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+/// DNS-SD service type share servers advertise under.
+pub const SERVICE: &str = "_sharekaro._tcp.local";
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+const TTL_SECS: u32 = 120;
+
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+
+/// A share server found on the LAN by `browse`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredPeer {
+    /// The advertising machine's host name.
+    pub name: String,
+    pub addr: SocketAddr,
+    pub tls: bool,
+}
+
+impl DiscoveredPeer {
+    /// The URL `connect_client` dials for this peer.
+    pub fn url(&self) -> String {
+        let scheme = if self.tls { "wss" } else { "ws" };
+        format!("{}://{}", scheme, self.addr)
+    }
+}
+
+/// The name a server on this machine advertises: the host name up to its
+/// first dot, since it becomes a single DNS label.
+pub fn instance_name() -> String {
+    let host = gethostname::gethostname().to_string_lossy().into_owned();
+    let label = host.split('.').next().unwrap_or_default();
+    let label: String = label.chars().take(63).collect();
+    if label.is_empty() {
+        "sharekaro".to_string()
+    } else {
+        label
+    }
+}
+
+/// Answer mDNS queries for `SERVICE` with this server's port until
+/// `shutdown` fires. Failing to join the multicast group is logged rather
+/// than fatal; the server works the same, it just can't be found.
+pub async fn advertise(port: u16, tls: bool, mut shutdown: watch::Receiver<bool>) {
+    let socket = match responder_socket() {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("Not advertising over mDNS: {}", e);
+            return;
+        }
+    };
+    let name = instance_name();
+    let group = SocketAddr::from((MDNS_GROUP, MDNS_PORT));
+    let announcement = response(0, &name, port, tls, false);
+    if let Err(e) = socket.send_to(&announcement, group).await {
+        debug!("mDNS announcement failed: {}", e);
+    }
+    info!("Advertising {}.{} on port {}", name, SERVICE, port);
+
+    let mut buf = [0u8; 1500];
+    loop {
+        let (len, from) = tokio::select! {
+            received = socket.recv_from(&mut buf) => match received {
+                Ok(received) => received,
+                Err(e) => {
+                    debug!("mDNS receive failed: {}", e);
+                    continue;
+                }
+            },
+            _ = shutdown.changed() => break,
+        };
+        let Some(id) = asks_for_service(&buf[..len]) else {
+            continue;
+        };
+        // A query from any port but 5353 is a one-shot "legacy" resolver,
+        // answered directly and with its own id echoed (RFC 6762 §6.7).
+        let sent = if from.port() == MDNS_PORT {
+            socket
+                .send_to(&response(0, &name, port, tls, false), group)
+                .await
+        } else {
+            socket
+                .send_to(&response(id, &name, port, tls, true), from)
+                .await
+        };
+        if let Err(e) = sent {
+            debug!(%from, "mDNS reply failed: {}", e);
+        }
+    }
+}
+
+/// Ask the LAN for share servers and collect the answers that arrive within
+/// `wait`. The address is the one each answer came from, so it is reachable
+/// from here even when the advertising machine has several.
+pub async fn browse(wait: Duration) -> std::io::Result<Vec<DiscoveredPeer>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket
+        .send_to(&query(), SocketAddr::from((MDNS_GROUP, MDNS_PORT)))
+        .await?;
+    let deadline = tokio::time::Instant::now() + wait;
+    let mut found: Vec<DiscoveredPeer> = Vec::new();
+    let mut buf = [0u8; 1500];
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, from) = received?;
+        for (name, port, tls) in parse_response(&buf[..len]) {
+            let peer = DiscoveredPeer {
+                name,
+                addr: SocketAddr::new(from.ip(), port),
+                tls,
+            };
+            if !found.iter().any(|p| p.addr == peer.addr) {
+                found.push(peer);
+            }
+        }
+    }
+    Ok(found)
+}
+
+// Bound to 5353 next to any system responder (Avahi, Bonjour), which also
+// sets SO_REUSEADDR.
+fn responder_socket() -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT).into())?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+fn query() -> Vec<u8> {
+    let mut packet = header(0, 0, 1, 0);
+    put_name(&mut packet, SERVICE);
+    put_u16(&mut packet, TYPE_PTR);
+    put_u16(&mut packet, CLASS_IN);
+    packet
+}
+
+// The query's id if it is a query with a question for `SERVICE`.
+fn asks_for_service(packet: &[u8]) -> Option<u16> {
+    let id = read_u16(packet, 0)?;
+    let flags = read_u16(packet, 2)?;
+    if flags & 0x8000 != 0 {
+        return None;
+    }
+    let mut pos = 12;
+    for _ in 0..read_u16(packet, 4)? {
+        let (name, next) = read_name(packet, pos)?;
+        let qtype = read_u16(packet, next)?;
+        pos = next + 4;
+        if name.eq_ignore_ascii_case(SERVICE) && matches!(qtype, TYPE_PTR | TYPE_ANY) {
+            return Some(id);
+        }
+    }
+    None
+}
+
+// PTR, SRV and TXT records for this server. Legacy unicast replies repeat
+// the question.
+fn response(id: u16, name: &str, port: u16, tls: bool, with_question: bool) -> Vec<u8> {
+    let instance = format!("{}.{}", name, SERVICE);
+    let target = format!("{}.local", name);
+    let mut packet = header(id, 0x8400, u16::from(with_question), 3);
+    if with_question {
+        put_name(&mut packet, SERVICE);
+        put_u16(&mut packet, TYPE_PTR);
+        put_u16(&mut packet, CLASS_IN);
+    }
+
+    let mut ptr = Vec::new();
+    put_name(&mut ptr, &instance);
+    put_record(&mut packet, SERVICE, TYPE_PTR, &ptr);
+
+    let mut srv = Vec::new();
+    put_u16(&mut srv, 0);
+    put_u16(&mut srv, 0);
+    put_u16(&mut srv, port);
+    put_name(&mut srv, &target);
+    put_record(&mut packet, &instance, TYPE_SRV, &srv);
+
+    let entry = if tls { "tls=1" } else { "tls=0" };
+    let mut txt = vec![entry.len() as u8];
+    txt.extend_from_slice(entry.as_bytes());
+    put_record(&mut packet, &instance, TYPE_TXT, &txt);
+    packet
+}
+
+// Each advertised instance as (name, port, tls), from the SRV records for
+// `SERVICE` and the TXT record next to them.
+fn parse_response(packet: &[u8]) -> Vec<(String, u16, bool)> {
+    let mut found = Vec::new();
+    let Some(flags) = read_u16(packet, 2) else {
+        return found;
+    };
+    if flags & 0x8000 == 0 {
+        return found;
+    }
+    let counts: Option<Vec<u16>> = (0..4).map(|i| read_u16(packet, 4 + 2 * i)).collect();
+    let Some(counts) = counts else {
+        return found;
+    };
+    let mut pos = 12;
+    for _ in 0..counts[0] {
+        let Some((_, next)) = read_name(packet, pos) else {
+            return found;
+        };
+        pos = next + 4;
+    }
+
+    let suffix = format!(".{}", SERVICE);
+    let mut srvs = Vec::new();
+    let mut tls = Vec::new();
+    for _ in 0..counts[1] as usize + counts[2] as usize + counts[3] as usize {
+        let Some((owner, next)) = read_name(packet, pos) else {
+            break;
+        };
+        let (Some(rtype), Some(len)) = (read_u16(packet, next), read_u16(packet, next + 8)) else {
+            break;
+        };
+        let data = next + 10;
+        pos = data + len as usize;
+        let Some(rdata) = packet.get(data..pos) else {
+            break;
+        };
+        let Some(instance) = owner
+            .len()
+            .checked_sub(suffix.len())
+            .filter(|&at| owner[at..].eq_ignore_ascii_case(&suffix))
+            .map(|at| owner[..at].to_string())
+        else {
+            continue;
+        };
+        match rtype {
+            TYPE_SRV => {
+                if let Some(port) = read_u16(packet, data + 4) {
+                    srvs.push((instance, port));
+                }
+            }
+            TYPE_TXT => tls.push((instance, txt_entries(rdata).any(|e| e == "tls=1"))),
+            _ => {}
+        }
+    }
+    for (instance, port) in srvs {
+        let secure = tls.iter().any(|(name, on)| *name == instance && *on);
+        found.push((instance, port, secure));
+    }
+    found
+}
+
+fn txt_entries(rdata: &[u8]) -> impl Iterator<Item = &str> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        let len = *rdata.get(pos)? as usize;
+        let entry = rdata.get(pos + 1..pos + 1 + len)?;
+        pos += 1 + len;
+        Some(std::str::from_utf8(entry).unwrap_or_default())
+    })
+}
+
+fn header(id: u16, flags: u16, questions: u16, answers: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(512);
+    for field in [id, flags, questions, answers, 0, 0] {
+        put_u16(&mut packet, field);
+    }
+    packet
+}
+
+fn put_record(packet: &mut Vec<u8>, owner: &str, rtype: u16, rdata: &[u8]) {
+    put_name(packet, owner);
+    put_u16(packet, rtype);
+    put_u16(packet, CLASS_IN);
+    packet.extend_from_slice(&TTL_SECS.to_be_bytes());
+    put_u16(packet, rdata.len() as u16);
+    packet.extend_from_slice(rdata);
+}
+
+fn put_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+}
+
+fn put_u16(packet: &mut Vec<u8>, value: u16) {
+    packet.extend_from_slice(&value.to_be_bytes());
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        packet.get(pos..pos + 2)?.try_into().ok()?,
+    ))
+}
+
+// A possibly compressed name at `pos`, and where the data after it starts.
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Every pointer must go backwards, so this bounds the loop.
+    let mut jumps = 0;
+    loop {
+        let len = *packet.get(pos)? as usize;
+        if len & 0xC0 == 0xC0 {
+            let target = (read_u16(packet, pos)? & 0x3FFF) as usize;
+            end.get_or_insert(pos + 2);
+            jumps += 1;
+            if target >= pos || jumps > 32 {
+                return None;
+            }
+            pos = target;
+        } else if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        } else {
+            let label = packet.get(pos + 1..pos + 1 + len)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            pos += 1 + len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_answer_queries_for_the_service() {
+        let id = asks_for_service(&query()).expect("our own query asks for the service");
+        assert_eq!(id, 0);
+        let mut other = header(7, 0, 1, 0);
+        put_name(&mut other, "_http._tcp.local");
+        put_u16(&mut other, TYPE_PTR);
+        put_u16(&mut other, CLASS_IN);
+        assert_eq!(asks_for_service(&other), None);
+
+        let reply = response(7, "desk", 9234, true, true);
+        assert_eq!(read_u16(&reply, 0), Some(7));
+        assert_eq!(asks_for_service(&reply), None, "a response is not a query");
+        assert_eq!(parse_response(&reply), [("desk".to_string(), 9234, true)]);
+        assert_eq!(
+            parse_response(&response(0, "laptop", 9235, false, false)),
+            [("laptop".to_string(), 9235, false)]
+        );
+    }
+
+    #[test]
+    fn compressed_names_are_followed_but_not_in_circles() {
+        // "local" at 12, then "_tcp" pointing back at it.
+        let mut packet = header(0, 0, 0, 0);
+        put_name(&mut packet, "local");
+        packet.extend_from_slice(&[4, b'_', b't', b'c', b'p', 0xC0, 12]);
+        assert_eq!(read_name(&packet, 19), Some(("_tcp.local".to_string(), 26)));
+
+        let looped = [0u8, 0, 0, 0, 0xC0, 4];
+        assert_eq!(read_name(&looped, 4), None);
+    }
+}
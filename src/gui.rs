@@ -1,51 +1,293 @@
+use crate::browser::ChromeBrowser;
 use crate::chrome::{
-    ChromeTab, export_cookies_for_tab, fetch_tabs, get_cookies_for_tab,
-    import_and_open_with_cookies,
+    CdpEndpoint, ChromeHandle, ChromeTab, Cookie, CookieCache, CookieFormat,
+    DEFAULT_EXPORT_TEMPLATE, EventSocketHealth, ReconnectPolicy, cached_cookies,
+    capture_screenshot, default_export_dir, export_cookies_for_tab, fetch_tabs, filter_cookies,
+    get_active_tab, get_cookies_for_urls, import_to_n, match_cookies_to_url, normalize_url,
+    open_tab, parse_domain_patterns, preview_import, relative_expiry, resolve_tab,
+    universal_cookie_loader, validate_cookie, watch_targets,
 };
-use crate::network::{GrantMessage, RevokeCookie, RevokeMessage};
+use crate::discovery::DiscoveredPeer;
+use crate::hotkey::{self, Hotkey, HotkeyGrab};
+use crate::network::{
+    Broadcasts, GrantMessage, PeerLink, PendingGrant, ReceivedShare, ReceiverState, RevokeAck,
+    RevokeBatchMessage, RevokeMessage, grant_for_tab, revoke_for_tab,
+};
+use crate::settings::{Settings, ViewMode};
 use eframe::{App, CreationContext};
 use egui::{
-    Align, CentralPanel, Color32, Direction, FontId, Frame, Label, Layout, Margin, RichText,
-    Rounding, ScrollArea, Sense, TopBottomPanel, Vec2,
+    Align, CentralPanel, Color32, ColorImage, CornerRadius, FontId, Frame, Key, Label, Layout,
+    Margin, Modifiers, RichText, ScrollArea, Sense, Stroke, StrokeKind, TextureHandle,
+    TextureOptions, TopBottomPanel, UiBuilder, Vec2,
 };
 use rfd::FileDialog;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     net::SocketAddr,
-    path::PathBuf,
-    sync::{Arc, Mutex},
-    thread,
-    time::Duration,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
 };
 use tokio::runtime::Handle;
-use tokio::sync::broadcast::Sender as BroadcastSender;
+use tokio::sync::broadcast::{Receiver as BroadcastReceiver, Sender as BroadcastSender};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
 
 pub struct ChromeTabApp {
+    cdp: CdpEndpoint,
     tabs: Arc<Mutex<Vec<ChromeTab>>>,
+    /// Cookies per tab id, refreshed shortly after the tab list changes.
+    cookie_counts: Arc<Mutex<HashMap<String, usize>>>,
+    /// Set when tabs change; the counting loop picks it up.
+    counts_stale: Arc<AtomicBool>,
+    cdp_status: Arc<Mutex<CdpStatus>>,
+    event_health: Arc<Mutex<EventSocketHealth>>,
     cookie_import: CookieImportState,
-    grant_tx: BroadcastSender<GrantMessage>,
-    revoke_tx: BroadcastSender<RevokeMessage>,
+    cookie_cache: Arc<Mutex<CookieCache>>,
+    thumbnails: Thumbnails,
+    settings: Settings,
+    selected_index: usize,
+    /// Tab ids ticked on the grid for "Share selected" / "Revoke selected".
+    selected_tabs: HashSet<String>,
+    /// Grid card last hovered or clicked, outlined; Ctrl+Enter shares it.
+    focused_tab: Option<String>,
+    servers: Vec<(String, Broadcasts)>,
+    active_server: usize,
+    revoke_acks: Vec<BroadcastReceiver<RevokeAck>>,
+    mirrors: HashMap<String, JoinHandle<()>>,
+    extra_origins: String,
+    share_domains: String,
+    tab_filter: String,
+    tab_sort: TabSort,
+    /// Only show tabs from this browser profile, when more than one is open.
+    browser_context: Option<String>,
     listen_addr: String,
-    listening: bool,
+    /// Name typed next to "Save peer" for the address in `listen_addr`.
+    new_peer_name: String,
+    /// URL typed into "Request tab", pulled from the peer being received from.
+    request_url: String,
+    listening: Arc<AtomicBool>,
+    receiver_status: Arc<Mutex<Option<String>>>,
+    receiver: Arc<Mutex<ReceiverState>>,
+    /// Share servers the last mDNS browse found on the LAN.
+    discovered: Arc<Mutex<Vec<DiscoveredPeer>>>,
+    share_draft: Option<ShareDraft>,
+    share_confirm: Option<ShareConfirm>,
+    /// "Don't ask again" was ticked; Share sends straight away until restart.
+    skip_share_confirm: bool,
+    /// When each tab last went out, so a double-click doesn't send it twice.
+    recent_shares: HashMap<String, Instant>,
+    /// The last share or revoke of each tab that went through, for the
+    /// caption on its card.
+    share_marks: HashMap<String, ShareMark>,
+    /// Shares and revokes still running, keyed like their job; `true` for a
+    /// revoke. Moved into `share_marks` once they report success.
+    pending_marks: HashMap<String, bool>,
+    approval: Option<Approval>,
+    last_export: Option<PathBuf>,
+    hotkey_pressed: Arc<AtomicBool>,
+    /// Keeps the global hotkey registered while the window is open.
+    hotkey: Option<HotkeyGrab>,
+    /// Newest last; see `push_toast`.
+    toasts: Vec<Toast>,
+    /// Toasts from tasks off the UI thread, such as the receiver ending.
+    toast_tx: mpsc::UnboundedSender<Toast>,
+    toast_rx: mpsc::UnboundedReceiver<Toast>,
+    /// What `watch_peers` saw last frame, to toast only on changes.
+    seen_link: Option<PeerLink>,
+    seen_peers: HashSet<SocketAddr>,
+    chrome: Arc<Mutex<Option<ChromeHandle>>>,
+    stop_refresh: Arc<AtomicBool>,
+    jobs: Jobs,
+    proxy: Option<String>,
+    export_template: String,
+    include_storage: bool,
     rt_handle: Handle,
-    remote_to_local: Arc<Mutex<HashMap<String, String>>>,
+    egui_ctx: egui::Context,
+}
+
+/// Startup options for `ChromeTabApp` that come from the command line.
+pub struct AppOptions {
+    pub proxy: Option<String>,
+    pub cookie_ttl: Duration,
+    pub export_template: Option<String>,
+    /// Peer to start receiving from right away, as if Listen was pressed.
+    pub connect_to: Option<String>,
+    pub cdp_events: ReconnectPolicy,
+    /// See `ReceiverState::reconnect`.
+    pub peer_reconnect: ReconnectPolicy,
+    /// Overrides `Settings::share_hotkey`.
+    pub share_hotkey: Option<String>,
+    /// The browser behind `cdp`, stopped when the window closes.
+    pub chrome: Arc<Mutex<Option<ChromeHandle>>>,
+    /// Pre-shared secret for receiving; see `ReceiverState::secret`.
+    pub secret: Option<String>,
+    /// See `ReceiverState::pin_cert`.
+    pub pin_cert: Option<String>,
+    /// See `ReceiverState::auth_token`.
+    pub auth_token: Option<String>,
+    /// Initial domain allowlist; see `chrome::filter_cookies`.
+    pub share_domains: Vec<String>,
+    /// Send each shared tab's `localStorage` along with its cookies.
+    pub include_storage: bool,
+    /// See `ReceiverState::close_on_revoke`.
+    pub close_on_revoke: bool,
+    /// Where received shares are remembered between runs, usually
+    /// `network::received_path()`; `None` keeps them in memory only.
+    pub received_path: Option<PathBuf>,
+}
+
+#[derive(Clone)]
+enum CdpStatus {
+    Connecting,
+    Connected,
+    Unreachable(String),
+}
+
+/// Order of the tab grid and list, picked in the titlebar.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+enum TabSort {
+    /// As `/json` returns them.
+    #[default]
+    Chrome,
+    Title,
+    Url,
+    /// `/json` order reversed, which roughly puts the newest tabs first.
+    Recent,
+}
+
+impl TabSort {
+    const ALL: [TabSort; 4] = [
+        TabSort::Chrome,
+        TabSort::Title,
+        TabSort::Url,
+        TabSort::Recent,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            TabSort::Chrome => "Chrome's order",
+            TabSort::Title => "Title A→Z",
+            TabSort::Url => "URL",
+            TabSort::Recent => "Recently opened",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum BatchAction {
+    Share,
+    Revoke,
+}
+
+#[derive(Clone, Copy)]
+enum TabAction {
+    Export,
+    Share,
+    /// Share, asking peers to load it in the tab they're looking at.
+    ShareInCurrent,
+    EditShare,
+    Revoke,
+    /// Revoke, having peers delete every cookie and all site data for the
+    /// tab's URL rather than only the cookies it has now.
+    RevokeSite,
+    ToggleMirror,
 }
 
 impl ChromeTabApp {
     pub fn new(
         cc: &CreationContext<'_>,
-        grant_tx: BroadcastSender<GrantMessage>,
-        revoke_tx: BroadcastSender<RevokeMessage>,
+        cdp: CdpEndpoint,
+        servers: Vec<(String, Broadcasts)>,
+        options: AppOptions,
         rt_handle: Handle,
     ) -> Self {
         let tabs = Arc::new(Mutex::new(Vec::new()));
+        let cdp_status = Arc::new(Mutex::new(CdpStatus::Connecting));
+        let event_health = Arc::new(Mutex::new(EventSocketHealth::Connecting));
+        let cookie_counts = Arc::new(Mutex::new(HashMap::new()));
+        let counts_stale = Arc::new(AtomicBool::new(true));
+        let cookie_cache = Arc::new(Mutex::new(CookieCache::new(options.cookie_ttl)));
         let tabs_clone = Arc::clone(&tabs);
-        thread::spawn(move || {
-            loop {
-                if let Ok(new_tabs) = fetch_tabs() {
-                    *tabs_clone.lock().unwrap() = new_tabs;
+        let stale_clone = Arc::clone(&counts_stale);
+        let status_clone = Arc::clone(&cdp_status);
+        let health_clone = Arc::clone(&event_health);
+        let refresh_cdp = cdp.clone();
+        let policy = options.cdp_events.clone();
+        let stop_refresh = Arc::new(AtomicBool::new(false));
+        let stop_clone = Arc::clone(&stop_refresh);
+        // Tab updates are event-driven; while the event socket is down each
+        // retry also re-polls so the GUI shows Chrome as unreachable.
+        // A burst of events between two listings costs one more listing.
+        rt_handle.spawn(async move {
+            let changed = tokio::sync::Notify::new();
+            let watch = watch_targets(
+                &refresh_cdp,
+                &policy,
+                &stop_clone,
+                || changed.notify_one(),
+                |health| {
+                    if matches!(health, EventSocketHealth::Reconnecting { .. }) {
+                        changed.notify_one();
+                    }
+                    *health_clone.lock().unwrap() = health;
+                },
+            );
+            let refresh = async {
+                loop {
+                    changed.notified().await;
+                    refresh_tabs(&refresh_cdp, &tabs_clone, &status_clone).await;
+                    stale_clone.store(true, Ordering::Relaxed);
+                }
+            };
+            tokio::select! {
+                () = watch => {}
+                () = refresh => {}
+            }
+        });
+
+        // Counts trail tab events by up to COOKIE_COUNT_INTERVAL, so a burst
+        // of targetInfoChanged events costs one pass, and that pass only
+        // reads tabs that are new or have moved.
+        let count_cdp = cdp.clone();
+        let count_tabs = Arc::clone(&tabs);
+        let count_cache = Arc::clone(&cookie_cache);
+        let counts_clone = Arc::clone(&cookie_counts);
+        let stale_clone = Arc::clone(&counts_stale);
+        let stop_counting = Arc::clone(&stop_refresh);
+        rt_handle.spawn(async move {
+            let mut counted = HashMap::new();
+            while !stop_counting.load(Ordering::Relaxed) {
+                if stale_clone.swap(false, Ordering::Relaxed) {
+                    let tabs = count_tabs.lock().unwrap().clone();
+                    refresh_cookie_counts(
+                        &count_cdp,
+                        &tabs,
+                        &count_cache,
+                        &mut counted,
+                        &counts_clone,
+                    )
+                    .await;
+                }
+                tokio::time::sleep(COOKIE_COUNT_INTERVAL).await;
+            }
+        });
+
+        let discovered = Arc::new(Mutex::new(Vec::new()));
+        let found = Arc::clone(&discovered);
+        let stop_browsing = Arc::clone(&stop_refresh);
+        rt_handle.spawn(async move {
+            while !stop_browsing.load(Ordering::Relaxed) {
+                match crate::discovery::browse(DISCOVERY_WAIT).await {
+                    Ok(peers) => *found.lock().unwrap() = peers,
+                    Err(e) => debug!("mDNS browse failed: {}", e),
                 }
-                thread::sleep(Duration::from_secs(1));
+                tokio::time::sleep(DISCOVERY_INTERVAL).await;
             }
         });
 
@@ -53,25 +295,977 @@ impl ChromeTabApp {
         style.visuals.dark_mode = true;
         cc.egui_ctx.set_style(style);
 
-        Self {
+        let settings = Settings::load();
+        let open_in_background = settings.open_in_background;
+        let approve_grants = settings.approve_grants;
+        let hotkey_pressed = Arc::new(AtomicBool::new(false));
+        let (toast_tx, toast_rx) = mpsc::unbounded_channel();
+        let hotkey = options
+            .share_hotkey
+            .as_deref()
+            .or(settings.share_hotkey.as_deref())
+            .map(|binding| {
+                register_hotkey(binding, cc.egui_ctx.clone(), Arc::clone(&hotkey_pressed))
+            });
+        let mut app = Self {
+            cdp,
             tabs,
+            cookie_counts,
+            counts_stale,
+            cdp_status,
+            event_health,
             cookie_import: CookieImportState::default(),
-            grant_tx,
-            revoke_tx,
-            listen_addr: "0.0.0.0:9234".into(),
-            listening: false,
+            cookie_cache,
+            thumbnails: Thumbnails::default(),
+            export_template: options
+                .export_template
+                .or_else(|| settings.export_template.clone())
+                .unwrap_or_else(|| DEFAULT_EXPORT_TEMPLATE.to_string()),
+            include_storage: options.include_storage,
+            settings,
+            selected_index: 0,
+            selected_tabs: HashSet::new(),
+            focused_tab: None,
+            tab_sort: TabSort::default(),
+            revoke_acks: servers
+                .iter()
+                .map(|(_, server)| server.revoke_acks.subscribe())
+                .collect(),
+            servers,
+            active_server: 0,
+            mirrors: HashMap::new(),
+            extra_origins: String::new(),
+            share_domains: options.share_domains.join(", "),
+            tab_filter: String::new(),
+            browser_context: None,
+            listen_addr: options.connect_to.clone().unwrap_or_default(),
+            new_peer_name: String::new(),
+            request_url: String::new(),
+            listening: Arc::new(AtomicBool::new(false)),
+            receiver_status: Arc::new(Mutex::new(None)),
+            receiver: Arc::new(Mutex::new(ReceiverState {
+                open_in_background,
+                approve_grants,
+                secret: options.secret,
+                pin_cert: options.pin_cert,
+                auth_token: options.auth_token,
+                reconnect: options.peer_reconnect,
+                close_on_revoke: options.close_on_revoke,
+                ..ReceiverState::default()
+            })),
+            discovered,
+            share_draft: None,
+            share_confirm: None,
+            skip_share_confirm: false,
+            recent_shares: HashMap::new(),
+            share_marks: HashMap::new(),
+            pending_marks: HashMap::new(),
+            approval: None,
+            last_export: None,
+            hotkey_pressed,
+            hotkey: None,
+            toasts: Vec::new(),
+            toast_tx,
+            toast_rx,
+            seen_link: None,
+            seen_peers: HashSet::new(),
+            chrome: options.chrome,
+            stop_refresh,
+            jobs: Jobs::default(),
+            proxy: options.proxy,
             rt_handle,
-            remote_to_local: Arc::new(Mutex::new(HashMap::new())),
+            egui_ctx: cc.egui_ctx.clone(),
+        };
+        match hotkey {
+            Some(Ok(grab)) => app.hotkey = Some(grab),
+            Some(Err(e)) => {
+                warn!("Global hotkey unavailable: {}", e);
+                app.push_toast(
+                    ToastLevel::Error,
+                    &format!("Global hotkey unavailable: {}", e),
+                );
+            }
+            None => {}
+        }
+        if let Some(path) = options.received_path {
+            // `new` runs on the UI thread, outside the runtime.
+            let open_tabs = app.rt_handle.block_on(fetch_tabs(&app.cdp)).ok();
+            app.receiver
+                .lock()
+                .unwrap()
+                .persist_at(path, open_tabs.as_deref());
+        }
+        if options.connect_to.is_some() {
+            app.start_receiver();
+        }
+        app
+    }
+
+    // Re-read the tab list off the UI thread; `cdp_status` says how it went.
+    fn refresh_now(&self) {
+        let (cdp, tabs, status) = (
+            self.cdp.clone(),
+            Arc::clone(&self.tabs),
+            Arc::clone(&self.cdp_status),
+        );
+        let ctx = self.egui_ctx.clone();
+        self.rt_handle.spawn(async move {
+            refresh_tabs(&cdp, &tabs, &status).await;
+            ctx.request_repaint();
+        });
+    }
+
+    // ⟳ and Ctrl+R: reload the tabs and forget cached cookies and screenshots.
+    fn reload(&mut self) {
+        self.refresh_now();
+        self.cookie_cache.lock().unwrap().clear();
+        self.cookie_counts.lock().unwrap().clear();
+        self.counts_stale.store(true, Ordering::Relaxed);
+        self.thumbnails.clear();
+    }
+
+    // Shares, revokes and new mirrors go out on the server picked in the
+    // GUI; `main` always passes at least one.
+    fn server(&self) -> &Broadcasts {
+        &self.servers[self.active_server].1
+    }
+
+    // Receive from `listen_addr` alongside our own server. The connection
+    // task reports back through `receiver_status` when it ends, including
+    // when the address turns out to be this instance.
+    fn start_receiver(&mut self) {
+        let addr = self.listen_addr.trim().to_string();
+        if self.settings.last_peer.as_ref() != Some(&addr) {
+            self.settings.last_peer = Some(addr);
+            if let Err(e) = self.settings.save() {
+                warn!("Failed to save settings: {}", e);
+            }
+        }
+        let url = crate::network::peer_url(&self.listen_addr);
+        let proxy = self.proxy.clone();
+        let cdp = self.cdp.clone();
+        let state = Arc::clone(&self.receiver);
+        let listening = Arc::clone(&self.listening);
+        let status = Arc::clone(&self.receiver_status);
+        let toasts = self.toast_tx.clone();
+        listening.store(true, Ordering::Relaxed);
+        state.lock().unwrap().link = PeerLink::Connecting;
+        *status.lock().unwrap() = Some(format!("Receiving from {}", url));
+        self.rt_handle.spawn(async move {
+            let (level, message) =
+                match crate::network::connect_client(cdp, url.clone(), proxy, state).await {
+                    Ok(()) => (ToastLevel::Info, format!("Disconnected from {}", url)),
+                    Err(e) => (ToastLevel::Error, format!("Not receiving: {}", e)),
+                };
+            let _ = toasts.send(Toast::new(level, &message));
+            *status.lock().unwrap() = Some(message);
+            listening.store(false, Ordering::Relaxed);
+        });
+    }
+
+    // Saved peers with a connect button each, the last one used
+    // highlighted, and a field to save the address typed above.
+    fn peer_book(&mut self, ui: &mut egui::Ui) {
+        let listening = self.listening.load(Ordering::Relaxed);
+        let mut connect = None;
+        let mut remove = None;
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Peers:");
+            for (i, peer) in self.settings.peers.iter().enumerate() {
+                let last = self.settings.last_peer.as_deref() == Some(peer.addr.as_str());
+                if ui
+                    .add_enabled(!listening, egui::Button::selectable(last, &peer.name))
+                    .on_hover_text(format!("Listen on {}", peer.addr))
+                    .clicked()
+                {
+                    connect = Some(peer.addr.clone());
+                }
+                if ui
+                    .small_button("✕")
+                    .on_hover_text(format!("Forget {}", peer.name))
+                    .clicked()
+                {
+                    remove = Some(i);
+                }
+            }
+            for peer in self.discovered_peers() {
+                if ui
+                    .add_enabled(!listening, egui::Button::new(format!("📡 {}", peer.name)))
+                    .on_hover_text(format!("Listen on {} (found on the LAN)", peer.url()))
+                    .clicked()
+                {
+                    connect = Some(peer.url());
+                }
+            }
+            ui.add(
+                egui::TextEdit::singleline(&mut self.new_peer_name)
+                    .hint_text("Name")
+                    .desired_width(100.0),
+            );
+            let can_save =
+                !self.new_peer_name.trim().is_empty() && !self.listen_addr.trim().is_empty();
+            if ui
+                .add_enabled(can_save, egui::Button::new("Save peer"))
+                .on_hover_text("Remember the address above under this name")
+                .clicked()
+            {
+                self.settings
+                    .add_peer(&self.new_peer_name, &self.listen_addr);
+                self.new_peer_name.clear();
+                if let Err(e) = self.settings.save() {
+                    warn!("Failed to save settings: {}", e);
+                }
+            }
+        });
+        if let Some(i) = remove {
+            self.settings.peers.remove(i);
+            if let Err(e) = self.settings.save() {
+                warn!("Failed to save settings: {}", e);
+            }
+        }
+        if let Some(addr) = connect {
+            self.listen_addr = addr;
+            self.start_receiver();
+        }
+    }
+
+    // Peers found over mDNS that aren't saved already, and aren't one of
+    // this instance's own servers.
+    fn discovered_peers(&self) -> Vec<DiscoveredPeer> {
+        let own_name = crate::discovery::instance_name();
+        let own_ports: Vec<u16> = self.servers.iter().map(|(_, s)| s.addr().port()).collect();
+        self.discovered
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|p| !(p.name == own_name && own_ports.contains(&p.addr.port())))
+            .filter(|p| {
+                !self.settings.peers.iter().any(|saved| {
+                    crate::network::peer_url(&saved.addr) == p.url()
+                        || saved.addr.trim() == p.addr.to_string()
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    // Everything a background tab action needs, copied out of the app when
+    // it starts so the job can run off the UI thread.
+    fn action_context(&self) -> ActionContext {
+        ActionContext {
+            cdp: self.cdp.clone(),
+            cookie_cache: Arc::clone(&self.cookie_cache),
+            extra_origins: self.extra_origins.clone(),
+            share_domains: self.share_domains.clone(),
+            include_indexed_db: self.settings.include_indexed_db,
+            include_storage: self.include_storage,
+            encrypt_grants: self.settings.encrypt_grants,
+            export_dir: self
+                .cookie_import
+                .export_dir
+                .clone()
+                .unwrap_or_else(default_export_dir),
+            export_template: self.export_template.clone(),
+            export_format: self.settings.export_format,
+            annotate_expiry: self.settings.annotate_expiry,
+            server: self.server().clone(),
+        }
+    }
+
+    // Run `job` on the runtime; `finish_jobs` picks up its outcome. A key
+    // that is still running is not started again.
+    fn spawn_job(&mut self, key: &str, job: impl Future<Output = JobOutcome> + Send + 'static) {
+        if !self.jobs.busy.insert(key.to_string()) {
+            return;
+        }
+        let done = self.jobs.done_tx.clone();
+        let ctx = self.egui_ctx.clone();
+        let key = key.to_string();
+        self.rt_handle.spawn(async move {
+            let _ = done.send((key, job.await));
+            ctx.request_repaint();
+        });
+    }
+
+    fn open_imported(&mut self, path: PathBuf, url: String, copies: usize) {
+        let cdp = self.cdp.clone();
+        let background = self.settings.open_in_background;
+        self.spawn_job(IMPORT_JOB, async move {
+            let opened = match universal_cookie_loader(&path) {
+                Ok(cookies) => import_to_n(&cdp, &cookies, &url, copies, background).await,
+                Err(e) => Err(e),
+            };
+            match opened {
+                Ok((ids, report)) => JobOutcome::Status(
+                    ToastLevel::Success,
+                    format!("Opened {} tab(s); {}", ids.len(), report),
+                ),
+                Err(e) => JobOutcome::failed(format!("Error: {}", e)),
+            }
+        });
+    }
+
+    fn finish_jobs(&mut self) {
+        while let Ok((key, outcome)) = self.jobs.done_rx.try_recv() {
+            self.jobs.busy.remove(&key);
+            let mark = self.pending_marks.remove(&key);
+            let (level, status) = match outcome {
+                JobOutcome::Status(level, status) => (level, status),
+                JobOutcome::Exported(path) => {
+                    let status = format!("Cookies exported to {}", path.display());
+                    self.last_export = Some(path);
+                    self.push_toast(ToastLevel::Success, &status);
+                    self.cookie_import.last_status = Some(status);
+                    continue;
+                }
+                JobOutcome::Draft(draft) => {
+                    self.share_draft = Some(draft);
+                    continue;
+                }
+                JobOutcome::ActiveTab(tab) => {
+                    self.share_tab(tab, false);
+                    continue;
+                }
+                JobOutcome::Mirror(tab) => {
+                    self.start_mirror(tab);
+                    continue;
+                }
+                JobOutcome::Batch {
+                    level,
+                    status,
+                    all_done,
+                } => {
+                    if all_done {
+                        self.selected_tabs.clear();
+                    }
+                    (level, status)
+                }
+            };
+            if let Some(revoked) = mark
+                && level != ToastLevel::Error
+            {
+                self.record_mark(key, revoked);
+            }
+            self.push_toast(level, &status);
+            self.cookie_import.last_status = Some(status);
+        }
+    }
+
+    // A revoke only updates a tab that was shared; one never shared has
+    // nothing to say "revoked" about.
+    fn record_mark(&mut self, tab_id: String, revoked: bool) {
+        let mark = ShareMark {
+            at: Instant::now(),
+            revoked,
+        };
+        if revoked {
+            if let Some(existing) = self.share_marks.get_mut(&tab_id) {
+                *existing = mark;
+            }
+        } else {
+            self.share_marks.insert(tab_id, mark);
+        }
+    }
+
+    fn push_toast(&mut self, level: ToastLevel, text: &str) {
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+        self.toasts.push(Toast::new(level, text));
+    }
+
+    // Toast peers coming and going: those connected to our servers, and
+    // the link to the peer we receive from.
+    fn watch_peers(&mut self) {
+        while let Ok(toast) = self.toast_rx.try_recv() {
+            self.push_toast(toast.level, &toast.text);
+        }
+
+        let peers: HashSet<SocketAddr> = self
+            .servers
+            .iter()
+            .flat_map(|(_, server)| server.peers())
+            .collect();
+        let mut joined: Vec<_> = peers.difference(&self.seen_peers).copied().collect();
+        let mut left: Vec<_> = self.seen_peers.difference(&peers).copied().collect();
+        joined.sort();
+        left.sort();
+        for addr in joined {
+            self.push_toast(ToastLevel::Success, &format!("Peer {} connected", addr));
+        }
+        for addr in left {
+            self.push_toast(ToastLevel::Info, &format!("Peer {} disconnected", addr));
+        }
+        self.seen_peers = peers;
+
+        let link = self
+            .listening
+            .load(Ordering::Relaxed)
+            .then(|| self.receiver.lock().unwrap().link.clone());
+        if link == self.seen_link {
+            return;
+        }
+        let addr = self.listen_addr.trim().to_string();
+        match (&self.seen_link, &link) {
+            (_, Some(PeerLink::Connected)) => {
+                self.push_toast(ToastLevel::Success, &format!("Connected to {}", addr))
+            }
+            (Some(PeerLink::Connected), Some(PeerLink::Reconnecting { error, .. })) => self
+                .push_toast(
+                    ToastLevel::Error,
+                    &format!("Lost {} ({}); reconnecting", addr, error),
+                ),
+            _ => {}
+        }
+        self.seen_link = link;
+    }
+
+    fn show_revoke_acks(&mut self) {
+        let mut statuses = Vec::new();
+        for acks in &mut self.revoke_acks {
+            while let Ok(ack) = acks.try_recv() {
+                let tabs = self.tabs.lock().unwrap();
+                let tab = tabs
+                    .iter()
+                    .find(|t| t.id == ack.tab_id)
+                    .map_or(ack.tab_id.as_str(), |t| t.title.as_str());
+                statuses.push(format!(
+                    "Peer deleted {} cookie(s) from “{}”",
+                    ack.deleted, tab
+                ));
+            }
+        }
+        for status in statuses {
+            self.push_toast(ToastLevel::Info, &status);
+            self.cookie_import.last_status = Some(status);
+        }
+    }
+
+    // Revoke every open tab's cookies on all peers in a single message.
+    fn revoke_all(&mut self) {
+        let context = self.action_context();
+        self.spawn_job(REVOKE_ALL_JOB, async move {
+            let (level, status) = context.revoke_all().await;
+            JobOutcome::Status(level, status)
+        });
+    }
+
+    // Share or revoke every selected tab, one message per tab. Shares are
+    // confirmed first, like a single card's.
+    fn run_on_selected(&mut self, action: BatchAction) {
+        let tabs: Vec<ChromeTab> = self
+            .tabs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|t| self.selected_tabs.contains(&t.id))
+            .cloned()
+            .collect();
+        match action {
+            BatchAction::Share => self.confirm_share(HeldShare::Selected(tabs)),
+            BatchAction::Revoke => self.run_batch(action, tabs),
+        }
+    }
+
+    fn run_batch(&mut self, action: BatchAction, tabs: Vec<ChromeTab>) {
+        let ids: Vec<String> = tabs.into_iter().map(|t| t.id).collect();
+        let context = self.action_context();
+        self.spawn_job(SELECTION_JOB, async move {
+            context.run_batch(action, &ids).await
+        });
+    }
+
+    // Hold `share` in the confirmation dialog, or send it straight away once
+    // "Don't ask again" has been ticked.
+    fn confirm_share(&mut self, share: HeldShare) {
+        if self.skip_share_confirm {
+            self.dispatch_share(share);
+            return;
+        }
+        let cookies = match &share {
+            HeldShare::Tab { tab, .. } => self.cookie_counts.lock().unwrap().get(&tab.id).copied(),
+            HeldShare::Edited(draft) => Some(draft.cookies.len()),
+            HeldShare::Selected(tabs) => {
+                let counts = self.cookie_counts.lock().unwrap();
+                tabs.iter()
+                    .map(|t| counts.get(&t.id))
+                    .sum::<Option<usize>>()
+            }
+        };
+        self.share_confirm = Some(ShareConfirm {
+            share,
+            cookies,
+            peers: self.server().grants.receiver_count(),
+            dont_ask_again: false,
+        });
+    }
+
+    fn dispatch_share(&mut self, share: HeldShare) {
+        match share {
+            HeldShare::Tab { tab, in_current } => {
+                let action = if in_current {
+                    TabAction::ShareInCurrent
+                } else {
+                    TabAction::Share
+                };
+                self.spawn_tab_action(action, &tab.id);
+            }
+            HeldShare::Edited(draft) => self.send_grant(draft.tab, draft.cookies),
+            HeldShare::Selected(tabs) => self.run_batch(BatchAction::Share, tabs),
+        }
+    }
+
+    fn send_grant(&mut self, tab: ChromeTab, cookies: Vec<Cookie>) {
+        if self.recently_shared(&tab.id) {
+            return;
+        }
+        self.recent_shares.insert(tab.id.clone(), Instant::now());
+        if !self.jobs.busy.contains(&tab.id) {
+            self.pending_marks.insert(tab.id.clone(), false);
+        }
+        let context = self.action_context();
+        let key = tab.id.clone();
+        self.spawn_job(&key, async move {
+            let (level, status) = context.send_grant(&tab, cookies, false).await;
+            JobOutcome::Status(level, status)
+        });
+    }
+
+    // Triggered by the global hotkey, usually while Chrome has focus, so the
+    // result is only seen in the toast. Finding the tab takes a CDP call; the
+    // share itself then goes through `share_tab` like a click on its card.
+    fn share_active_tab(&mut self) {
+        let cdp = self.cdp.clone();
+        self.spawn_job(ACTIVE_TAB_JOB, async move {
+            match get_active_tab(&cdp).await {
+                Ok(tab) => JobOutcome::ActiveTab(tab),
+                Err(e) => JobOutcome::failed(format!("Not shared: {}", e)),
+            }
+        });
+    }
+
+    // Share `tab` unless it only just went out, asking first unless "Don't
+    // ask again" was ticked.
+    fn share_tab(&mut self, tab: ChromeTab, in_current: bool) {
+        if self.recently_shared(&tab.id) {
+            return;
+        }
+        self.confirm_share(HeldShare::Tab { tab, in_current });
+    }
+
+    fn run_tab_action(&mut self, action: TabAction, tab_id: &str) {
+        if let TabAction::ToggleMirror = action
+            && let Some(task) = self.mirrors.remove(tab_id)
+        {
+            task.abort();
+            self.cookie_import.last_status = Some(format!("Stopped mirroring {}", tab_id));
+            return;
+        }
+        if let TabAction::Export = action
+            && self.cookie_import.export_dir.is_none()
+            && !self.pick_export_dir()
+        {
+            return;
+        }
+        let share = matches!(action, TabAction::Share | TabAction::ShareInCurrent);
+        if share && self.recently_shared(tab_id) {
+            return;
+        }
+        if share {
+            let tab = self
+                .tabs
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|t| t.id == tab_id)
+                .cloned();
+            if let Some(tab) = tab {
+                self.share_tab(tab, matches!(action, TabAction::ShareInCurrent));
+                return;
+            }
+        }
+        self.spawn_tab_action(action, tab_id);
+    }
+
+    // Ask where exports should go; false if the dialog was cancelled.
+    fn pick_export_dir(&mut self) -> bool {
+        let start = self
+            .cookie_import
+            .export_dir
+            .clone()
+            .unwrap_or_else(default_export_dir);
+        match FileDialog::new()
+            .set_title("Export cookies to")
+            .set_directory(start)
+            .pick_folder()
+        {
+            Some(dir) => {
+                self.cookie_import.export_dir = Some(dir);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn spawn_tab_action(&mut self, action: TabAction, tab_id: &str) {
+        if let TabAction::Share | TabAction::ShareInCurrent = action {
+            if self.recently_shared(tab_id) {
+                return;
+            }
+            self.recent_shares
+                .insert(tab_id.to_string(), Instant::now());
+        }
+        let revoked = match action {
+            TabAction::Share | TabAction::ShareInCurrent => Some(false),
+            TabAction::Revoke | TabAction::RevokeSite => Some(true),
+            _ => None,
+        };
+        if let Some(revoked) = revoked
+            && !self.jobs.busy.contains(tab_id)
+        {
+            self.pending_marks.insert(tab_id.to_string(), revoked);
+        }
+        let context = self.action_context();
+        let id = tab_id.to_string();
+        self.spawn_job(tab_id, async move { context.run(action, &id).await });
+    }
+
+    // Whether `tab_id` went out less than `SHARE_DEBOUNCE` ago, in which case
+    // the repeat is dropped with a toast rather than opening a second tab on
+    // every peer.
+    fn recently_shared(&mut self, tab_id: &str) -> bool {
+        self.recent_shares
+            .retain(|_, at| at.elapsed() < SHARE_DEBOUNCE);
+        if !self.recent_shares.contains_key(tab_id) {
+            return false;
+        }
+        let title = self
+            .tabs
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|t| t.id == tab_id)
+            .map_or(tab_id.to_string(), |t| t.title.clone());
+        self.push_toast(ToastLevel::Info, &format!("Already shared “{}”", title));
+        true
+    }
+
+    // Started from the job that re-resolved `tab`; stopping needs no CDP and
+    // happens in `run_tab_action`.
+    fn start_mirror(&mut self, tab: ChromeTab) {
+        let task = self.rt_handle.spawn(crate::network::mirror_tab(
+            self.cdp.clone(),
+            tab.clone(),
+            self.server().navigations.clone(),
+            self.settings.mirror_cookies,
+            parse_domain_patterns(&self.share_domains),
+            self.settings.encrypt_grants,
+        ));
+        if let Some(earlier) = self.mirrors.insert(tab.id.clone(), task) {
+            earlier.abort();
+        }
+        self.cookie_import.last_status = Some(format!(
+            "Mirroring {}; peers that received it will follow its navigations",
+            tab.title
+        ));
+    }
+}
+
+/// Job keys for actions that aren't about a single tab; per-tab jobs are
+/// keyed by tab id.
+const REVOKE_ALL_JOB: &str = "revoke-all";
+const SELECTION_JOB: &str = "selection";
+const ACTIVE_TAB_JOB: &str = "active-tab";
+const IMPORT_JOB: &str = "import";
+const NEW_TAB_JOB: &str = "new-tab";
+
+/// What a background job hands back to `update`.
+enum JobOutcome {
+    Status(ToastLevel, String),
+    Exported(PathBuf),
+    Draft(ShareDraft),
+    /// The tab the hotkey found in front, to be shared from the UI thread.
+    ActiveTab(ChromeTab),
+    /// A tab to start mirroring; the task is kept on the UI thread.
+    Mirror(ChromeTab),
+    /// A batch over the selection, which is cleared if all of it went out.
+    /// Anything less is an error, even if most of it was delivered.
+    Batch {
+        level: ToastLevel,
+        status: String,
+        all_done: bool,
+    },
+}
+
+impl JobOutcome {
+    fn failed(status: String) -> Self {
+        JobOutcome::Status(ToastLevel::Error, status)
+    }
+}
+
+/// Actions running as tasks on the runtime so slow CDP calls don't
+/// stall the UI. `busy` holds the keys still in flight.
+struct Jobs {
+    busy: HashSet<String>,
+    done_tx: mpsc::UnboundedSender<(String, JobOutcome)>,
+    done_rx: mpsc::UnboundedReceiver<(String, JobOutcome)>,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        let (done_tx, done_rx) = mpsc::unbounded_channel();
+        Self {
+            busy: HashSet::new(),
+            done_tx,
+            done_rx,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ActionContext {
+    cdp: CdpEndpoint,
+    cookie_cache: Arc<Mutex<CookieCache>>,
+    extra_origins: String,
+    share_domains: String,
+    include_indexed_db: bool,
+    include_storage: bool,
+    encrypt_grants: bool,
+    export_dir: PathBuf,
+    export_template: String,
+    export_format: CookieFormat,
+    annotate_expiry: bool,
+    server: Broadcasts,
+}
+
+impl ActionContext {
+    // The card grid renders from a snapshot, so re-resolve the tab by id
+    // before touching CDP; it may have been closed since the last refresh.
+    async fn run(&self, action: TabAction, tab_id: &str) -> JobOutcome {
+        let tab = match resolve_tab(&self.cdp, tab_id).await {
+            Ok(tab) => tab,
+            Err(e) => return JobOutcome::failed(format!("Error: {}", e)),
+        };
+        match action {
+            TabAction::Export => match export_cookies_for_tab(
+                &self.cdp,
+                &tab,
+                &self.export_dir,
+                &self.export_template,
+                self.export_format,
+                self.annotate_expiry,
+            )
+            .await
+            {
+                Ok(path) => JobOutcome::Exported(path),
+                Err(e) => JobOutcome::failed(format!("Failed to export cookies: {}", e)),
+            },
+            TabAction::Share | TabAction::ShareInCurrent | TabAction::EditShare => {
+                if let Err(e) = normalize_url(&tab.url) {
+                    return JobOutcome::failed(format!("Not shared: {}", e));
+                }
+                let cookies = match self.capture_cookies(&tab).await {
+                    Ok(cookies) => cookies,
+                    Err(e) => return JobOutcome::failed(format!("Not shared: {}", e)),
+                };
+                if matches!(action, TabAction::EditShare) {
+                    JobOutcome::Draft(ShareDraft { tab, cookies })
+                } else {
+                    let in_current = matches!(action, TabAction::ShareInCurrent);
+                    let (level, status) = self.send_grant(&tab, cookies, in_current).await;
+                    JobOutcome::Status(level, status)
+                }
+            }
+            TabAction::Revoke | TabAction::RevokeSite => match self.revoke_message(&tab).await {
+                Ok(mut revoke) => {
+                    self.cookie_cache.lock().unwrap().invalidate(&tab.id);
+                    revoke.whole_site = matches!(action, TabAction::RevokeSite);
+                    let what = if revoke.whole_site {
+                        "site-wide revoke"
+                    } else {
+                        "revoke"
+                    };
+                    let (level, status) = broadcast_status(&self.server.revokes, revoke, what);
+                    JobOutcome::Status(level, status)
+                }
+                Err(e) => JobOutcome::failed(format!("Not revoked: {}", e)),
+            },
+            TabAction::ToggleMirror => JobOutcome::Mirror(tab),
+        }
+    }
+
+    // Cookies for a share or revoke: the tab's own, plus those for any extra
+    // origins entered in the GUI, narrowed to the domain allowlist. The cache
+    // only covers the tab's own URL.
+    async fn capture_cookies(&self, tab: &ChromeTab) -> Result<Vec<Cookie>, String> {
+        let extra_urls = parse_origins(&self.extra_origins)?;
+        let cookies = if extra_urls.is_empty() {
+            cached_cookies(&self.cookie_cache, &self.cdp, tab).await
+        } else {
+            get_cookies_for_urls(&self.cdp, tab, &extra_urls).await
+        };
+        let cookies = cookies.map_err(|e| e.to_string())?;
+        Ok(filter_cookies(
+            cookies,
+            &parse_domain_patterns(&self.share_domains),
+        ))
+    }
+
+    async fn revoke_message(&self, tab: &ChromeTab) -> Result<RevokeMessage, String> {
+        Ok(revoke_for_tab(tab, &self.capture_cookies(tab).await?))
+    }
+
+    async fn send_grant(
+        &self,
+        tab: &ChromeTab,
+        cookies: Vec<Cookie>,
+        open_in_current: bool,
+    ) -> (ToastLevel, String) {
+        let grant = GrantMessage {
+            open_in_current,
+            ..grant_for_tab(
+                &ChromeBrowser::new(self.cdp.clone()),
+                tab,
+                cookies,
+                self.include_indexed_db,
+                self.include_storage,
+                self.encrypt_grants,
+            )
+            .await
+        };
+        broadcast_status(&self.server.grants, grant, "grant")
+    }
+
+    async fn revoke_all(&self) -> (ToastLevel, String) {
+        let tabs = match fetch_tabs(&self.cdp).await {
+            Ok(tabs) => tabs,
+            Err(e) => return (ToastLevel::Error, format!("Error: {}", e)),
+        };
+        let mut batch = Vec::new();
+        for tab in &tabs {
+            match self.revoke_message(tab).await {
+                Ok(revoke) if revoke.cookies.is_empty() => {}
+                Ok(revoke) => batch.push(revoke),
+                Err(e) => return (ToastLevel::Error, format!("Not revoked: {}", e)),
+            }
+            self.cookie_cache.lock().unwrap().invalidate(&tab.id);
+        }
+        let what = format!("revoke for {} tab(s)", batch.len());
+        broadcast_status(
+            &self.server.revoke_batches,
+            RevokeBatchMessage { tabs: batch },
+            &what,
+        )
+    }
+
+    async fn run_batch(&self, action: BatchAction, ids: &[String]) -> JobOutcome {
+        let mut failures = Vec::new();
+        for id in ids {
+            if let Err(e) = self.deliver(action, id).await {
+                failures.push(e);
+            }
+        }
+        let verb = match action {
+            BatchAction::Share => "Shared",
+            BatchAction::Revoke => "Revoked",
+        };
+        let status = if failures.is_empty() {
+            format!("{} {} tab(s)", verb, ids.len())
+        } else {
+            format!(
+                "{} {} of {} tab(s); {}",
+                verb,
+                ids.len() - failures.len(),
+                ids.len(),
+                failures.join("; ")
+            )
+        };
+        let (level, all_done) = if failures.is_empty() {
+            (ToastLevel::Success, true)
+        } else {
+            (ToastLevel::Error, false)
+        };
+        JobOutcome::Batch {
+            level,
+            status,
+            all_done,
+        }
+    }
+
+    async fn deliver(&self, action: BatchAction, tab_id: &str) -> Result<(), String> {
+        let tab = resolve_tab(&self.cdp, tab_id)
+            .await
+            .map_err(|e| format!("{}: {}", tab_id, e))?;
+        let failed = |e: String| format!("“{}”: {}", tab.title, e);
+        match action {
+            BatchAction::Share => {
+                normalize_url(&tab.url).map_err(failed)?;
+                let cookies = self.capture_cookies(&tab).await.map_err(failed)?;
+                let grant = grant_for_tab(
+                    &ChromeBrowser::new(self.cdp.clone()),
+                    &tab,
+                    cookies,
+                    self.include_indexed_db,
+                    self.include_storage,
+                    self.encrypt_grants,
+                )
+                .await;
+                self.server
+                    .grants
+                    .send(grant)
+                    .map_err(|_| failed("no peers connected".into()))?;
+            }
+            BatchAction::Revoke => {
+                let revoke = self.revoke_message(&tab).await.map_err(failed)?;
+                self.cookie_cache.lock().unwrap().invalidate(&tab.id);
+                self.server
+                    .revokes
+                    .send(revoke)
+                    .map_err(|_| failed("no peers connected".into()))?;
+            }
         }
+        Ok(())
     }
 }
 
 impl App for ChromeTabApp {
+    // Runs for the ✖ button and the window manager's close alike, both of
+    // which end in ViewportCommand::Close.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.stop_refresh.store(true, Ordering::Relaxed);
+        if let Some(chrome) = self.chrome.lock().unwrap().take() {
+            chrome.stop();
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.hotkey_pressed.swap(false, Ordering::Relaxed) {
+            self.share_active_tab();
+        }
+        // Taken before any widget sees them, so Ctrl+R doesn't also revoke
+        // in the list view.
+        let (reload, share_focused) = ctx.input_mut(|i| {
+            (
+                i.consume_key(Modifiers::COMMAND, Key::R),
+                i.consume_key(Modifiers::COMMAND, Key::Enter),
+            )
+        });
+        if reload {
+            self.reload();
+        }
+        if share_focused
+            && let Some(id) = self.focused_tab.clone()
+            && !self.jobs.busy.contains(&id)
+        {
+            self.run_tab_action(TabAction::Share, &id);
+        }
+        self.show_revoke_acks();
+        self.finish_jobs();
+        self.watch_peers();
         TopBottomPanel::top("titlebar")
             .exact_height(32.0)
             .frame(
-                Frame::none()
+                Frame::NONE
                     .fill(Color32::from_gray(20))
                     .inner_margin(Margin::same(4))
                     .outer_margin(Margin {
@@ -87,13 +1281,87 @@ impl App for ChromeTabApp {
                     ui.heading(RichText::new("ShareKaro").size(16.0));
                     ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
                         if ui.small_button("✖").clicked() {
-                            std::process::exit(0);
+                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                        }
+                        let toggle = match self.settings.view_mode {
+                            ViewMode::Grid => "☰",
+                            ViewMode::List => "▦",
+                        };
+                        if ui.small_button(toggle).clicked() {
+                            self.settings.view_mode = match self.settings.view_mode {
+                                ViewMode::Grid => ViewMode::List,
+                                ViewMode::List => ViewMode::Grid,
+                            };
+                            if let Err(e) = self.settings.save() {
+                                warn!("Failed to save settings: {}", e);
+                            }
                         }
-                        if ui.small_button("⟳").clicked() {
-                            if let Ok(new_tabs) = fetch_tabs() {
-                                *self.tabs.lock().unwrap() = new_tabs;
+                        if ui
+                            .small_button("⟳")
+                            .on_hover_text("Refresh (Ctrl+R)")
+                            .clicked()
+                        {
+                            self.reload();
+                        }
+                        if ui
+                            .selectable_label(self.settings.show_thumbnails, "🖼")
+                            .on_hover_text("Show a screenshot of each tab; ⟳ retakes them")
+                            .clicked()
+                        {
+                            self.settings.show_thumbnails = !self.settings.show_thumbnails;
+                            if let Err(e) = self.settings.save() {
+                                warn!("Failed to save settings: {}", e);
                             }
                         }
+                        egui::ComboBox::from_id_salt("tab_sort")
+                            .selected_text(self.tab_sort.label())
+                            .show_ui(ui, |ui| {
+                                for sort in TabSort::ALL {
+                                    ui.selectable_value(&mut self.tab_sort, sort, sort.label());
+                                }
+                            });
+                        let health = self.event_health.lock().unwrap().clone();
+                        let (color, label, detail) = match &health {
+                            EventSocketHealth::Connecting => (
+                                Color32::GRAY,
+                                "connecting",
+                                "Connecting to Chrome's event socket".to_string(),
+                            ),
+                            EventSocketHealth::Live => (
+                                Color32::from_rgb(90, 200, 120),
+                                "live",
+                                "Tab updates are arriving from Chrome".to_string(),
+                            ),
+                            EventSocketHealth::Reconnecting { attempt, error } => (
+                                Color32::from_rgb(220, 180, 90),
+                                "reconnecting",
+                                format!("Event socket down ({}); retry #{}", error, attempt),
+                            ),
+                        };
+                        ui.label(RichText::new(format!("● {}", label)).small().color(color))
+                            .on_hover_text(detail);
+                        let peers = self.server().peers();
+                        let (color, detail) = if peers.is_empty() {
+                            (Color32::GRAY, "No peers connected".to_string())
+                        } else {
+                            let addrs: Vec<String> = peers.iter().map(|p| p.to_string()).collect();
+                            (Color32::from_rgb(90, 200, 120), addrs.join("\n"))
+                        };
+                        let noun = if peers.len() == 1 { "peer" } else { "peers" };
+                        ui.label(
+                            RichText::new(format!("● {} {}", peers.len(), noun))
+                                .small()
+                                .color(color),
+                        )
+                        .on_hover_text(detail);
+                        let (name, server) = &self.servers[self.active_server];
+                        ui.label(RichText::new(server.addr().to_string()).small().weak())
+                            .on_hover_text(format!("Server “{}” is listening here", name));
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.tab_filter)
+                                .hint_text("Filter tabs")
+                                .desired_width(180.0),
+                        );
                     });
                 });
             });
@@ -101,99 +1369,399 @@ impl App for ChromeTabApp {
         CentralPanel::default().show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.label("Peer to listen on:");
-                ui.text_edit_singleline(&mut self.listen_addr);
-                let button_label = if self.listening { "Listening…" } else { "Listen" };
-                if ui.add_enabled(!self.listening, egui::Button::new(button_label)).clicked() {
-                    if let Ok(addr) = self.listen_addr.parse::<SocketAddr>() {
-                        let remote_map = Arc::clone(&self.remote_to_local);
-                        thread::spawn(move || {
-                            let rt = tokio::runtime::Builder::new_current_thread()
-                                .enable_all()
-                                .build()
-                                .expect("runtime creation failed");
-                            rt.block_on(async move {
-                                crate::network::connect_client(addr, remote_map).await;
-                            });
-                        });
-                        self.listening = true;
+                ui.add(egui::TextEdit::singleline(&mut self.listen_addr).hint_text("host:9234"));
+                let listening = self.listening.load(Ordering::Relaxed);
+                let button_label = if listening { "Listening…" } else { "Listen" };
+                if ui
+                    .add_enabled(!listening, egui::Button::new(button_label))
+                    .clicked()
+                    && !self.listen_addr.trim().is_empty()
+                {
+                    self.start_receiver();
+                }
+                if ui
+                    .checkbox(&mut self.settings.include_indexed_db, "Include IndexedDB")
+                    .on_hover_text(
+                        "Also send the tab's IndexedDB stores with each share. Can be large.",
+                    )
+                    .changed()
+                    && let Err(e) = self.settings.save()
+                {
+                    warn!("Failed to save settings: {}", e);
+                }
+                if ui
+                    .checkbox(&mut self.settings.mirror_cookies, "Mirror cookies")
+                    .on_hover_text(
+                        "While mirroring a tab, also re-send its cookies when they change.",
+                    )
+                    .changed()
+                    && let Err(e) = self.settings.save()
+                {
+                    warn!("Failed to save settings: {}", e);
+                }
+                if ui
+                    .checkbox(&mut self.settings.open_in_background, "Open in background")
+                    .on_hover_text(
+                        "Open imported and received tabs behind the one you're using \
+                         instead of switching to them.",
+                    )
+                    .changed()
+                {
+                    self.receiver.lock().unwrap().open_in_background =
+                        self.settings.open_in_background;
+                    if let Err(e) = self.settings.save() {
+                        warn!("Failed to save settings: {}", e);
                     }
                 }
-            });
-
-            ui.separator();
-            let tabs = self.tabs.lock().unwrap();
-            if tabs.is_empty() {
-                ui.add_space(40.0);
-                ui.add(
-                    Label::new(
-                        RichText::new(
-                            "No tabs found.\nEnsure Chrome is running with --remote-debugging-port=9222."
+                if ui
+                    .checkbox(&mut self.settings.approve_grants, "Ask before importing")
+                    .on_hover_text(
+                        "Hold each received grant until you've reviewed its cookies and \
+                         picked which to import.",
+                    )
+                    .changed()
+                {
+                    self.receiver.lock().unwrap().approve_grants = self.settings.approve_grants;
+                    if let Err(e) = self.settings.save() {
+                        warn!("Failed to save settings: {}", e);
+                    }
+                }
+                let format = self.settings.export_format;
+                egui::ComboBox::from_id_salt("export_format")
+                    .selected_text(match format {
+                        CookieFormat::Json => "Export JSON",
+                        CookieFormat::Netscape => "Export cookies.txt",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut self.settings.export_format,
+                            CookieFormat::Json,
+                            "Export JSON",
+                        );
+                        ui.selectable_value(
+                            &mut self.settings.export_format,
+                            CookieFormat::Netscape,
+                            "Export cookies.txt",
                         )
-                        .italics()
-                        .color(Color32::from_rgb(200, 100, 100)),
+                        .on_hover_text("Netscape format, for curl, wget and yt-dlp");
+                    });
+                if self.settings.export_format != format
+                    && let Err(e) = self.settings.save()
+                {
+                    warn!("Failed to save settings: {}", e);
+                }
+                let export_dir = match &self.cookie_import.export_dir {
+                    Some(dir) => format!("Exporting to {}", dir.display()),
+                    None => "Choose where exports go; asked on the first export".to_string(),
+                };
+                if ui.button("📁").on_hover_text(export_dir).clicked() {
+                    self.pick_export_dir();
+                }
+                if ui
+                    .checkbox(&mut self.settings.annotate_expiry, "Readable expiry")
+                    .on_hover_text(
+                        "Add a human-readable date next to each cookie's expires in exports. \
+                         Import ignores it.",
                     )
-                    .wrap(),
+                    .changed()
+                    && let Err(e) = self.settings.save()
+                {
+                    warn!("Failed to save settings: {}", e);
+                }
+                if ui
+                    .checkbox(&mut self.settings.encrypt_grants, "Encrypt grants")
+                    .on_hover_text(
+                        "Seal each grant with a per-connection key so cookie values never \
+                         cross the wire or land in logs as plaintext.",
+                    )
+                    .changed()
+                    && let Err(e) = self.settings.save()
+                {
+                    warn!("Failed to save settings: {}", e);
+                }
+            });
+
+            self.peer_book(ui);
+
+            let link = self.receiver.lock().unwrap().link.clone();
+            if let (true, PeerLink::Reconnecting { attempt, error }) =
+                (self.listening.load(Ordering::Relaxed), link)
+            {
+                ui.label(
+                    RichText::new(format!("Reconnecting (attempt {}): {}", attempt, error))
+                        .small()
+                        .color(Color32::from_rgb(220, 180, 90)),
                 );
-            } else {
-                let card_width = 260.0;
-                let cols = (ui.available_width() / (card_width + 16.0)).floor().max(1.0) as usize;
-                ScrollArea::vertical().show(ui, |ui| {
-                    ui.spacing_mut().item_spacing = Vec2::splat(16.0);
-                    ui.columns(cols, |columns| {
-                        for (i, tab) in tabs.iter().enumerate() {
-                            let col_ui = &mut columns[i % cols];
-                            let (rect, resp) = col_ui.allocate_exact_size(
-                                Vec2::new(card_width, 80.0),
-                                Sense::click(),
-                            );
-                            let bg = if resp.hovered() { Color32::from_gray(50) } else { Color32::from_gray(40) };
-                            col_ui.painter().rect_filled(rect, Rounding::same(8), bg);
-                            col_ui.allocate_ui_at_rect(rect.shrink(8.0), |ui| {
-                                ui.horizontal(|ui| {
-                                    ui.label(RichText::new(format!("{}.", i + 1)).strong());
-                                    ui.label(
-                                        RichText::new(&tab.title)
-                                            .font(FontId::proportional(16.0))
-                                            .strong(),
-                                    );
-                                    if ui.small_button("Share").clicked() {
-                                        let cookies = get_cookies_for_tab(tab).unwrap_or_default();
-                                        let grant = GrantMessage {
-                                            tab_id: tab.id.clone(),
-                                            url: tab.url.clone(),
-                                            cookies,
-                                        };
-                                        let _ = self.grant_tx.send(grant);
-                                    }
-                                    if ui.small_button("Revoke").clicked() {
-                                        let cookies: Vec<RevokeCookie> =
-                                            get_cookies_for_tab(tab)
-                                                .unwrap_or_default()
-                                                .into_iter()
-                                                .map(|c| RevokeCookie { name: c.name, domain: c.domain, path: c.path })
-                                                .collect();
-                                        let revoke = RevokeMessage { tab_id: tab.id.clone(), cookies };
-                                        let _ = self.revoke_tx.send(revoke);
-                                    }
-                                });
-                                ui.add_space(2.0);
-                                ui.label(RichText::new(clip(&tab.url, 45)).monospace());
-                            });
-                            if resp.clicked() {
-                                match export_cookies_for_tab(tab) {
-                                    Ok(path) => self.cookie_import.last_status = Some(format!("Cookies exported to {}", path)),
-                                    Err(e) => self.cookie_import.last_status = Some(format!("Failed to export cookies: {}", e)),
-                                }
-                            }
-                        }
-                    });
+            } else if let Some(status) = self.receiver_status.lock().unwrap().as_deref() {
+                ui.label(RichText::new(status).small().weak());
+            }
+            if self.listening.load(Ordering::Relaxed) {
+                ui.horizontal(|ui| {
+                    ui.label("Ask the peer for:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.request_url)
+                            .hint_text("https://app.example.com/"),
+                    );
+                    if ui
+                        .button("Request tab")
+                        .on_hover_text(
+                            "Have the peer send its tab on this URL, if it allows pulling tabs",
+                        )
+                        .clicked()
+                    {
+                        let requested =
+                            self.receiver.lock().unwrap().request_tab(&self.request_url);
+                        self.cookie_import.last_status = Some(match requested {
+                            Ok(()) => format!("Requested {}", self.request_url.trim()),
+                            Err(e) => format!("Not requested: {}", e),
+                        });
+                    }
                 });
+                if let Some(refused) = &self.receiver.lock().unwrap().refused {
+                    ui.label(
+                        RichText::new(refused)
+                            .small()
+                            .color(Color32::from_rgb(220, 180, 90)),
+                    );
+                }
             }
-
-            ui.add_space(18.0);
+            ui.horizontal(|ui| {
+                ui.label("Also share cookies for:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.extra_origins)
+                        .hint_text("login.example.com, https://sso.example.org"),
+                )
+                .on_hover_text(
+                    "Extra origins whose cookies go out with every share and revoke, \
+                     e.g. the identity provider behind an SSO login.",
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Only cookies from:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.share_domains)
+                        .hint_text("every domain; e.g. *.openai.com, chatgpt.com"),
+                )
+                .on_hover_text(
+                    "Domain globs a cookie must match to be shared or revoked, e.g. to \
+                     leave out third-party trackers. Empty shares everything.",
+                );
+            });
+
+            ui.separator();
+            // Mirror tasks end on their own when the tab closes.
+            self.mirrors.retain(|_, task| !task.is_finished());
+            let mut pending_action = None;
+            let mut revoke_all = false;
+            let mut batch_action = None;
+            let status = self.cdp_status.lock().unwrap().clone();
+            let all_tabs = self.tabs.lock().unwrap();
+            let no_tabs = all_tabs.is_empty();
+            let contexts = browser_contexts(&all_tabs);
+            if let Some(context) = &self.browser_context
+                && !contexts.contains(context)
+            {
+                self.browser_context = None;
+            }
+            let mut tabs = filter_tabs(&all_tabs, &self.tab_filter);
+            if let Some(context) = &self.browser_context {
+                tabs.retain(|t| t.browserContextId.as_ref() == Some(context));
+            }
+            sort_tabs(&mut tabs, self.tab_sort);
+            self.thumbnails.forget_closed(&all_tabs);
+            self.selected_tabs
+                .retain(|id| all_tabs.iter().any(|t| &t.id == id));
+            if let Some(id) = &self.focused_tab
+                && !tabs.iter().any(|t| &t.id == id)
+            {
+                self.focused_tab = None;
+            }
+            drop(all_tabs);
+            if no_tabs {
+                ui.add_space(40.0);
+                match status {
+                    CdpStatus::Connecting => empty_state(
+                        ui,
+                        "Connecting to Chrome…",
+                        &format!(
+                            "Waiting for the DevTools endpoint at {}.",
+                            self.cdp.http_url("")
+                        ),
+                    ),
+                    CdpStatus::Unreachable(err) => {
+                        empty_state(
+                            ui,
+                            &format!("Chrome CDP unreachable on port {}", self.cdp.port),
+                            &format!(
+                                "{}: {}\nStart Chrome with --remote-debugging-port={}, or point \
+                                 --cdp-host/--cdp-port at a running instance.",
+                                self.cdp.http_url(""),
+                                err,
+                                self.cdp.port
+                            ),
+                        );
+                        if ui.button("Retry").clicked() {
+                            *self.cdp_status.lock().unwrap() = CdpStatus::Connecting;
+                            self.refresh_now();
+                        }
+                    }
+                    CdpStatus::Connected => {
+                        empty_state(
+                            ui,
+                            "Chrome has no open pages",
+                            "Open a tab in Chrome and it will show up here to share.",
+                        );
+                        if ui
+                            .add_enabled(
+                                !self.jobs.busy.contains(NEW_TAB_JOB),
+                                egui::Button::new("Open a new tab"),
+                            )
+                            .clicked()
+                        {
+                            let cdp = self.cdp.clone();
+                            self.spawn_job(NEW_TAB_JOB, async move {
+                                match open_tab(&cdp, "about:blank").await {
+                                    Ok(_) => JobOutcome::Status(
+                                        ToastLevel::Success,
+                                        "Opened a new tab".into(),
+                                    ),
+                                    Err(e) => JobOutcome::failed(format!("Error: {}", e)),
+                                }
+                            });
+                        }
+                    }
+                }
+            } else {
+                ui.horizontal(|ui| {
+                    let running = self.jobs.busy.contains(REVOKE_ALL_JOB);
+                    if ui
+                        .add_enabled(!running, egui::Button::new("Revoke all"))
+                        .on_hover_text("Revoke every open tab's cookies on all peers at once")
+                        .clicked()
+                    {
+                        revoke_all = true;
+                    }
+                    if running {
+                        ui.spinner();
+                    }
+                    if contexts.len() > 1 {
+                        let label = |context: Option<&String>| match context {
+                            Some(c) => {
+                                let n = contexts.iter().position(|x| x == c).unwrap_or(0);
+                                format!("Profile {}", n + 1)
+                            }
+                            None => "All profiles".to_string(),
+                        };
+                        egui::ComboBox::from_id_salt("browser_context")
+                            .selected_text(label(self.browser_context.as_ref()))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.browser_context, None, label(None));
+                                for context in &contexts {
+                                    ui.selectable_value(
+                                        &mut self.browser_context,
+                                        Some(context.clone()),
+                                        label(Some(context)),
+                                    )
+                                    .on_hover_text(context);
+                                }
+                            })
+                            .response
+                            .on_hover_text("Chrome has tabs open in several profiles or windows");
+                    }
+                });
+                if !self.selected_tabs.is_empty() {
+                    ui.horizontal(|ui| {
+                        let count = self.selected_tabs.len();
+                        let idle = !self.jobs.busy.contains(SELECTION_JOB);
+                        let share = egui::Button::new(format!("Share selected ({})", count));
+                        if ui.add_enabled(idle, share).clicked() {
+                            batch_action = Some(BatchAction::Share);
+                        }
+                        let revoke = egui::Button::new(format!("Revoke selected ({})", count));
+                        if ui.add_enabled(idle, revoke).clicked() {
+                            batch_action = Some(BatchAction::Revoke);
+                        }
+                        if ui.small_button("Clear selection").clicked() {
+                            self.selected_tabs.clear();
+                        }
+                        if !idle {
+                            ui.spinner();
+                        }
+                    });
+                }
+                if self.servers.len() > 1 {
+                    server_picker(ui, &self.servers, &mut self.active_server);
+                }
+                if self.server().grants.receiver_count() == 0 {
+                    ui.label(
+                        RichText::new(
+                            "No peers connected yet. On the receiving machine, enter this \
+                             machine's address in “Peer to listen on” and press Listen.",
+                        )
+                        .small()
+                        .color(Color32::from_rgb(220, 180, 90)),
+                    );
+                }
+                pending_action = if tabs.is_empty() {
+                    ui.label(format!("No tabs match “{}”.", self.tab_filter.trim()));
+                    None
+                } else {
+                    match self.settings.view_mode {
+                        ViewMode::Grid => {
+                            let thumbnails = if self.settings.show_thumbnails {
+                                self.thumbnails
+                                    .update(ctx, &self.rt_handle, &self.cdp, &tabs);
+                                Some(&self.thumbnails.textures)
+                            } else {
+                                None
+                            };
+                            let counts = self.cookie_counts.lock().unwrap();
+                            tab_grid(
+                                ui,
+                                &tabs,
+                                &counts,
+                                thumbnails,
+                                &self.share_marks,
+                                &self.mirrors,
+                                &self.jobs.busy,
+                                &mut self.selected_tabs,
+                                &mut self.focused_tab,
+                            )
+                        }
+                        ViewMode::List => tab_list(
+                            ui,
+                            &tabs,
+                            &self.mirrors,
+                            &self.jobs.busy,
+                            &mut self.selected_index,
+                        ),
+                    }
+                };
+            }
+            if let Some((action, tab_id)) = pending_action {
+                self.run_tab_action(action, &tab_id);
+            }
+            if revoke_all {
+                self.revoke_all();
+            }
+            if let Some(action) = batch_action {
+                self.run_on_selected(action);
+            }
+
+            let received = self.receiver.lock().unwrap().received.clone();
+            if !received.is_empty() {
+                ui.add_space(18.0);
+                ui.separator();
+                ui.heading("Received Shares");
+                received_list(ui, &received);
+            }
+
+            ui.add_space(18.0);
             ui.separator();
             ui.heading("Import Cookies and Open Tab");
 
+            let importing = self.jobs.busy.contains(IMPORT_JOB);
+            let mut import_job = None;
             let import = &mut self.cookie_import;
             ui.horizontal(|ui| {
                 if ui.button("Choose JSON File").clicked() {
@@ -201,13 +1769,39 @@ impl App for ChromeTabApp {
                 }
                 if let Some(path) = &import.last_path {
                     ui.label(path.display().to_string());
+                    if ui
+                        .button("Preview")
+                        .on_hover_text(
+                            "List the cookies an import would set, without opening a tab",
+                        )
+                        .clicked()
+                    {
+                        match preview_import(path) {
+                            Ok(cookies) => {
+                                import.last_status =
+                                    Some(format!("{} cookie(s) would be set", cookies.len()));
+                                import.preview = Some(cookies);
+                            }
+                            Err(e) => {
+                                import.last_status = Some(format!("Cannot import: {}", e));
+                                import.preview = None;
+                            }
+                        }
+                    }
                 }
             });
+            if let Some(cookies) = &import.preview {
+                import_preview(ui, cookies, &import.url_to_open);
+            }
 
             if import.show_dialog {
-                if let Some(path) = FileDialog::new().add_filter("JSON", &["json"]).pick_file() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("Cookies", &["json", "txt"])
+                    .pick_file()
+                {
                     import.last_path = Some(path.clone());
                     import.last_status = Some(format!("Loaded {}", path.display()));
+                    import.preview = None;
                 }
                 import.show_dialog = false;
             }
@@ -215,27 +1809,1047 @@ impl App for ChromeTabApp {
             ui.horizontal(|ui| {
                 ui.label("URL to open:");
                 ui.text_edit_singleline(&mut import.url_to_open);
-                if ui.button("Open").clicked() {
-                    if let (Some(path), true) = (&import.last_path, !import.url_to_open.trim().is_empty()) {
-                        match import_and_open_with_cookies(path, &import.url_to_open) {
-                            Ok(_) => import.last_status = Some("Tab opened successfully".to_string()),
-                            Err(e) => import.last_status = Some(format!("Error: {}", e)),
-                        }
+                import.copies = import.copies.max(1);
+                ui.add(
+                    egui::DragValue::new(&mut import.copies)
+                        .range(1..=20)
+                        .suffix(" tab(s)"),
+                );
+                if ui
+                    .add_enabled(!importing, egui::Button::new("Open"))
+                    .clicked()
+                {
+                    if let (Some(path), true) =
+                        (&import.last_path, !import.url_to_open.trim().is_empty())
+                    {
+                        import_job =
+                            Some((path.clone(), import.url_to_open.clone(), import.copies));
                     } else {
-                        import.last_status = Some("Select a file and enter a URL to proceed".to_string());
+                        import.last_status =
+                            Some("Select a file and enter a URL to proceed".to_string());
                     }
                 }
+                if importing {
+                    ui.spinner();
+                }
             });
 
             if let Some(msg) = &import.last_status {
-                ui.label(msg);
+                ui.horizontal(|ui| {
+                    ui.label(msg);
+                    if let Some(path) = &self.last_export
+                        && ui
+                            .small_button("Open folder")
+                            .on_hover_text("Show the last exported file in the file manager")
+                            .clicked()
+                        && let Err(e) = reveal_in_file_manager(path)
+                    {
+                        warn!("Failed to open file manager: {}", e);
+                    }
+                });
+            }
+            if let Some((path, url, copies)) = import_job {
+                self.open_imported(path, url, copies);
             }
         });
 
+        if self.approval.is_none() {
+            let mut receiver = self.receiver.lock().unwrap();
+            if !receiver.pending.is_empty() {
+                let pending = receiver.pending.remove(0);
+                self.approval = Some(Approval {
+                    selected: vec![true; pending.grant.cookies.len()],
+                    pending,
+                });
+            }
+        }
+        if let Some(approval) = &mut self.approval {
+            match approval_prompt(ctx, approval) {
+                Some(true) => {
+                    let Approval {
+                        mut pending,
+                        selected,
+                    } = self.approval.take().unwrap();
+                    let mut keep = selected.into_iter();
+                    pending
+                        .grant
+                        .cookies
+                        .retain(|_| keep.next().unwrap_or(false));
+                    let cdp = self.cdp.clone();
+                    let state = Arc::clone(&self.receiver);
+                    self.rt_handle.spawn(async move {
+                        crate::network::import_grant(&cdp, &state, pending.grant, pending.from)
+                            .await
+                    });
+                }
+                Some(false) => {
+                    let approval = self.approval.take().unwrap();
+                    info!(
+                        "Rejected grant for {} from {}",
+                        approval.pending.grant.url, approval.pending.from
+                    );
+                }
+                None => {}
+            }
+        }
+
+        if let Some(confirm) = &mut self.share_confirm {
+            match share_confirm_prompt(ctx, confirm) {
+                Some(true) => {
+                    let confirm = self.share_confirm.take().unwrap();
+                    self.skip_share_confirm = confirm.dont_ask_again;
+                    self.dispatch_share(confirm.share);
+                }
+                Some(false) => self.share_confirm = None,
+                None => {}
+            }
+        }
+
+        if let Some(draft) = &mut self.share_draft {
+            match share_editor(ctx, draft) {
+                Some(true) => {
+                    let draft = self.share_draft.take().unwrap();
+                    self.confirm_share(HeldShare::Edited(draft));
+                }
+                Some(false) => self.share_draft = None,
+                None => {}
+            }
+        }
+
+        self.toasts
+            .retain(|t| t.created_at.elapsed() < TOAST_DURATION);
+        if !self.toasts.is_empty() {
+            egui::Area::new(egui::Id::new("toasts"))
+                .anchor(egui::Align2::RIGHT_BOTTOM, Vec2::new(-16.0, -16.0))
+                .order(egui::Order::Foreground)
+                .interactable(false)
+                .show(ctx, |ui| {
+                    for toast in &self.toasts {
+                        toast.show(ui);
+                    }
+                });
+        }
+
         ctx.request_repaint_after(Duration::from_millis(200));
     }
 }
 
+/// Most often the cookie counts on the cards are brought up to date.
+const COOKIE_COUNT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Repeat shares of one tab within this long are dropped.
+const SHARE_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// The last time a tab was shared or, after that, revoked.
+#[derive(Clone, Copy, Debug)]
+struct ShareMark {
+    at: Instant,
+    revoked: bool,
+}
+
+impl ShareMark {
+    /// `shared 2m ago`, `revoked just now`.
+    fn caption(&self) -> String {
+        let verb = if self.revoked { "revoked" } else { "shared" };
+        format!("{} {}", verb, ago(self.at.elapsed()))
+    }
+}
+
+fn ago(elapsed: Duration) -> String {
+    match elapsed.as_secs() {
+        0..60 => "just now".into(),
+        secs @ 60..3600 => format!("{}m ago", secs / 60),
+        secs @ 3600..86400 => format!("{}h ago", secs / 3600),
+        secs => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// How long each mDNS browse listens for answers, and the pause between them.
+const DISCOVERY_WAIT: Duration = Duration::from_secs(2);
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(15);
+
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+/// The last stretch of `TOAST_DURATION`, over which a toast fades out.
+const TOAST_FADE: Duration = Duration::from_secs(1);
+const MAX_TOASTS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ToastLevel {
+    Info,
+    Success,
+    Error,
+}
+
+/// A notification in the bottom-right stack, dropped after `TOAST_DURATION`.
+struct Toast {
+    text: String,
+    created_at: Instant,
+    level: ToastLevel,
+}
+
+impl Toast {
+    fn new(level: ToastLevel, text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            created_at: Instant::now(),
+            level,
+        }
+    }
+
+    fn show(&self, ui: &mut egui::Ui) {
+        let left = TOAST_DURATION.saturating_sub(self.created_at.elapsed());
+        let opacity = (left.as_secs_f32() / TOAST_FADE.as_secs_f32()).min(1.0);
+        let color = match self.level {
+            ToastLevel::Info => ui.visuals().text_color(),
+            ToastLevel::Success => Color32::from_rgb(90, 200, 120),
+            ToastLevel::Error => Color32::from_rgb(230, 100, 100),
+        };
+        ui.scope(|ui| {
+            ui.set_opacity(opacity);
+            Frame::popup(ui.style()).show(ui, |ui| {
+                ui.set_max_width(360.0);
+                ui.label(RichText::new(&self.text).color(color));
+            });
+        });
+    }
+}
+
+// Presses arrive while the window is usually not focused, or even visible,
+// so each one wakes the UI.
+fn register_hotkey(
+    binding: &str,
+    ctx: egui::Context,
+    pressed: Arc<AtomicBool>,
+) -> Result<HotkeyGrab, String> {
+    let hotkey: Hotkey = binding
+        .parse()
+        .map_err(|e| format!("{:?}: {}", binding, e))?;
+    hotkey::register(&hotkey, move || {
+        pressed.store(true, Ordering::Relaxed);
+        ctx.request_repaint();
+    })
+    .map_err(|e| e.to_string())
+}
+
+async fn refresh_tabs(cdp: &CdpEndpoint, tabs: &Mutex<Vec<ChromeTab>>, status: &Mutex<CdpStatus>) {
+    match fetch_tabs(cdp).await {
+        Ok(new_tabs) => {
+            *tabs.lock().unwrap() = new_tabs;
+            *status.lock().unwrap() = CdpStatus::Connected;
+        }
+        Err(e) => {
+            tabs.lock().unwrap().clear();
+            *status.lock().unwrap() = CdpStatus::Unreachable(e.to_string());
+        }
+    }
+}
+
+// Count the cookies of tabs that are new, have moved to another URL or
+// have no count yet, reading through the cookie cache; closed tabs drop out.
+// `counted` remembers the URL each tab was last counted at. Tabs whose
+// cookies can't be read are left without a count.
+async fn refresh_cookie_counts(
+    cdp: &CdpEndpoint,
+    tabs: &[ChromeTab],
+    cache: &Mutex<CookieCache>,
+    counted: &mut HashMap<String, String>,
+    counts: &Mutex<HashMap<String, usize>>,
+) {
+    counted.retain(|id, _| tabs.iter().any(|t| &t.id == id));
+    let stale = uncounted_tabs(tabs, counted, &counts.lock().unwrap());
+    let mut fresh = Vec::new();
+    for tab in stale {
+        counted.insert(tab.id.clone(), tab.url.clone());
+        let cookies = cached_cookies(cache, cdp, tab).await;
+        fresh.push((tab.id.clone(), cookies.ok().map(|c| c.len())));
+    }
+    let mut counts = counts.lock().unwrap();
+    counts.retain(|id, _| counted.contains_key(id));
+    for (id, count) in fresh {
+        match count {
+            Some(count) => counts.insert(id, count),
+            None => counts.remove(&id),
+        };
+    }
+}
+
+fn uncounted_tabs<'a>(
+    tabs: &'a [ChromeTab],
+    counted: &HashMap<String, String>,
+    counts: &HashMap<String, usize>,
+) -> Vec<&'a ChromeTab> {
+    tabs.iter()
+        .filter(|t| counted.get(&t.id) != Some(&t.url) || !counts.contains_key(&t.id))
+        .collect()
+}
+
+fn empty_state(ui: &mut egui::Ui, title: &str, detail: &str) {
+    ui.label(RichText::new(title).size(18.0).strong());
+    ui.add_space(4.0);
+    ui.add(
+        Label::new(
+            RichText::new(detail)
+                .italics()
+                .color(Color32::from_rgb(200, 100, 100)),
+        )
+        .wrap(),
+    );
+    ui.add_space(8.0);
+}
+
+// Distinct browser contexts (profiles) among `tabs`, in listing order.
+fn browser_contexts(tabs: &[ChromeTab]) -> Vec<String> {
+    let mut contexts: Vec<String> = Vec::new();
+    for context in tabs.iter().filter_map(|t| t.browserContextId.as_ref()) {
+        if !contexts.contains(context) {
+            contexts.push(context.clone());
+        }
+    }
+    contexts
+}
+
+// Case-insensitive substring match on title or URL; a blank query keeps all.
+fn filter_tabs(tabs: &[ChromeTab], query: &str) -> Vec<ChromeTab> {
+    let query = query.trim().to_lowercase();
+    tabs.iter()
+        .filter(|tab| {
+            query.is_empty()
+                || tab.title.to_lowercase().contains(&query)
+                || tab.url.to_lowercase().contains(&query)
+        })
+        .cloned()
+        .collect()
+}
+
+// Ties keep their `/json` order, so equal titles don't swap on refresh.
+fn sort_tabs(tabs: &mut [ChromeTab], sort: TabSort) {
+    match sort {
+        TabSort::Chrome => {}
+        TabSort::Title => tabs.sort_by_cached_key(|t| t.title.to_lowercase()),
+        TabSort::Url => tabs.sort_by(|a, b| a.url.cmp(&b.url)),
+        TabSort::Recent => tabs.reverse(),
+    }
+}
+
+const THUMBNAIL_SIZE: [u32; 2] = [240, 135];
+
+/// Tab screenshots for the grid, taken once per tab on first display and
+/// again only when cleared, so CDP isn't asked for one every frame.
+#[derive(Default)]
+struct Thumbnails {
+    textures: HashMap<String, TextureHandle>,
+    requested: HashSet<String>,
+    arrived: Arc<Mutex<Vec<(String, ColorImage)>>>,
+}
+
+impl Thumbnails {
+    // Upload finished captures and start captures for visible tabs that have
+    // none yet.
+    fn update(
+        &mut self,
+        ctx: &egui::Context,
+        rt: &Handle,
+        cdp: &CdpEndpoint,
+        visible: &[ChromeTab],
+    ) {
+        for (id, image) in self.arrived.lock().unwrap().drain(..) {
+            let texture = ctx.load_texture(format!("thumb-{}", id), image, TextureOptions::LINEAR);
+            self.textures.insert(id, texture);
+        }
+        for tab in visible {
+            if !self.requested.insert(tab.id.clone()) {
+                continue;
+            }
+            let (cdp, tab, arrived, ctx) = (
+                cdp.clone(),
+                tab.clone(),
+                Arc::clone(&self.arrived),
+                ctx.clone(),
+            );
+            rt.spawn(async move {
+                match capture_thumbnail(&cdp, &tab).await {
+                    Ok(image) => {
+                        arrived.lock().unwrap().push((tab.id, image));
+                        ctx.request_repaint();
+                    }
+                    Err(e) => debug!("No thumbnail for {}: {}", tab.title, e),
+                }
+            });
+        }
+    }
+
+    fn forget_closed(&mut self, open: &[ChromeTab]) {
+        let open: HashSet<&str> = open.iter().map(|t| t.id.as_str()).collect();
+        self.textures.retain(|id, _| open.contains(id.as_str()));
+        self.requested.retain(|id| open.contains(id.as_str()));
+    }
+
+    fn clear(&mut self) {
+        self.textures.clear();
+        self.requested.clear();
+    }
+}
+
+async fn capture_thumbnail(
+    cdp: &CdpEndpoint,
+    tab: &ChromeTab,
+) -> Result<ColorImage, Box<dyn std::error::Error + Send + Sync>> {
+    let png = capture_screenshot(cdp, tab).await?;
+    let image = image::load_from_memory_with_format(&png, image::ImageFormat::Png)?
+        .thumbnail(THUMBNAIL_SIZE[0], THUMBNAIL_SIZE[1])
+        .to_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+    Ok(ColorImage::from_rgba_unmultiplied(size, image.as_raw()))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn tab_grid(
+    ui: &mut egui::Ui,
+    tabs: &[ChromeTab],
+    cookie_counts: &HashMap<String, usize>,
+    thumbnails: Option<&HashMap<String, TextureHandle>>,
+    share_marks: &HashMap<String, ShareMark>,
+    mirrors: &HashMap<String, JoinHandle<()>>,
+    busy: &HashSet<String>,
+    selected: &mut HashSet<String>,
+    focused: &mut Option<String>,
+) -> Option<(TabAction, String)> {
+    let mut pending_action = None;
+    ui.label(
+        RichText::new("Click a card to export · Ctrl+Enter shares the outlined card")
+            .small()
+            .weak(),
+    );
+    let card_width = 260.0;
+    let card_height = match thumbnails {
+        Some(_) => 80.0 + THUMBNAIL_SIZE[1] as f32 + 8.0,
+        None => 80.0,
+    };
+    let cols = (ui.available_width() / (card_width + 16.0))
+        .floor()
+        .max(1.0) as usize;
+    ScrollArea::vertical().show(ui, |ui| {
+        ui.spacing_mut().item_spacing = Vec2::splat(16.0);
+        ui.columns(cols, |columns| {
+            for (i, tab) in tabs.iter().enumerate() {
+                let col_ui = &mut columns[i % cols];
+                let (rect, resp) =
+                    col_ui.allocate_exact_size(Vec2::new(card_width, card_height), Sense::click());
+                // The card's own response misses hovers over its buttons.
+                let hovered = col_ui.rect_contains_pointer(rect);
+                if hovered {
+                    *focused = Some(tab.id.clone());
+                }
+                let bg = if hovered {
+                    Color32::from_gray(50)
+                } else {
+                    Color32::from_gray(40)
+                };
+                col_ui
+                    .painter()
+                    .rect_filled(rect, CornerRadius::same(8), bg);
+                if focused.as_ref() == Some(&tab.id) {
+                    col_ui.painter().rect_stroke(
+                        rect,
+                        CornerRadius::same(8),
+                        Stroke::new(1.5, Color32::from_rgb(90, 160, 230)),
+                        StrokeKind::Inside,
+                    );
+                }
+                col_ui.scope_builder(UiBuilder::new().max_rect(rect.shrink(8.0)), |ui| {
+                    ui.horizontal(|ui| {
+                        let mut ticked = selected.contains(&tab.id);
+                        if ui.checkbox(&mut ticked, "").changed() {
+                            if ticked {
+                                selected.insert(tab.id.clone());
+                            } else {
+                                selected.remove(&tab.id);
+                            }
+                        }
+                        ui.label(RichText::new(format!("{}.", i + 1)).strong());
+                        ui.label(
+                            RichText::new(&tab.title)
+                                .font(FontId::proportional(16.0))
+                                .strong(),
+                        );
+                        if let Some(count) = cookie_counts.get(&tab.id) {
+                            ui.label(RichText::new(format!("🍪 {}", count)).small())
+                                .on_hover_text("Cookies this tab carries");
+                        }
+                        let mark = share_marks.get(&tab.id);
+                        if let Some(mark) = mark {
+                            ui.label(RichText::new(mark.caption()).small().weak());
+                        }
+                        // One action per tab at a time; the buttons come back
+                        // once the running one reports.
+                        let idle = !busy.contains(&tab.id);
+                        let share = match mark {
+                            Some(mark) if !mark.revoked => "Re-share",
+                            _ => "Share",
+                        };
+                        if ui
+                            .add_enabled(idle, egui::Button::new(share).small())
+                            .on_hover_text("Shift+click to open it in the peer's current tab")
+                            .clicked()
+                        {
+                            let action = if ui.input(|i| i.modifiers.shift) {
+                                TabAction::ShareInCurrent
+                            } else {
+                                TabAction::Share
+                            };
+                            pending_action = Some((action, tab.id.clone()));
+                        }
+                        if ui
+                            .add_enabled(idle, egui::Button::new("Edit").small())
+                            .on_hover_text("Review and edit the cookies before sharing")
+                            .clicked()
+                        {
+                            pending_action = Some((TabAction::EditShare, tab.id.clone()));
+                        }
+                        let revoke = ui
+                            .add_enabled(idle, egui::Button::new("Revoke").small())
+                            .on_hover_text("Right-click to revoke everything for this site");
+                        if revoke.clicked() {
+                            pending_action = Some((TabAction::Revoke, tab.id.clone()));
+                        }
+                        revoke.context_menu(|ui| {
+                            if ui
+                                .button("Revoke all for this site")
+                                .on_hover_text(
+                                    "Peers delete every cookie for this URL and clear its \
+                                     stored data, not just the cookies it has here",
+                                )
+                                .clicked()
+                            {
+                                pending_action = Some((TabAction::RevokeSite, tab.id.clone()));
+                                ui.close();
+                            }
+                        });
+                        if ui
+                            .selectable_label(mirrors.contains_key(&tab.id), "Mirror")
+                            .on_hover_text("Send this tab's navigations to peers as they happen")
+                            .clicked()
+                        {
+                            pending_action = Some((TabAction::ToggleMirror, tab.id.clone()));
+                        }
+                        if !idle {
+                            ui.spinner();
+                        }
+                    });
+                    ui.add_space(2.0);
+                    ui.label(RichText::new(clip(&tab.url, 45)).monospace());
+                    if let Some(texture) = thumbnails.and_then(|t| t.get(&tab.id)) {
+                        ui.add_space(4.0);
+                        ui.image((texture.id(), texture.size_vec2()));
+                    }
+                });
+                if resp.clicked() {
+                    *focused = Some(tab.id.clone());
+                    pending_action = Some((TabAction::Export, tab.id.clone()));
+                }
+            }
+        });
+    });
+    pending_action
+}
+
+// Rows are driven from the keyboard: arrows move the selection, Enter exports,
+// S shares (Shift+S into the peer's current tab), E opens the share editor, R
+// revokes and M toggles live mirroring. Keys are ignored while a text field
+// has focus so typing an address doesn't trigger actions.
+fn tab_list(
+    ui: &mut egui::Ui,
+    tabs: &[ChromeTab],
+    mirrors: &HashMap<String, JoinHandle<()>>,
+    busy: &HashSet<String>,
+    selected: &mut usize,
+) -> Option<(TabAction, String)> {
+    *selected = (*selected).min(tabs.len() - 1);
+    let mut moved = false;
+    let mut key_action = None;
+    if ui.memory(|m| m.focused().is_none()) {
+        ui.input(|i| {
+            if i.key_pressed(Key::ArrowDown) && *selected + 1 < tabs.len() {
+                *selected += 1;
+                moved = true;
+            }
+            if i.key_pressed(Key::ArrowUp) && *selected > 0 {
+                *selected -= 1;
+                moved = true;
+            }
+            if i.key_pressed(Key::Enter) {
+                key_action = Some(TabAction::Export);
+            } else if i.key_pressed(Key::S) && i.modifiers.shift {
+                key_action = Some(TabAction::ShareInCurrent);
+            } else if i.key_pressed(Key::S) {
+                key_action = Some(TabAction::Share);
+            } else if i.key_pressed(Key::E) {
+                key_action = Some(TabAction::EditShare);
+            } else if i.key_pressed(Key::R) {
+                key_action = Some(TabAction::Revoke);
+            } else if i.key_pressed(Key::M) {
+                key_action = Some(TabAction::ToggleMirror);
+            }
+        });
+    }
+    let mut pending_action = key_action.map(|a| (a, tabs[*selected].id.clone()));
+
+    ui.label(
+        RichText::new("↑/↓ select · Enter export · S share · ⇧S share into current tab · E edit & share · R revoke · M mirror")
+            .small()
+            .weak(),
+    );
+    ScrollArea::vertical().show(ui, |ui| {
+        for (i, tab) in tabs.iter().enumerate() {
+            let marker = if mirrors.contains_key(&tab.id) {
+                "  ⟲ mirroring"
+            } else {
+                ""
+            };
+            let text = format!(
+                "{}. {}  —  {}{}",
+                i + 1,
+                tab.title,
+                clip(&tab.url, 60),
+                marker
+            );
+            let resp = ui
+                .horizontal(|ui| {
+                    let resp = ui.selectable_label(i == *selected, text);
+                    if busy.contains(&tab.id) {
+                        ui.spinner();
+                    }
+                    resp
+                })
+                .inner;
+            if resp.clicked() {
+                *selected = i;
+            }
+            if resp.double_clicked() {
+                pending_action = Some((TabAction::Export, tab.id.clone()));
+            }
+            if moved && i == *selected {
+                resp.scroll_to_me(None);
+            }
+        }
+    });
+    pending_action
+}
+
+// A share held back so its cookies can be tweaked, e.g. pointed at a staging
+// domain, before the grant is built from the edited set.
+struct ShareDraft {
+    tab: ChromeTab,
+    cookies: Vec<Cookie>,
+}
+
+const SAME_SITE: [&str; 3] = ["Strict", "Lax", "None"];
+
+// Returns `Some(true)` to send the edited cookies and `Some(false)` to drop
+// the draft. Send stays disabled while any row fails `validate_cookie`.
+fn share_editor(ctx: &egui::Context, draft: &mut ShareDraft) -> Option<bool> {
+    let mut outcome = None;
+    let mut open = true;
+    egui::Window::new(format!("Share {}", clip(&draft.tab.title, 40)))
+        .open(&mut open)
+        .collapsible(false)
+        .default_width(720.0)
+        .show(ctx, |ui| {
+            ui.label(RichText::new(clip(&draft.tab.url, 80)).monospace().weak());
+            let mut remove = None;
+            let mut all_valid = true;
+            ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                egui::Grid::new("share_editor")
+                    .striped(true)
+                    .num_columns(9)
+                    .show(ui, |ui| {
+                        for heading in [
+                            "Name", "Value", "Domain", "Path", "Secure", "HttpOnly", "SameSite",
+                            "Expires", "",
+                        ] {
+                            ui.label(RichText::new(heading).strong());
+                        }
+                        ui.end_row();
+                        for (i, cookie) in draft.cookies.iter_mut().enumerate() {
+                            cookie_row(ui, i, cookie);
+                            if ui.small_button("🗑").on_hover_text("Leave out").clicked() {
+                                remove = Some(i);
+                            }
+                            ui.end_row();
+                            if let Err(e) = validate_cookie(cookie) {
+                                all_valid = false;
+                                ui.label("");
+                                ui.label(
+                                    RichText::new(e)
+                                        .small()
+                                        .color(Color32::from_rgb(220, 90, 90)),
+                                );
+                                ui.end_row();
+                            }
+                        }
+                    });
+            });
+            if let Some(i) = remove {
+                draft.cookies.remove(i);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                let send = ui.add_enabled(
+                    all_valid && !draft.cookies.is_empty(),
+                    egui::Button::new(format!("Send {} cookie(s)", draft.cookies.len())),
+                );
+                if send.clicked() {
+                    outcome = Some(true);
+                }
+                if ui.button("Cancel").clicked() {
+                    outcome = Some(false);
+                }
+            });
+        });
+    if !open {
+        outcome = Some(false);
+    }
+    outcome
+}
+
+// A share held until confirmed, since it sends a live session to every
+// connected peer. Counts are as of the click.
+struct ShareConfirm {
+    share: HeldShare,
+    cookies: Option<usize>,
+    peers: usize,
+    /// Only takes effect on Confirm.
+    dont_ask_again: bool,
+}
+
+// What Confirm sends.
+enum HeldShare {
+    /// A card's Share; `in_current` for Shift+Share, which peers load in
+    /// their current tab.
+    Tab { tab: ChromeTab, in_current: bool },
+    /// The share editor's Send, with the edited cookies.
+    Edited(ShareDraft),
+    /// Share selected, one grant per tab.
+    Selected(Vec<ChromeTab>),
+}
+
+/// Tabs named in the confirmation for Share selected; the rest are counted.
+const SHARE_CONFIRM_LISTED: usize = 5;
+
+// Returns `Some(true)` to share and `Some(false)` on Cancel, Escape or a
+// click outside the dialog.
+fn share_confirm_prompt(ctx: &egui::Context, confirm: &mut ShareConfirm) -> Option<bool> {
+    let mut outcome = None;
+    let modal = egui::Modal::new(egui::Id::new("share_confirm")).show(ctx, |ui| {
+        ui.set_max_width(480.0);
+        let tabs = match &confirm.share {
+            HeldShare::Tab { tab, .. } => std::slice::from_ref(tab),
+            HeldShare::Edited(draft) => std::slice::from_ref(&draft.tab),
+            HeldShare::Selected(tabs) => tabs.as_slice(),
+        };
+        if let [tab] = tabs {
+            ui.heading("Share this tab?");
+            ui.label(RichText::new(clip(&tab.title, 60)).strong());
+            ui.label(RichText::new(clip(&tab.url, 70)).monospace());
+        } else {
+            ui.heading(format!("Share {} tabs?", tabs.len()));
+            for tab in tabs.iter().take(SHARE_CONFIRM_LISTED) {
+                ui.label(RichText::new(clip(&tab.title, 60)).strong());
+            }
+            if tabs.len() > SHARE_CONFIRM_LISTED {
+                ui.label(format!("and {} more", tabs.len() - SHARE_CONFIRM_LISTED));
+            }
+        }
+        let cookies = match (confirm.cookies, tabs.len()) {
+            (Some(count), _) => format!("{} cookie(s)", count),
+            (None, 1) => "Its cookies".to_string(),
+            (None, _) => "Their cookies".to_string(),
+        };
+        ui.label(format!(
+            "{} will go to {} connected peer(s).",
+            cookies, confirm.peers
+        ));
+        match &confirm.share {
+            HeldShare::Tab {
+                in_current: true, ..
+            } => {
+                ui.label("It replaces the page in each peer's current tab.");
+            }
+            HeldShare::Edited(_) => {
+                ui.label("As edited, rather than as the tab has them.");
+            }
+            _ => {}
+        }
+        ui.checkbox(&mut confirm.dont_ask_again, "Don't ask again this session");
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Confirm").clicked() {
+                outcome = Some(true);
+            }
+            if ui.button("Cancel").clicked() {
+                outcome = Some(false);
+            }
+        });
+    });
+    if outcome.is_none() && modal.should_close() {
+        outcome = Some(false);
+    }
+    outcome
+}
+
+// A received grant on screen for review, with one checkbox per cookie.
+struct Approval {
+    pending: PendingGrant,
+    selected: Vec<bool>,
+}
+
+// Returns `Some(true)` to import the selected cookies and `Some(false)` to
+// reject the grant. Cookie values are never shown.
+fn approval_prompt(ctx: &egui::Context, approval: &mut Approval) -> Option<bool> {
+    let mut outcome = None;
+    let grant = &approval.pending.grant;
+    egui::Window::new("Incoming share")
+        .collapsible(false)
+        .default_width(520.0)
+        .show(ctx, |ui| {
+            ui.label(RichText::new(clip(&grant.url, 80)).monospace());
+            ui.label(format!("from {}", approval.pending.from));
+            if let Some(source) = &grant.source {
+                ui.label(
+                    RichText::new(format!("Captured on {}", source))
+                        .small()
+                        .weak(),
+                );
+            }
+            ui.horizontal(|ui| {
+                if ui.small_button("Select all").clicked() {
+                    approval.selected.fill(true);
+                }
+                if ui.small_button("Select none").clicked() {
+                    approval.selected.fill(false);
+                }
+            });
+            ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                for (cookie, selected) in grant.cookies.iter().zip(&mut approval.selected) {
+                    ui.checkbox(selected, cookie_summary(cookie));
+                }
+            });
+            if !grant.indexed_db.is_empty() {
+                ui.label(
+                    RichText::new(format!(
+                        "Also replays {} IndexedDB record(s)",
+                        grant.indexed_db.len()
+                    ))
+                    .small(),
+                );
+            }
+            if !grant.storage.is_empty() {
+                ui.label(
+                    RichText::new(format!(
+                        "Also sets {} localStorage item(s)",
+                        grant.storage.len()
+                    ))
+                    .small(),
+                );
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                let count = approval.selected.iter().filter(|s| **s).count();
+                if ui
+                    .add_enabled(
+                        count > 0,
+                        egui::Button::new(format!("Import {} cookie(s)", count)),
+                    )
+                    .clicked()
+                {
+                    outcome = Some(true);
+                }
+                if ui.button("Reject").clicked() {
+                    outcome = Some(false);
+                }
+            });
+        });
+    outcome
+}
+
+// `sid  .example.com/  Secure HttpOnly SameSite=Lax`: everything a receiver
+// needs to judge a cookie except its value.
+fn cookie_summary(cookie: &Cookie) -> String {
+    let mut summary = format!("{}  {}{}", cookie.name, cookie.domain, cookie.path);
+    if cookie.secure == Some(true) {
+        summary.push_str("  Secure");
+    }
+    if cookie.httpOnly == Some(true) {
+        summary.push_str("  HttpOnly");
+    }
+    if let Some(same_site) = &cookie.sameSite {
+        summary.push_str(&format!("  SameSite={}", same_site));
+    }
+    summary
+}
+
+fn cookie_row(ui: &mut egui::Ui, i: usize, cookie: &mut Cookie) {
+    ui.label(&cookie.name);
+    ui.add(egui::TextEdit::singleline(&mut cookie.value).desired_width(160.0));
+    ui.add(egui::TextEdit::singleline(&mut cookie.domain).desired_width(140.0));
+    ui.add(egui::TextEdit::singleline(&mut cookie.path).desired_width(60.0));
+    let mut secure = cookie.secure.unwrap_or(false);
+    if ui.checkbox(&mut secure, "").changed() {
+        cookie.secure = Some(secure);
+    }
+    let mut http_only = cookie.httpOnly.unwrap_or(false);
+    if ui.checkbox(&mut http_only, "").changed() {
+        cookie.httpOnly = Some(http_only);
+    }
+    egui::ComboBox::from_id_salt(("same_site", i))
+        .selected_text(cookie.sameSite.as_deref().unwrap_or("—"))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut cookie.sameSite, None, "—");
+            for value in SAME_SITE {
+                ui.selectable_value(&mut cookie.sameSite, Some(value.to_string()), value);
+            }
+        });
+    ui.label(relative_expiry(
+        cookie.expires,
+        cookie.session,
+        chrono::Utc::now(),
+    ));
+}
+
+fn server_picker(ui: &mut egui::Ui, servers: &[(String, Broadcasts)], active: &mut usize) {
+    let describe = |(name, server): &(String, Broadcasts)| {
+        format!(
+            "{} ({}) · {} peer(s)",
+            name,
+            server.addr(),
+            server.peers().len()
+        )
+    };
+    ui.horizontal(|ui| {
+        ui.label("Share via:");
+        egui::ComboBox::from_id_salt("server")
+            .selected_text(describe(&servers[*active]))
+            .show_ui(ui, |ui| {
+                for (i, server) in servers.iter().enumerate() {
+                    ui.selectable_value(active, i, describe(server));
+                }
+            });
+    });
+}
+
+// One line per cookie an import would set, greyed out with the reason when
+// it wouldn't be sent to the URL typed so far.
+fn import_preview(ui: &mut egui::Ui, cookies: &[Cookie], url: &str) {
+    let matches = match_cookies_to_url(cookies, url).ok();
+    ScrollArea::vertical()
+        .id_salt("import_preview")
+        .max_height(160.0)
+        .show(ui, |ui| {
+            for (i, cookie) in cookies.iter().enumerate() {
+                let summary = cookie_summary(cookie);
+                match matches.as_ref().and_then(|m| m[i].1.as_ref().err()) {
+                    Some(reason) => {
+                        ui.label(RichText::new(format!("{}  — {}", summary, reason)).weak());
+                    }
+                    None => {
+                        ui.label(summary);
+                    }
+                }
+            }
+        });
+}
+
+fn received_list(ui: &mut egui::Ui, received: &[ReceivedShare]) {
+    for share in received {
+        let host = share
+            .source
+            .as_ref()
+            .map(|s| format!(" ({})", s.source_host))
+            .unwrap_or_default();
+        let text = format!(
+            "{}  —  from {}{} · {} cookie(s)",
+            clip(&share.url, 60),
+            share.from,
+            host,
+            share.cookie_count
+        );
+        let label = if share.revoked {
+            ui.label(RichText::new(format!("{}  (revoked)", text)).weak())
+        } else {
+            ui.label(text)
+        };
+        if let Some(source) = &share.source {
+            label.on_hover_text(format!("Captured on {}", source));
+        }
+    }
+}
+
+// A broadcast send only fails when nobody is subscribed, i.e. no peer is
+// connected, so say that rather than letting the message vanish.
+fn broadcast_status<T>(tx: &BroadcastSender<T>, msg: T, what: &str) -> (ToastLevel, String) {
+    match tx.send(msg) {
+        Ok(peers) => (
+            ToastLevel::Success,
+            format!("Sent {} to {} peer(s)", what, peers),
+        ),
+        Err(_) => (
+            ToastLevel::Error,
+            format!("No peers connected — {} not delivered", what),
+        ),
+    }
+}
+
+// Split a comma/space separated list of origins into navigable URLs.
+fn parse_origins(input: &str) -> Result<Vec<String>, String> {
+    input
+        .split([',', ' ', '\n'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(normalize_url)
+        .collect()
+}
+
+// Open the folder holding `path`, selecting the file where the platform's
+// file manager supports it.
+fn reveal_in_file_manager(path: &Path) -> std::io::Result<()> {
+    let path = path.canonicalize()?;
+    #[cfg(target_os = "macos")]
+    {
+        Command::new("open").arg("-R").arg(&path).spawn()?;
+    }
+    #[cfg(target_os = "windows")]
+    {
+        let mut select = std::ffi::OsString::from("/select,");
+        select.push(&path);
+        Command::new("explorer").arg(select).spawn()?;
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    {
+        // FileManager1 selects the file in Nautilus, Dolphin and friends;
+        // without a file manager on the session bus just open the folder.
+        let shown = url::Url::from_file_path(&path).is_ok_and(|uri| {
+            Command::new("dbus-send")
+                .args([
+                    "--session",
+                    "--print-reply",
+                    "--dest=org.freedesktop.FileManager1",
+                    "--type=method_call",
+                    "/org/freedesktop/FileManager1",
+                    "org.freedesktop.FileManager1.ShowItems",
+                ])
+                .arg(format!("array:string:{}", uri))
+                .arg("string:")
+                .output()
+                .is_ok_and(|out| out.status.success())
+        });
+        if !shown {
+            let dir = path.parent().unwrap_or(&path);
+            Command::new("xdg-open").arg(dir).spawn()?;
+        }
+    }
+    Ok(())
+}
+
 fn clip(s: &str, max: usize) -> String {
     if s.chars().count() > max {
         s.chars().take(max).collect::<String>() + "…"
@@ -244,20 +2858,328 @@ fn clip(s: &str, max: usize) -> String {
     }
 }
 
+#[derive(Default)]
 pub struct CookieImportState {
     pub url_to_open: String,
     pub last_status: Option<String>,
     pub last_path: Option<PathBuf>,
     pub show_dialog: bool,
+    pub copies: usize,
+    /// Folder exports are written to, picked on the first export.
+    pub export_dir: Option<PathBuf>,
+    /// The chosen file's cookies as an import would set them, once
+    /// "Preview" is pressed.
+    pub preview: Option<Vec<Cookie>>,
 }
 
-impl Default for CookieImportState {
-    fn default() -> Self {
-        Self {
-            url_to_open: String::new(),
-            last_status: None,
-            last_path: None,
-            show_dialog: false,
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast;
+
+    #[test]
+    fn tab_filter_matches_title_or_url_ignoring_case() {
+        let tab = |id: &str, title: &str, url: &str| ChromeTab {
+            id: id.into(),
+            title: title.into(),
+            url: url.into(),
+            webSocketDebuggerUrl: None,
+            target_type: None,
+            browserContextId: None,
+        };
+        let tabs = [
+            tab("T1", "Inbox - Mail", "https://mail.example.com/"),
+            tab("T2", "Dashboard", "https://Grafana.internal/d/1"),
+            tab("T3", "Docs", "https://docs.example.com/"),
+        ];
+        let ids = |query| {
+            filter_tabs(&tabs, query)
+                .into_iter()
+                .map(|t| t.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids("inbox"), ["T1"]);
+        assert_eq!(ids("grafana"), ["T2"]);
+        assert_eq!(ids("EXAMPLE"), ["T1", "T3"]);
+        assert_eq!(ids("  "), ["T1", "T2", "T3"]);
+        assert!(ids("nothing").is_empty());
+    }
+
+    #[test]
+    fn tab_sort_is_stable() {
+        let tab = |id: &str, title: &str, url: &str| ChromeTab {
+            id: id.into(),
+            title: title.into(),
+            url: url.into(),
+            webSocketDebuggerUrl: None,
+            target_type: None,
+            browserContextId: None,
+        };
+        let tabs = [
+            tab("T1", "docs", "https://b.example.com/"),
+            tab("T2", "Mail", "https://a.example.com/"),
+            tab("T3", "Docs", "https://c.example.com/"),
+        ];
+        let ids = |sort| {
+            let mut sorted = tabs.to_vec();
+            sort_tabs(&mut sorted, sort);
+            sorted.into_iter().map(|t| t.id).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(TabSort::Chrome), ["T1", "T2", "T3"]);
+        assert_eq!(ids(TabSort::Title), ["T1", "T3", "T2"]);
+        assert_eq!(ids(TabSort::Url), ["T2", "T1", "T3"]);
+        assert_eq!(ids(TabSort::Recent), ["T3", "T2", "T1"]);
+    }
+
+    // An action context whose CDP port has nothing listening on it.
+    async fn unreachable_context() -> (ActionContext, u16) {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server = crate::network::spawn_server(
+            "127.0.0.1:0".parse().unwrap(),
+            crate::network::ServerOptions::default(),
+        )
+        .await
+        .unwrap();
+        let context = ActionContext {
+            cdp: CdpEndpoint::new("127.0.0.1", port),
+            cookie_cache: Arc::new(Mutex::new(CookieCache::new(Duration::from_secs(5)))),
+            extra_origins: String::new(),
+            share_domains: String::new(),
+            include_indexed_db: false,
+            include_storage: false,
+            encrypt_grants: false,
+            export_dir: PathBuf::new(),
+            export_template: String::new(),
+            export_format: CookieFormat::Json,
+            annotate_expiry: false,
+            server,
+        };
+        (context, port)
+    }
+
+    #[tokio::test]
+    async fn unreadable_cookies_fail_a_share_instead_of_sending_none() {
+        let (context, port) = unreachable_context().await;
+        let tab = ChromeTab {
+            id: "T1".into(),
+            title: "T1".into(),
+            url: "https://example.com/".into(),
+            webSocketDebuggerUrl: Some(format!("ws://127.0.0.1:{}/devtools/page/T1", port)),
+            target_type: Some("page".into()),
+            browserContextId: None,
+        };
+        assert!(context.capture_cookies(&tab).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_partly_failed_batch_toasts_as_an_error() {
+        let (context, _) = unreachable_context().await;
+        let ids = ["T1".to_string()];
+        let outcome = context.run_batch(BatchAction::Share, &ids).await;
+        let JobOutcome::Batch {
+            level,
+            status,
+            all_done,
+        } = outcome
+        else {
+            panic!("not a batch outcome");
+        };
+        assert_eq!(level, ToastLevel::Error);
+        assert!(
+            status.starts_with("Shared 0 of 1 tab(s); T1: "),
+            "{}",
+            status
+        );
+        assert!(!all_done);
+
+        let JobOutcome::Batch { level, status, .. } =
+            context.run_batch(BatchAction::Share, &[]).await
+        else {
+            panic!("not a batch outcome");
+        };
+        assert_eq!(
+            (level, status.as_str()),
+            (ToastLevel::Success, "Shared 0 tab(s)")
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn hotkey_shares_are_confirmed_debounced_and_marked() {
+        let (context, port) = unreachable_context().await;
+        let cc = eframe::CreationContext::_new_kittest(egui::Context::default());
+        let mut app = ChromeTabApp::new(
+            &cc,
+            CdpEndpoint::new("127.0.0.1", port),
+            vec![("main".into(), context.server.clone())],
+            AppOptions {
+                proxy: None,
+                cookie_ttl: Duration::from_secs(5),
+                export_template: None,
+                connect_to: None,
+                cdp_events: ReconnectPolicy::default(),
+                peer_reconnect: ReconnectPolicy::default(),
+                share_hotkey: None,
+                chrome: Arc::new(Mutex::new(None)),
+                secret: None,
+                pin_cert: None,
+                auth_token: None,
+                share_domains: Vec::new(),
+                include_storage: false,
+                close_on_revoke: false,
+                received_path: None,
+            },
+            Handle::current(),
+        );
+        // Nothing here needs the tab list or cookie counts kept fresh.
+        app.stop_refresh.store(true, Ordering::Relaxed);
+        let tab = ChromeTab {
+            id: "T1".into(),
+            title: "Mail".into(),
+            url: "https://mail.example.com/".into(),
+            webSocketDebuggerUrl: None,
+            target_type: Some("page".into()),
+            browserContextId: None,
+        };
+        let press = |app: &mut ChromeTabApp| {
+            app.jobs
+                .done_tx
+                .send((ACTIVE_TAB_JOB.into(), JobOutcome::ActiveTab(tab.clone())))
+                .unwrap();
+            app.finish_jobs();
+        };
+
+        press(&mut app);
+        let Some(confirm) = app.share_confirm.take() else {
+            panic!("the hotkey shared without asking");
+        };
+        assert!(matches!(&confirm.share, HeldShare::Tab { tab, .. } if tab.id == "T1"));
+        app.dispatch_share(confirm.share);
+        assert!(app.recent_shares.contains_key("T1"));
+        assert_eq!(app.pending_marks.get("T1"), Some(&false));
+
+        // Holding the key down repeats the press.
+        press(&mut app);
+        assert!(app.share_confirm.is_none());
+        assert!(
+            app.toasts
+                .iter()
+                .any(|t| t.text.starts_with("Already shared"))
+        );
+    }
+
+    #[test]
+    fn only_new_moved_or_uncounted_tabs_are_recounted() {
+        let tab = |id: &str, url: &str| ChromeTab {
+            id: id.into(),
+            title: id.into(),
+            url: url.into(),
+            webSocketDebuggerUrl: None,
+            target_type: Some("page".into()),
+            browserContextId: None,
+        };
+        let tabs = [
+            tab("T1", "https://a.example.com/"),
+            tab("T2", "https://b.example.com/moved"),
+            tab("T3", "https://c.example.com/"),
+            tab("T4", "https://d.example.com/"),
+        ];
+        let counted: HashMap<String, String> = [
+            ("T1", "https://a.example.com/"),
+            ("T2", "https://b.example.com/"),
+            ("T4", "https://d.example.com/"),
+        ]
+        .into_iter()
+        .map(|(id, url)| (id.to_string(), url.to_string()))
+        .collect();
+        // T4's cookies couldn't be read last time.
+        let counts: HashMap<String, usize> = [("T1".to_string(), 3), ("T2".to_string(), 1)].into();
+
+        let stale: Vec<&str> = uncounted_tabs(&tabs, &counted, &counts)
+            .iter()
+            .map(|t| t.id.as_str())
+            .collect();
+        assert_eq!(stale, ["T2", "T3", "T4"]);
+    }
+
+    #[test]
+    fn share_captions_round_down_to_the_largest_unit() {
+        assert_eq!(ago(Duration::from_secs(59)), "just now");
+        assert_eq!(ago(Duration::from_secs(150)), "2m ago");
+        assert_eq!(ago(Duration::from_secs(2 * 3600 + 59)), "2h ago");
+        assert_eq!(ago(Duration::from_secs(3 * 86400)), "3d ago");
+        let mark = ShareMark {
+            at: Instant::now(),
+            revoked: true,
+        };
+        assert_eq!(mark.caption(), "revoked just now");
+    }
+
+    #[test]
+    fn profiles_are_listed_once_in_tab_order() {
+        let tab = |id: &str, context: Option<&str>| ChromeTab {
+            id: id.into(),
+            title: id.into(),
+            url: "https://example.com/".into(),
+            webSocketDebuggerUrl: None,
+            target_type: Some("page".into()),
+            browserContextId: context.map(str::to_string),
+        };
+        let tabs = [
+            tab("T1", Some("B")),
+            tab("T2", Some("A")),
+            tab("T3", None),
+            tab("T4", Some("B")),
+        ];
+        assert_eq!(browser_contexts(&tabs), ["B", "A"]);
+        assert!(browser_contexts(&tabs[2..3]).is_empty());
+    }
+
+    #[test]
+    fn send_without_peers_is_reported() {
+        let (tx, rx) = broadcast::channel::<u32>(4);
+        drop(rx);
+        assert_eq!(
+            broadcast_status(&tx, 1, "grant"),
+            (
+                ToastLevel::Error,
+                "No peers connected — grant not delivered".to_string()
+            )
+        );
+
+        let _peer = tx.subscribe();
+        assert_eq!(
+            broadcast_status(&tx, 2, "grant"),
+            (ToastLevel::Success, "Sent grant to 1 peer(s)".to_string())
+        );
+    }
+
+    #[test]
+    fn cookie_summary_leaves_out_the_value() {
+        let cookie: Cookie = serde_json::from_value(serde_json::json!({
+            "domain": ".example.com",
+            "name": "sid",
+            "path": "/",
+            "value": "s3cret",
+            "secure": true,
+            "sameSite": "Lax",
+        }))
+        .unwrap();
+        let summary = cookie_summary(&cookie);
+        assert_eq!(summary, "sid  .example.com/  Secure  SameSite=Lax");
+        assert!(!summary.contains("s3cret"));
+    }
+
+    #[test]
+    fn extra_origins_are_normalized() {
+        assert_eq!(parse_origins(" ").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            parse_origins("login.example.com, http://sso.example.org").unwrap(),
+            ["https://login.example.com", "http://sso.example.org"]
+        );
+        assert!(parse_origins("example.com chrome://settings").is_err());
     }
 }
@@ -0,0 +1,169 @@
+use crate::browser::Browser;
+use crate::chrome::{ChromeTab, Cookie, filter_cookies, normalize_url};
+use crate::network::{Broadcasts, grant_for_tab, revoke_for_tab};
+use crate::settings::Settings;
+use std::error::Error;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use url::Url;
+
+/// Which tab `--no-gui` shares.
+#[derive(Clone, Debug)]
+pub enum ShareTarget {
+    /// Position in Chrome's `/json` listing, as shown by `print_tabs_once`.
+    Index(usize),
+    /// The one tab on exactly this URL or, for a bare origin such as
+    /// `mail.example.org`, the one tab on that origin.
+    Url(String),
+}
+
+pub async fn resolve_target(
+    browser: &dyn Browser,
+    target: &ShareTarget,
+) -> Result<ChromeTab, Box<dyn Error + Send + Sync>> {
+    let tabs = browser.fetch_tabs().await?;
+    match target {
+        ShareTarget::Index(i) => tabs
+            .get(*i)
+            .cloned()
+            .ok_or_else(|| format!("no tab {}; Chrome has {} open", i, tabs.len()).into()),
+        ShareTarget::Url(url) => {
+            let wanted = Url::parse(&normalize_url(url)?)?;
+            let mut matches = tabs_on(tabs, &wanted).into_iter();
+            match (matches.next(), matches.next()) {
+                (Some(tab), None) => Ok(tab),
+                (None, _) => Err(format!("no open tab is on {}", wanted).into()),
+                (Some(_), Some(_)) => Err(format!(
+                    "more than one tab is on {}; give the full URL of the one to share",
+                    wanted
+                )
+                .into()),
+            }
+        }
+    }
+}
+
+// Tabs on exactly `wanted`, or if none are and it names no more than an
+// origin, those on that origin. Never a prefix match, so a peer asking for
+// `https://a` can't take whichever tab's URL happens to start that way.
+fn tabs_on(tabs: Vec<ChromeTab>, wanted: &Url) -> Vec<ChromeTab> {
+    let parsed: Vec<(ChromeTab, Option<Url>)> = tabs
+        .into_iter()
+        .map(|t| {
+            let url = Url::parse(&t.url).ok();
+            (t, url)
+        })
+        .collect();
+    let exact: Vec<ChromeTab> = parsed
+        .iter()
+        .filter(|(_, url)| url.as_ref() == Some(wanted))
+        .map(|(t, _)| t.clone())
+        .collect();
+    let origin_only =
+        wanted.path() == "/" && wanted.query().is_none() && wanted.fragment().is_none();
+    if !exact.is_empty() || !origin_only {
+        return exact;
+    }
+    parsed
+        .into_iter()
+        .filter(|(_, url)| url.as_ref().is_some_and(|u| u.origin() == wanted.origin()))
+        .map(|(t, _)| t)
+        .collect()
+}
+
+/// Share `target` over `server` without a window: wait for the first peer,
+/// send the grant, then take `share` / `revoke` / `peers` / `quit` commands
+/// from stdin. Without a terminal on stdin it keeps serving until the
+/// process is signalled. Only cookies matching `share_domains` go out, along
+/// with the tab's `localStorage` when `include_storage` is set.
+pub async fn run(
+    browser: &dyn Browser,
+    server: &Broadcasts,
+    target: &ShareTarget,
+    share_domains: &[String],
+    include_storage: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let settings = Settings::load();
+    let tab = resolve_target(browser, target).await?;
+    println!(
+        "Sharing “{}” ({}) from {}",
+        tab.title,
+        tab.url,
+        server.addr()
+    );
+
+    println!("Waiting for a peer to connect…");
+    while server.peers().is_empty() {
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    let share_tab = || {
+        share(
+            browser,
+            server,
+            &tab,
+            &settings,
+            share_domains,
+            include_storage,
+        )
+    };
+    let mut shared = share_tab().await?;
+
+    println!("Commands: share, revoke, peers, quit");
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        match line.trim() {
+            "share" => shared = share_tab().await?,
+            "revoke" => {
+                let revoke = revoke_for_tab(&tab, &shared);
+                match server.revokes.send(revoke) {
+                    Ok(peers) => println!("Sent revoke to {} peer(s)", peers),
+                    Err(_) => println!("No peers connected — revoke not delivered"),
+                }
+            }
+            "peers" => {
+                for peer in server.peers() {
+                    println!("  {}", peer);
+                }
+            }
+            "quit" | "exit" => return Ok(()),
+            "" => {}
+            other => println!(
+                "Unknown command {:?}; try share, revoke, peers or quit",
+                other
+            ),
+        }
+    }
+    println!("stdin closed; still serving until interrupted");
+    std::future::pending().await
+}
+
+// Capture the tab's cookies now and grant them, returning what was sent so
+// a later revoke covers the same set.
+async fn share(
+    browser: &dyn Browser,
+    server: &Broadcasts,
+    tab: &ChromeTab,
+    settings: &Settings,
+    share_domains: &[String],
+    include_storage: bool,
+) -> Result<Vec<Cookie>, Box<dyn Error + Send + Sync>> {
+    let cookies = filter_cookies(browser.get_cookies_for_tab(tab).await?, share_domains);
+    let grant = grant_for_tab(
+        browser,
+        tab,
+        cookies.clone(),
+        settings.include_indexed_db,
+        include_storage,
+        settings.encrypt_grants,
+    )
+    .await;
+    match server.grants.send(grant) {
+        Ok(peers) => println!(
+            "Sent grant of {} cookie(s) to {} peer(s)",
+            cookies.len(),
+            peers
+        ),
+        Err(_) => println!("No peers connected — grant not delivered"),
+    }
+    Ok(cookies)
+}
@@ -0,0 +1,189 @@
+use global_hotkey::hotkey::{Code, HotKey, Modifiers};
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A system-wide key combination such as `Ctrl+Shift+S`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub super_key: bool,
+    pub key: HotkeyKey,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HotkeyKey {
+    /// An ASCII letter (stored lowercase) or digit.
+    Char(char),
+    /// F1 through F24.
+    F(u8),
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut hotkey = Hotkey {
+            ctrl: false,
+            shift: false,
+            alt: false,
+            super_key: false,
+            key: HotkeyKey::F(0),
+        };
+        let parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let (key, modifiers) = parts.split_last().ok_or("empty hotkey")?;
+        for modifier in modifiers {
+            let flag = match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut hotkey.ctrl,
+                "shift" => &mut hotkey.shift,
+                "alt" | "option" => &mut hotkey.alt,
+                "super" | "cmd" | "win" | "meta" => &mut hotkey.super_key,
+                other => return Err(format!("unknown modifier {:?}", other)),
+            };
+            *flag = true;
+        }
+        hotkey.key = match key.to_ascii_lowercase().as_str() {
+            k if k.len() == 1 && k.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                HotkeyKey::Char(k.chars().next().unwrap())
+            }
+            k => match k.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n @ 1..=24) => HotkeyKey::F(n),
+                _ => return Err(format!("unsupported key {:?}", key)),
+            },
+        };
+        if !(hotkey.ctrl || hotkey.alt || hotkey.super_key || matches!(hotkey.key, HotkeyKey::F(_)))
+        {
+            return Err("a global hotkey needs Ctrl, Alt or Super, or an F key".into());
+        }
+        Ok(hotkey)
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (on, name) in [
+            (self.ctrl, "Ctrl+"),
+            (self.shift, "Shift+"),
+            (self.alt, "Alt+"),
+            (self.super_key, "Super+"),
+        ] {
+            if on {
+                f.write_str(name)?;
+            }
+        }
+        match self.key {
+            HotkeyKey::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            HotkeyKey::F(n) => write!(f, "F{}", n),
+        }
+    }
+}
+
+impl Hotkey {
+    fn to_global(self) -> Result<HotKey, Box<dyn Error>> {
+        let code = match self.key {
+            HotkeyKey::Char(c) if c.is_ascii_digit() => format!("Digit{}", c),
+            HotkeyKey::Char(c) => format!("Key{}", c.to_ascii_uppercase()),
+            HotkeyKey::F(n) => format!("F{}", n),
+        };
+        let code: Code = code
+            .parse()
+            .map_err(|_| format!("{} has no key code", self))?;
+        let mut modifiers = Modifiers::empty();
+        for (on, modifier) in [
+            (self.ctrl, Modifiers::CONTROL),
+            (self.shift, Modifiers::SHIFT),
+            (self.alt, Modifiers::ALT),
+            (self.super_key, Modifiers::SUPER),
+        ] {
+            if on {
+                modifiers |= modifier;
+            }
+        }
+        Ok(HotKey::new(Some(modifiers), code))
+    }
+}
+
+/// A hotkey registered with `register`; dropping it releases the key.
+pub struct HotkeyGrab {
+    _manager: GlobalHotKeyManager,
+}
+
+/// Grab `hotkey` for the whole desktop and call `on_press` each time it is
+/// pressed, on a thread of the platform's choosing.
+///
+/// Call this from the thread running the window's event loop: macOS only
+/// delivers hotkeys to the main thread's loop, and Windows to the thread that
+/// registered them. On Linux only X11 is supported, as Wayland compositors
+/// don't let clients grab keys globally.
+pub fn register(
+    hotkey: &Hotkey,
+    on_press: impl Fn() + Send + Sync + 'static,
+) -> Result<HotkeyGrab, Box<dyn Error>> {
+    #[cfg(target_os = "linux")]
+    if std::env::var_os("DISPLAY").is_none() {
+        return Err(
+            "no X11 display for the global hotkey; Wayland sessions need \
+                    the compositor to bind it instead"
+                .into(),
+        );
+    }
+    let global = hotkey.to_global()?;
+    let manager = GlobalHotKeyManager::new()?;
+    manager.register(global).map_err(|e| match e {
+        global_hotkey::Error::AlreadyRegistered(_) => {
+            format!("{} is already taken by another application", hotkey)
+        }
+        e => format!("could not grab {}: {}", hotkey, e),
+    })?;
+    let id = global.id();
+    GlobalHotKeyEvent::set_event_handler(Some(move |event: GlobalHotKeyEvent| {
+        if event.id == id && event.state == HotKeyState::Pressed {
+            on_press();
+        }
+    }));
+    tracing::info!("Listening for {} globally", hotkey);
+    Ok(HotkeyGrab { _manager: manager })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hotkeys_parse_and_round_trip() {
+        let hotkey: Hotkey = "ctrl + shift + s".parse().unwrap();
+        assert!(hotkey.ctrl && hotkey.shift && !hotkey.alt);
+        assert_eq!(hotkey.key, HotkeyKey::Char('s'));
+        assert_eq!(hotkey.to_string(), "Ctrl+Shift+S");
+        assert_eq!("F9".parse::<Hotkey>().unwrap().key, HotkeyKey::F(9));
+        assert_eq!(
+            "Cmd+Alt+1".parse::<Hotkey>().unwrap().to_string(),
+            "Alt+Super+1"
+        );
+
+        assert!("Shift+S".parse::<Hotkey>().is_err());
+        assert!("Ctrl+Hyper+S".parse::<Hotkey>().is_err());
+        assert!("Ctrl+Space".parse::<Hotkey>().is_err());
+        assert!("Ctrl+F25".parse::<Hotkey>().is_err());
+    }
+
+    #[test]
+    fn hotkeys_map_onto_global_hotkey_codes() {
+        let global = |s: &str| s.parse::<Hotkey>().unwrap().to_global().unwrap();
+        assert_eq!(
+            global("Ctrl+Shift+S"),
+            HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::KeyS)
+        );
+        assert_eq!(
+            global("Cmd+Alt+1"),
+            HotKey::new(Some(Modifiers::SUPER | Modifiers::ALT), Code::Digit1)
+        );
+        assert_eq!(
+            global("F24"),
+            HotKey::new(Some(Modifiers::empty()), Code::F24)
+        );
+    }
+}
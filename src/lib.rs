@@ -1,3 +1,15 @@
+pub mod audit;
+pub mod browser;
+pub mod cdp;
 pub mod chrome;
+pub mod config;
+pub mod crypto;
+pub mod discovery;
 pub mod gui;
+pub mod headless;
+pub mod hotkey;
+pub mod logging;
 pub mod network;
+pub mod proxy;
+pub mod settings;
+pub mod tls;
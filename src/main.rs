@@ -1,39 +1,566 @@
 use std::error::Error;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use eframe::{App, CreationContext};
 use eframe::{NativeOptions, run_native};
-use egui::Vec2;
-use egui::ViewportBuilder;
-use sharekaro::chrome::{launch_chrome_with_cdp, listen_tabs_ws};
-use sharekaro::gui::ChromeTabApp;
-use sharekaro::network::spawn_server;
+use sharekaro::audit::AuditOp;
+use sharekaro::browser::{Browser, ChromeBrowser, FirefoxBrowser};
+use sharekaro::chrome::{
+    CdpEndpoint, ChromeHandle, ReconnectPolicy, fetch_tabs, launch_chrome_with_cdp,
+    match_cookies_to_url, universal_cookie_loader,
+};
+use sharekaro::config::Config;
+use sharekaro::gui::{AppOptions, ChromeTabApp};
+use sharekaro::headless::ShareTarget;
+use sharekaro::network::{
+    Broadcasts, PullOptions, ReceiverState, ServerOptions, connect_client, peer_url, received_path,
+    spawn_server,
+};
+use sharekaro::settings::Settings;
+use sharekaro::tls::load_identity;
 use tokio::runtime::{Handle, Runtime};
 
 #[derive(Parser)]
 struct Args {
+    /// Start the browser on its own existing profile instead of a throwaway
+    /// one: `default` for Chrome's, or brave-default, edge-default or
+    /// chromium-default, which also pick that browser to launch.
     #[arg(long)]
     profile: Option<String>,
+    /// Extra flag for the Chrome we start, e.g. --chrome-arg=--headless=new.
+    /// Repeatable; passed through verbatim after ShareKaro's own flags, so
+    /// whether Chrome accepts it is up to Chrome.
+    #[arg(long = "chrome-arg", value_name = "FLAG", allow_hyphen_values = true)]
+    chrome_args: Vec<String>,
+    #[arg(long)]
+    proxy: Option<String>,
+    /// Host serving the browser's DevTools endpoint, e.g. the far end of an SSH tunnel.
+    #[arg(long, default_value = "localhost")]
+    cdp_host: String,
+    #[arg(long, default_value_t = 9222)]
+    cdp_port: u16,
+    #[arg(long, default_value_t = 2000)]
+    cookie_cache_ttl_ms: u64,
+    /// Filename for cookie exports, with {title}, {domain}, {id}, {date} and
+    /// {ext} filled in. Defaults to the saved setting, then cookies_{title}.{ext}.
+    #[arg(long)]
+    export_template: Option<String>,
+    /// Ping Chrome's event socket after this much silence, and reconnect if
+    /// the ping goes unanswered for as long again.
+    #[arg(long, default_value_t = 10_000)]
+    cdp_keepalive_ms: u64,
+    /// Upper bound on the delay between event socket reconnect attempts.
+    #[arg(long, default_value_t = 30_000)]
+    cdp_max_backoff_ms: u64,
+    /// Ping the peer being received from this often, and reconnect (with
+    /// backoff) if a ping goes unanswered until the next one.
+    #[arg(long, default_value_t = 10_000)]
+    peer_ping_ms: u64,
+    /// Also receive shares from this peer (host:port or ws:// URL) from startup.
+    #[arg(long)]
+    peer: Option<String>,
+    /// Share Chrome's active tab whenever this key combination is pressed,
+    /// even while another window has focus (e.g. Ctrl+Shift+S). Overrides
+    /// the saved setting.
+    #[arg(long)]
+    share_hotkey: Option<String>,
+    /// Share server to run, as NAME=ADDR or just ADDR. Repeat to run several
+    /// independent servers, e.g. --serve work=0.0.0.0:9234 --serve home=0.0.0.0:9235;
+    /// the GUI picks which one each share goes out on.
+    #[arg(long = "serve", visible_alias = "listen", value_name = "NAME=ADDR",
+          value_parser = parse_server, default_value = "default=0.0.0.0:9234")]
+    servers: Vec<(String, SocketAddr)>,
+    /// Run without a window, sharing the tab picked by --share or --share-url
+    /// over the first --serve server. Commands are read from stdin.
+    #[arg(long, requires = "share_target")]
+    no_gui: bool,
+    /// Only share cookies whose domain matches one of these globs, e.g.
+    /// --share-domains '*.openai.com,chatgpt.com'. Shares everything if unset.
+    #[arg(long, value_delimiter = ',', value_name = "GLOB,...")]
+    share_domains: Vec<String>,
+    /// Let peers ask for a tab by URL and send them its cookies (narrowed by
+    /// --share-domains) without waiting for Share to be pressed.
+    #[arg(long)]
+    allow_pull: bool,
+    /// Send each shared tab's localStorage along with its cookies, for apps
+    /// that keep their login token there. Not done for pulled tabs.
+    #[arg(long)]
+    include_storage: bool,
+    /// When a peer revokes a share, close the tab it opened here as well as
+    /// deleting its cookies.
+    #[arg(long)]
+    close_on_revoke: bool,
+    /// Don't advertise the share servers on the LAN over mDNS.
+    #[arg(long)]
+    no_mdns: bool,
+    /// How many grants, revokes and navigations a slow peer may fall behind
+    /// by before it starts missing them. Defaults to 64.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    channel_capacity: Option<u16>,
+    /// Tab to share in --no-gui mode, by its position in Chrome's tab list.
+    #[arg(long, group = "share_target", value_name = "TAB_INDEX")]
+    share: Option<usize>,
+    /// Tab to share in --no-gui mode: the one on exactly this URL, or on this
+    /// origin if only that is given.
+    #[arg(long, group = "share_target")]
+    share_url: Option<String>,
+    /// Passphrase shared with peers out of band. Grants, and mirrored
+    /// navigations that carry cookies, are then always encrypted under a key
+    /// only holders of the same secret can derive, and unencrypted ones are
+    /// refused. It must be hard to guess, e.g. 5 or more random words; weaker
+    /// ones are rejected at startup.
+    #[arg(long, env = "SHAREKARO_SECRET", hide_env_values = true)]
+    secret: Option<String>,
+    /// Token peers must send before the server shares anything with them, and
+    /// that this instance sends when receiving.
+    #[arg(long, env = "SHAREKARO_AUTH_TOKEN", hide_env_values = true)]
+    auth_token: Option<String>,
+    /// Serve wss:// with this PEM certificate (chain). Needs --tls-key.
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key for --tls-cert.
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// Only accept a wss:// peer presenting the certificate with this SHA-256
+    /// fingerprint (as printed by the serving side), e.g. a self-signed one.
+    #[arg(long, value_name = "SHA256")]
+    pin_cert: Option<String>,
+    /// Which browser --cdp-host/--cdp-port point at. Firefox must already be
+    /// running with --remote-debugging-port and is only supported with --no-gui.
+    #[arg(long, value_parser = ["chrome", "firefox"], default_value = "chrome")]
+    browser: String,
+    /// List extension background pages, service workers and DevTools windows
+    /// alongside ordinary tabs. For debugging.
+    #[arg(long)]
+    show_all_targets: bool,
+    /// TOML file supplying defaults for --cdp-port, --serve, --auth-token,
+    /// --secret, --browser and --close-on-revoke. Defaults to config.toml in ShareKaro's config
+    /// directory; flags given here still win.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
+
+#[derive(Subcommand)]
+enum Command {
+    /// Report which cookies in a file would be sent to a URL, and why not.
+    ValidateCookies {
+        file: PathBuf,
+        #[arg(long)]
+        url: String,
+    },
+    /// Print the log of delivered grants and revokes.
+    Audit {
+        /// Only show entries whose URL contains this text.
+        #[arg(long)]
+        url: Option<String>,
+        /// Only show entries delivered to peers whose address contains this text.
+        #[arg(long)]
+        peer: Option<String>,
+        #[arg(long, value_parser = ["grant", "revoke"])]
+        op: Option<String>,
+        /// Print matching records as raw JSON lines.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Receive shares from a peer (host:port or ws:// URL) without the window,
+    /// importing each grant into Chrome and logging it, until interrupted.
+    Connect { peer: String },
+    /// Print the browser's open tabs as a JSON array, e.g. to pick one with
+    /// jq for --share-url. The browser must already be running.
+    List,
+}
+
 fn main() -> Result<(), eframe::Error> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    sharekaro::logging::init();
+    if let Err(e) = apply_config(&mut args, &matches) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    if let Some(Command::Connect { peer }) = &args.command {
+        receive(&args, peer);
+        return Ok(());
+    }
+    if let Some(command) = &args.command {
+        let result = match command {
+            Command::ValidateCookies { file, url } => {
+                validate_cookies(file, url).map_err(|e| e as Box<dyn Error>)
+            }
+            Command::Audit {
+                url,
+                peer,
+                op,
+                json,
+            } => print_audit(url.as_deref(), peer.as_deref(), op.as_deref(), *json),
+            Command::List => list_tabs(&args).map_err(|e| e as Box<dyn Error>),
+            Command::Connect { .. } => unreachable!("handled above"),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let firefox = args.browser == "firefox";
+    if firefox && !args.no_gui {
+        // The window is built on CDP events, tab mirroring and friends.
+        eprintln!("Error: --browser firefox needs --no-gui");
+        std::process::exit(1);
+    }
     let rt = Runtime::new().expect("Failed to create Tokio runtime");
     let handle: Handle = rt.handle().clone();
 
-    let (_child, _temp_profile) = launch_chrome_with_cdp(args.profile.clone());
+    let cdp = cdp_endpoint(&args);
+    // A remote CDP host means someone else's Chrome; don't start one locally.
+    let chrome = if cdp.is_local() && !firefox {
+        rt.block_on(launch_chrome_with_cdp(
+            args.profile.clone(),
+            &cdp,
+            &args.chrome_args,
+        ))
+        .inspect_err(|e| eprintln!("Error: {}", e))
+        .ok()
+    } else {
+        None
+    };
+    let chrome = Arc::new(Mutex::new(chrome));
+
+    let tls = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => match load_identity(cert, key) {
+            Ok(identity) => {
+                println!("TLS certificate fingerprint: {}", identity.fingerprint);
+                Some(identity.config)
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                stop_chrome(&chrome);
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+    let server_options = ServerOptions {
+        secret: args.secret.clone(),
+        tls,
+        auth_token: args.auth_token.clone(),
+        pull: args.allow_pull.then(|| PullOptions {
+            browser: if firefox {
+                Arc::new(FirefoxBrowser::new(cdp.clone()))
+            } else {
+                Arc::new(ChromeBrowser::new(cdp.clone()))
+            },
+            share_domains: args.share_domains.clone(),
+            encrypted: Settings::load().encrypt_grants,
+        }),
+        advertise: !args.no_mdns,
+        channel_capacity: args.channel_capacity.map(usize::from),
+    };
+    let mut servers = Vec::new();
+    for (name, addr) in &args.servers {
+        match rt.block_on(spawn_server(*addr, server_options.clone())) {
+            Ok(broadcasts) => {
+                println!("Server {} listening on {}", name, broadcasts.addr());
+                servers.push((name.clone(), broadcasts));
+            }
+            Err(e) => {
+                eprintln!("Error: could not start server {} on {}: {}", name, addr, e);
+                if e.kind() == std::io::ErrorKind::AddrInUse {
+                    eprintln!(
+                        "Pick another port with --serve {}=HOST:PORT, or port 0 for any free one",
+                        name
+                    );
+                }
+                stop_chrome(&chrome);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Ctrl+C / SIGTERM and closing the window both end up in the same cleanup.
+    let signal_servers = servers.clone();
+    let signal_chrome = Arc::clone(&chrome);
+    rt.spawn(async move {
+        shutdown_signal().await;
+        println!("Shutting down");
+        shutdown_servers(&signal_servers).await;
+        stop_chrome(&signal_chrome);
+        std::process::exit(0);
+    });
+    if args.no_gui {
+        let target = match (args.share, &args.share_url) {
+            (Some(index), _) => ShareTarget::Index(index),
+            (None, Some(url)) => ShareTarget::Url(url.clone()),
+            (None, None) => unreachable!("clap requires --share or --share-url"),
+        };
+        let browser: Box<dyn Browser> = if firefox {
+            Box::new(FirefoxBrowser::new(cdp))
+        } else {
+            Box::new(ChromeBrowser::new(cdp))
+        };
+        let result = rt.block_on(sharekaro::headless::run(
+            browser.as_ref(),
+            &servers[0].1,
+            &target,
+            &args.share_domains,
+            args.include_storage,
+        ));
+        rt.block_on(shutdown_servers(&servers));
+        stop_chrome(&chrome);
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    let (grant_tx, revoke_tx) = rt.block_on(spawn_server("0.0.0.0:9234".parse().unwrap()));
+    let app_servers = servers.clone();
+    let app_chrome = Arc::clone(&chrome);
 
     let app_factory =
         move |cc: &CreationContext<'_>| -> Result<Box<dyn App>, Box<dyn Error + Send + Sync>> {
             Ok(Box::new(ChromeTabApp::new(
                 cc,
-                grant_tx.clone(),
-                revoke_tx.clone(),
+                cdp.clone(),
+                app_servers.clone(),
+                AppOptions {
+                    proxy: args.proxy.clone(),
+                    cookie_ttl: Duration::from_millis(args.cookie_cache_ttl_ms),
+                    export_template: args.export_template.clone(),
+                    connect_to: args.peer.clone(),
+                    share_hotkey: args.share_hotkey.clone(),
+                    chrome: Arc::clone(&app_chrome),
+                    secret: args.secret.clone(),
+                    pin_cert: args.pin_cert.clone(),
+                    auth_token: args.auth_token.clone(),
+                    share_domains: args.share_domains.clone(),
+                    include_storage: args.include_storage,
+                    close_on_revoke: args.close_on_revoke,
+                    received_path: sharekaro::network::received_path(),
+                    cdp_events: ReconnectPolicy {
+                        keepalive: Duration::from_millis(args.cdp_keepalive_ms),
+                        max_backoff: Duration::from_millis(args.cdp_max_backoff_ms),
+                        ..ReconnectPolicy::default()
+                    },
+                    peer_reconnect: peer_reconnect(&args),
+                },
                 handle.clone(),
             )))
         };
 
-    run_native("ShareKaro", NativeOptions::default(), Box::new(app_factory))?;
+    let result = run_native("ShareKaro", NativeOptions::default(), Box::new(app_factory));
+    rt.block_on(shutdown_servers(&servers));
+    stop_chrome(&chrome);
+    result
+}
+
+// `connect`: the receiving half of the window, on the terminal. Grants are
+// imported as they arrive (import_grant logs each one) and revokes applied.
+fn receive(args: &Args, peer: &str) {
+    let rt = Runtime::new().expect("Failed to create Tokio runtime");
+    let cdp = cdp_endpoint(args);
+    let chrome = if cdp.is_local() {
+        rt.block_on(launch_chrome_with_cdp(
+            args.profile.clone(),
+            &cdp,
+            &args.chrome_args,
+        ))
+        .inspect_err(|e| eprintln!("Error: {}", e))
+        .ok()
+    } else {
+        None
+    };
+    let chrome = Mutex::new(chrome);
+    let state = Arc::new(Mutex::new(ReceiverState {
+        open_in_background: Settings::load().open_in_background,
+        secret: args.secret.clone(),
+        pin_cert: args.pin_cert.clone(),
+        auth_token: args.auth_token.clone(),
+        reconnect: peer_reconnect(args),
+        close_on_revoke: args.close_on_revoke,
+        ..ReceiverState::default()
+    }));
+    if let Some(path) = received_path() {
+        let open_tabs = rt.block_on(fetch_tabs(&cdp)).ok();
+        state.lock().unwrap().persist_at(path, open_tabs.as_deref());
+    }
+    let url = peer_url(peer);
+    let result = rt.block_on(async {
+        tokio::select! {
+            result = connect_client(cdp, url.clone(), args.proxy.clone(), Arc::clone(&state)) => result,
+            _ = shutdown_signal() => Ok(()),
+        }
+    });
+    let received = state.lock().unwrap().received.len();
+    println!("Received {} share(s) from {}", received, url);
+    stop_chrome(&chrome);
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// Fill in whatever the command line left unset (or at its default) from the
+// config file.
+fn apply_config(args: &mut Args, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let config = Config::load(args.config.as_deref())?;
+    let defaulted = |id| matches.value_source(id) == Some(ValueSource::DefaultValue);
+    if let Some(port) = config.cdp_port
+        && defaulted("cdp_port")
+    {
+        args.cdp_port = port;
+    }
+    if let Some(listen) = config.listen
+        && defaulted("servers")
+    {
+        let server = parse_server(&listen).map_err(|e| format!("listen in config: {}", e))?;
+        args.servers = vec![server];
+    }
+    if let Some(browser) = config.browser
+        && defaulted("browser")
+    {
+        if browser != "chrome" && browser != "firefox" {
+            return Err(format!(
+                "browser in config must be chrome or firefox, not {:?}",
+                browser
+            )
+            .into());
+        }
+        args.browser = browser;
+    }
+    args.close_on_revoke |= config.close_on_revoke.unwrap_or(false);
+    args.auth_token = args.auth_token.take().or(config.auth_token);
+    args.secret = args.secret.take().or(config.secret);
+    if let Some(secret) = &args.secret {
+        sharekaro::crypto::check_secret(secret)?;
+    }
+    Ok(())
+}
+
+fn cdp_endpoint(args: &Args) -> CdpEndpoint {
+    CdpEndpoint {
+        all_targets: args.show_all_targets,
+        ..CdpEndpoint::new(args.cdp_host.clone(), args.cdp_port)
+    }
+}
+
+fn peer_reconnect(args: &Args) -> ReconnectPolicy {
+    ReconnectPolicy {
+        keepalive: Duration::from_millis(args.peer_ping_ms),
+        ..ReconnectPolicy::default()
+    }
+}
+
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+async fn shutdown_servers(servers: &[(String, Broadcasts)]) {
+    futures::future::join_all(servers.iter().map(|(_, s)| s.shutdown(SHUTDOWN_GRACE))).await;
+}
+
+fn parse_server(spec: &str) -> Result<(String, SocketAddr), String> {
+    let (name, addr) = spec.split_once('=').unwrap_or((spec, spec));
+    let addr = addr
+        .parse()
+        .map_err(|e| format!("{:?} is not a host:port address: {}", addr, e))?;
+    if name.trim().is_empty() {
+        return Err("server name is empty".into());
+    }
+    Ok((name.trim().to_string(), addr))
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut term = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+// Whichever of the signal handler, the window and main gets here first does
+// the cleanup; the others find the handle already taken.
+fn stop_chrome(chrome: &Mutex<Option<ChromeHandle>>) {
+    if let Some(handle) = chrome.lock().unwrap().take() {
+        handle.stop();
+    }
+}
+
+fn validate_cookies(file: &Path, url: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let cookies = universal_cookie_loader(file)?;
+    let results = match_cookies_to_url(&cookies, url)?;
+    let (applicable, rejected): (Vec<_>, Vec<_>) = results.iter().partition(|(_, r)| r.is_ok());
+
+    println!("Applicable ({}):", applicable.len());
+    for (cookie, _) in &applicable {
+        println!("  ✔ {}  {}{}", cookie.name, cookie.domain, cookie.path);
+    }
+    println!("Not applicable ({}):", rejected.len());
+    for (cookie, reason) in &rejected {
+        if let Err(reason) = reason {
+            println!(
+                "  ✘ {}  {}{}  — {}",
+                cookie.name, cookie.domain, cookie.path, reason
+            );
+        }
+    }
+    Ok(())
+}
+
+fn list_tabs(args: &Args) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let cdp = cdp_endpoint(args);
+    let browser: Box<dyn Browser> = if args.browser == "firefox" {
+        Box::new(FirefoxBrowser::new(cdp))
+    } else {
+        Box::new(ChromeBrowser::new(cdp))
+    };
+    let tabs = Runtime::new()?.block_on(browser.fetch_tabs())?;
+    println!("{}", serde_json::to_string_pretty(&tabs)?);
+    Ok(())
+}
+
+fn print_audit(
+    url: Option<&str>,
+    peer: Option<&str>,
+    op: Option<&str>,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    let records = sharekaro::audit::read_all()?;
+    let matching = records.iter().filter(|r| {
+        url.is_none_or(|u| r.url.contains(u))
+            && peer.is_none_or(|p| r.peer.contains(p))
+            && op.is_none_or(|o| match r.op {
+                AuditOp::Grant => o == "grant",
+                AuditOp::Revoke => o == "revoke",
+            })
+    });
+    for record in matching {
+        if json {
+            println!("{}", serde_json::to_string(record)?);
+        } else {
+            println!(
+                "{}  {:<6} {:<21} {:>3} cookies  {}  [{}]",
+                record.ts.format("%Y-%m-%d %H:%M:%S"),
+                format!("{:?}", record.op).to_lowercase(),
+                record.peer,
+                record.cookie_count,
+                record.url,
+                record.op_id
+            );
+        }
+    }
     Ok(())
 }
@@ -1,22 +1,173 @@
+use chrono::Utc;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::sync::Mutex;
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use tokio::{net::TcpListener, sync::broadcast};
-use tokio_tungstenite::{accept_async, connect_async, tungstenite::Message};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use std::{net::SocketAddr, sync::Arc};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc, watch},
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::{
+    Connector, MaybeTlsStream, WebSocketStream, accept_async, client_async_tls_with_config,
+    connect_async_tls_with_config,
+    tungstenite::Message,
+    tungstenite::protocol::{CloseFrame, frame::coding::CloseCode},
+};
+use tracing::{debug, error, info, trace, warn};
+use url::Url;
+
+use crate::audit::{AuditOp, AuditRecord};
+use crate::chrome::CdpEndpoint;
+use crate::crypto::{Handshake, SessionKey};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct GrantMessage {
     pub tab_id: String,
     pub url: String,
     pub cookies: Vec<crate::chrome::Cookie>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indexed_db: Vec<crate::chrome::IndexedDbEntry>,
+    /// The tab's `localStorage` items, set on the receiving tab after it
+    /// loads. Only captured with `--include-storage`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub storage: HashMap<String, String>,
+    /// Seal this grant with the connection's session key instead of sending
+    /// it as plain JSON. Peers that never completed the key exchange are
+    /// skipped rather than sent plaintext.
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Load the tab in whichever tab the receiver is looking at, instead of
+    /// opening a new one.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub open_in_current: bool,
+    #[serde(default)]
+    pub op_id: String,
+    #[serde(flatten)]
+    pub source: Option<GrantSource>,
+}
+
+/// Where a grant was captured, so a receiver juggling several peers can tell
+/// which machine and tab a cookie set came from. Sent as the flat fields
+/// `source_host`, `source_title` and `captured_at`; older senders omit them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GrantSource {
+    pub source_host: String,
+    pub source_title: String,
+    pub captured_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl GrantSource {
+    pub fn for_tab(tab: &crate::chrome::ChromeTab) -> Self {
+        Self {
+            source_host: gethostname::gethostname().to_string_lossy().into_owned(),
+            source_title: tab.title.clone(),
+            captured_at: chrono::Utc::now(),
+        }
+    }
+}
+
+impl std::fmt::Display for GrantSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} · “{}” · captured {}",
+            self.source_host,
+            self.source_title,
+            self.captured_at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+        )
+    }
+}
+
+/// The grant for sharing `tab` with `cookies`, capturing its IndexedDB and
+/// `localStorage` too when asked. Shared by the GUI's Share button and
+/// headless mode.
+pub async fn grant_for_tab(
+    browser: &dyn crate::browser::Browser,
+    tab: &crate::chrome::ChromeTab,
+    cookies: Vec<crate::chrome::Cookie>,
+    include_indexed_db: bool,
+    include_storage: bool,
+    encrypted: bool,
+) -> GrantMessage {
+    let indexed_db = if include_indexed_db {
+        browser.capture_indexed_db(tab).await.unwrap_or_else(|e| {
+            warn!("IndexedDB capture failed: {}", e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    let storage = if include_storage {
+        browser
+            .capture_local_storage(tab)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("localStorage capture failed: {}", e);
+                HashMap::new()
+            })
+    } else {
+        HashMap::new()
+    };
+    GrantMessage {
+        tab_id: tab.id.clone(),
+        url: tab.url.clone(),
+        cookies,
+        indexed_db,
+        storage,
+        encrypted,
+        open_in_current: false,
+        op_id: crate::audit::new_op_id(),
+        source: Some(GrantSource::for_tab(tab)),
+    }
+}
+
+/// The revoke that undoes a grant of `cookies` from `tab`.
+pub fn revoke_for_tab(
+    tab: &crate::chrome::ChromeTab,
+    cookies: &[crate::chrome::Cookie],
+) -> RevokeMessage {
+    RevokeMessage {
+        tab_id: tab.id.clone(),
+        url: tab.url.clone(),
+        cookies: cookies
+            .iter()
+            .map(|c| RevokeCookie {
+                name: c.name.clone(),
+                domain: c.domain.clone(),
+                path: c.path.clone(),
+            })
+            .collect(),
+        whole_site: false,
+        op_id: crate::audit::new_op_id(),
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct RevokeMessage {
     pub tab_id: String,
+    #[serde(default)]
+    pub url: String,
     pub cookies: Vec<RevokeCookie>,
+    /// Delete every cookie for `url` and clear its site data, not just
+    /// `cookies`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub whole_site: bool,
+    #[serde(default)]
+    pub op_id: String,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -26,130 +177,1450 @@ pub struct RevokeCookie {
     pub path: String,
 }
 
-pub async fn spawn_server(
+/// Sent back by a receiver once it has applied a revoke: `deleted` is how
+/// many of the revoke's cookies its browser confirmed deleting. `tab_id` is
+/// the sender's id, as in the revoke.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RevokeAck {
+    pub tab_id: String,
+    pub deleted: usize,
+    #[serde(default)]
+    pub op_id: String,
+}
+
+/// Sent by a receiver to ask for the cookies of the server's tab on `url`
+/// instead of waiting for it to be shared. The server answers with a
+/// `Grant`, or a `RequestRefused` carrying the reason.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RequestTab {
+    pub url: String,
+}
+
+/// Several tabs' revokes in one frame, applied by the receiver in a single
+/// pass so a group of tabs loses access together.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RevokeBatchMessage {
+    pub tabs: Vec<RevokeMessage>,
+}
+
+/// Sent while a shared tab is live-mirrored: the receiver navigates its copy
+/// of `tab_id` to `url`, first setting `cookies` if the sender's changed.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NavigateMessage {
+    pub tab_id: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cookies: Vec<crate::chrome::Cookie>,
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+/// One running share server: senders for everything it fans out to its
+/// connected peers, plus its address, peer list and shutdown handle. Each
+/// `spawn_server` call is independent, so several can run side by side.
+#[derive(Clone)]
+pub struct Broadcasts {
+    pub grants: broadcast::Sender<GrantMessage>,
+    pub revokes: broadcast::Sender<RevokeMessage>,
+    pub revoke_batches: broadcast::Sender<RevokeBatchMessage>,
+    pub navigations: broadcast::Sender<NavigateMessage>,
+    /// Acknowledgements peers send back after applying a revoke.
+    pub revoke_acks: broadcast::Sender<RevokeAck>,
+    shutdown: watch::Sender<bool>,
+    // Every connection task holds a sender; `recv` returns `None` once the
+    // accept loop and all of them have exited.
+    drained: Arc<tokio::sync::Mutex<mpsc::Receiver<()>>>,
     addr: SocketAddr,
-) -> (
-    broadcast::Sender<GrantMessage>,
-    broadcast::Sender<RevokeMessage>,
-) {
-    let (grant_tx, _) = broadcast::channel(16);
-    let (revoke_tx, _) = broadcast::channel(16);
+    peers: Arc<Mutex<Vec<SocketAddr>>>,
+}
 
-    let listener = TcpListener::bind(addr)
-        .await
-        .unwrap_or_else(|e| panic!("Failed to bind server on {}: {}", addr, e));
-    println!("Server is listening on {}", addr);
+impl Broadcasts {
+    /// The address actually bound, with the real port if 0 was asked for.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Peers currently connected to this server.
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        self.peers.lock().unwrap().clone()
+    }
+
+    /// Stop accepting connections and close every peer socket with a reason,
+    /// waiting up to `grace` for the close frames to go out.
+    pub async fn shutdown(&self, grace: Duration) {
+        let _ = self.shutdown.send(true);
+        let mut drained = self.drained.lock().await;
+        if tokio::time::timeout(grace, drained.recv()).await.is_err() {
+            warn!("Some peer connections did not close in time");
+        }
+    }
+}
+
+/// How a share server talks to its peers.
+#[derive(Clone, Default)]
+pub struct ServerOptions {
+    /// With a secret, every grant and mirrored navigation is encrypted under
+    /// a key that also depends on it, so only peers given the same secret can
+    /// read them.
+    pub secret: Option<String>,
+    /// Serve `wss://` with this certificate instead of plain `ws://`.
+    pub tls: Option<Arc<rustls::ServerConfig>>,
+    /// Token each peer must present in an `Auth` frame before it is sent
+    /// anything.
+    pub auth_token: Option<String>,
+    /// Answer peers' `RequestTab`s from this browser. Requests are refused
+    /// without it.
+    pub pull: Option<PullOptions>,
+    /// Answer mDNS queries for `discovery::SERVICE` so peers on the LAN can
+    /// find the server. Never done for a loopback address.
+    pub advertise: bool,
+    /// Messages each peer may fall behind by before it misses some; see
+    /// `DEFAULT_CHANNEL_CAPACITY`.
+    pub channel_capacity: Option<usize>,
+}
+
+/// Per-peer backlog of grants, revokes and navigations. A peer further
+/// behind than this skips the oldest ones, which is logged.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 64;
 
-    let grant_tx_clone = grant_tx.clone();
-    let revoke_tx_clone = revoke_tx.clone();
+/// Where a server finds the tabs peers pull, and which of their cookies may
+/// go out.
+#[derive(Clone)]
+pub struct PullOptions {
+    pub browser: Arc<dyn crate::browser::Browser + Send + Sync>,
+    /// Domain allowlist, as for pushed shares; see `chrome::filter_cookies`.
+    pub share_domains: Vec<String>,
+    pub encrypted: bool,
+}
+
+/// How long a peer has to authenticate before it is dropped.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a new connection has to finish its TLS and WebSocket handshakes.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn spawn_server(addr: SocketAddr, options: ServerOptions) -> std::io::Result<Broadcasts> {
+    let capacity = options
+        .channel_capacity
+        .unwrap_or(DEFAULT_CHANNEL_CAPACITY)
+        .max(1);
+    let (grant_tx, _) = broadcast::channel::<GrantMessage>(capacity);
+    let (revoke_tx, _) = broadcast::channel::<RevokeMessage>(capacity);
+    let (revoke_batch_tx, _) = broadcast::channel::<RevokeBatchMessage>(capacity);
+    let (navigate_tx, _) = broadcast::channel::<NavigateMessage>(capacity);
+    let (ack_tx, _) = broadcast::channel::<RevokeAck>(capacity);
+    let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
+    let (alive_tx, drained_rx) = mpsc::channel::<()>(1);
+
+    let listener = TcpListener::bind(addr).await?;
+    let addr = listener.local_addr()?;
+    let scheme = if options.tls.is_some() { "wss" } else { "ws" };
+    info!("Server is listening on {}://{}", scheme, addr);
+    let tls = options.tls.map(TlsAcceptor::from);
+    let secret = options.secret;
+    let auth_token = options.auth_token;
+    let pull = options.pull;
+    if options.advertise && !addr.ip().is_loopback() {
+        tokio::spawn(crate::discovery::advertise(
+            addr.port(),
+            tls.is_some(),
+            shutdown_rx.clone(),
+        ));
+    }
+    let peers = Arc::new(Mutex::new(Vec::new()));
+    let accept_peers = Arc::clone(&peers);
+    let accept_acks = ack_tx.clone();
+
+    // The server only holds weak senders: the app side owns the channels, and
+    // once every `Broadcasts` is gone they close and the server winds down
+    // the same way as after an explicit `shutdown`.
+    let weak = WeakBroadcasts {
+        grants: grant_tx.downgrade(),
+        revokes: revoke_tx.downgrade(),
+        revoke_batches: revoke_batch_tx.downgrade(),
+        navigations: navigate_tx.downgrade(),
+    };
     tokio::spawn(async move {
         loop {
-            match listener.accept().await {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = shutdown_rx.changed() => break,
+            };
+            match accepted {
                 Ok((stream, peer)) => {
-                    println!("New connection from {}", peer);
+                    info!(%peer, "New connection");
 
-                    let mut grant_rx = grant_tx_clone.subscribe();
-                    let mut revoke_rx = revoke_tx_clone.subscribe();
-                    let ws = match accept_async(stream).await {
-                        Ok(ws) => ws,
-                        Err(e) => {
-                            eprintln!("Failed to accept WebSocket: {}", e);
-                            continue;
+                    if weak.closed() {
+                        info!("App side has gone away; server stopping");
+                        break;
+                    }
+                    let alive = alive_tx.clone();
+                    let tls = tls.clone();
+                    let peers = Arc::clone(&accept_peers);
+                    let secret = secret.clone();
+                    let pull = pull.clone();
+                    let auth_token = auth_token.clone();
+                    let weak = weak.clone();
+                    let acks = accept_acks.clone();
+                    let shutdown = shutdown_rx.clone();
+                    tokio::spawn(async move {
+                        let _alive = alive;
+                        let handshake = accept_peer(stream, tls.as_ref());
+                        let mut ws = match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await
+                        {
+                            Ok(Ok(ws)) => ws,
+                            Ok(Err(e)) => {
+                                warn!(%peer, "{}", e);
+                                return;
+                            }
+                            Err(_) => {
+                                warn!(%peer, "Handshake timed out");
+                                return;
+                            }
+                        };
+                        if let Some(token) = &auth_token
+                            && let Err(reason) = authenticate(&mut ws, token).await
+                        {
+                            warn!(%peer, "Dropping peer: {}", reason);
+                            let close = CloseFrame {
+                                code: CloseCode::Policy,
+                                reason: reason.into(),
+                            };
+                            let _ = ws.send(Message::Close(Some(close))).await;
+                            return;
                         }
-                    };
+                        let Some(feeds) = weak.subscribe(&shutdown) else {
+                            return;
+                        };
+                        peers.lock().unwrap().push(peer);
+                        serve_peer(ws, peer, feeds, &acks, secret.as_deref(), pull.as_ref()).await;
+                        peers.lock().unwrap().retain(|p| *p != peer);
+                    });
+                }
+                Err(e) => {
+                    error!("Error accepting connection: {}", e);
+                }
+            }
+        }
+        info!("Server on {} stopped accepting connections", addr);
+    });
 
-                    tokio::spawn(async move {
-                        let mut ws = ws;
-                        loop {
-                            tokio::select! {
-                                Ok(grant) = grant_rx.recv() => {
-                                    let mut msg = serde_json::to_value(&grant).unwrap();
-                                    if let Value::Object(ref mut map) = msg {
-                                        map.insert("type".into(), Value::String("Grant".into()));
-                                    }
-                                    let text = msg.to_string();
-                                    println!("Broadcasting grant: {}", text);
-                                    let _ = ws.send(Message::Text(text.into())).await;
-                                }
-                                Ok(revoke) = revoke_rx.recv() => {
-                                    let mut msg = serde_json::to_value(&revoke).unwrap();
-                                    if let Value::Object(ref mut map) = msg {
-                                        map.insert("type".into(), Value::String("Revoke".into()));
-                                    }
-                                    let text = msg.to_string();
-                                    println!("Broadcasting revoke: {}", text);
-                                    let _ = ws.send(Message::Text(text.into())).await;
+    Ok(Broadcasts {
+        grants: grant_tx,
+        revokes: revoke_tx,
+        revoke_batches: revoke_batch_tx,
+        navigations: navigate_tx,
+        revoke_acks: ack_tx,
+        shutdown: shutdown_tx,
+        drained: Arc::new(tokio::sync::Mutex::new(drained_rx)),
+        addr,
+        peers,
+    })
+}
+
+#[derive(Clone)]
+struct WeakBroadcasts {
+    grants: broadcast::WeakSender<GrantMessage>,
+    revokes: broadcast::WeakSender<RevokeMessage>,
+    revoke_batches: broadcast::WeakSender<RevokeBatchMessage>,
+    navigations: broadcast::WeakSender<NavigateMessage>,
+}
+
+impl WeakBroadcasts {
+    fn closed(&self) -> bool {
+        self.grants.upgrade().is_none()
+    }
+
+    /// `None` once the app side has dropped its senders.
+    fn subscribe(&self, shutdown: &watch::Receiver<bool>) -> Option<PeerFeeds> {
+        Some(PeerFeeds {
+            grants: self.grants.upgrade()?.subscribe(),
+            revokes: self.revokes.upgrade()?.subscribe(),
+            revoke_batches: self.revoke_batches.upgrade()?.subscribe(),
+            navigations: self.navigations.upgrade()?.subscribe(),
+            shutdown: shutdown.clone(),
+        })
+    }
+}
+
+/// Everything one peer connection listens to.
+struct PeerFeeds {
+    grants: broadcast::Receiver<GrantMessage>,
+    revokes: broadcast::Receiver<RevokeMessage>,
+    revoke_batches: broadcast::Receiver<RevokeBatchMessage>,
+    navigations: broadcast::Receiver<NavigateMessage>,
+    shutdown: watch::Receiver<bool>,
+}
+
+// Wait for the peer's `Auth` frame and check its token. The error is the
+// close reason sent back.
+async fn authenticate(ws: &mut ServerSocket, token: &str) -> Result<(), &'static str> {
+    let first = match tokio::time::timeout(AUTH_TIMEOUT, ws.next()).await {
+        Err(_) => return Err("no authentication in time"),
+        Ok(Some(Ok(Message::Text(text)))) => text,
+        Ok(_) => return Err("expected an Auth frame"),
+    };
+    let v: Value = serde_json::from_str(&first).map_err(|_| "expected an Auth frame")?;
+    if v["type"] != "Auth" {
+        return Err("expected an Auth frame");
+    }
+    match v["token"].as_str() {
+        Some(given) if tokens_match(given, token) => Ok(()),
+        _ => Err("authentication failed"),
+    }
+}
+
+// Compare without bailing at the first difference, so response timing says
+// nothing about how much of a guess was right.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    let (given, expected) = (given.as_bytes(), expected.as_bytes());
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// A peer's connection, TLS or not.
+trait PeerIo: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> PeerIo for T {}
+
+type ServerSocket = WebSocketStream<Box<dyn PeerIo>>;
+
+// The TLS handshake, if serving wss://, then the WebSocket upgrade. Run in
+// the peer's own task so a client that stalls mid-handshake holds up only
+// itself, not the accept loop.
+async fn accept_peer(stream: TcpStream, tls: Option<&TlsAcceptor>) -> Result<ServerSocket, String> {
+    let stream: Box<dyn PeerIo> = match tls {
+        Some(acceptor) => Box::new(
+            acceptor
+                .accept(stream)
+                .await
+                .map_err(|e| format!("TLS handshake failed: {}", e))?,
+        ),
+        None => Box::new(stream),
+    };
+    accept_async(stream)
+        .await
+        .map_err(|e| format!("Failed to accept WebSocket: {}", e))
+}
+
+// A closed feed means the app side dropped its senders, which is a shutdown;
+// a lagging one only means this peer missed messages.
+fn feed_closed(peer: SocketAddr, what: &str, err: &broadcast::error::RecvError) -> bool {
+    match err {
+        broadcast::error::RecvError::Closed => true,
+        broadcast::error::RecvError::Lagged(n) => {
+            warn!(%peer, "Peer fell behind and missed {} {}", n, what);
+            false
+        }
+    }
+}
+
+#[tracing::instrument(name = "peer", skip_all, fields(%peer))]
+async fn serve_peer(
+    mut ws: ServerSocket,
+    peer: SocketAddr,
+    mut feeds: PeerFeeds,
+    acks: &broadcast::Sender<RevokeAck>,
+    secret: Option<&str>,
+    pull: Option<&PullOptions>,
+) {
+    let handshake = Handshake::new();
+    let mut session_key: Option<SessionKey> = None;
+    // Set once the peer's Hello says it can inflate gzip frames.
+    let mut gzip = false;
+    let hello = tagged(
+        "Hello",
+        json!({
+            "public_key": handshake.public_key(),
+            "instance": instance_id(),
+        }),
+    );
+    let _ = ws.send(Message::Text(hello.into())).await;
+    // Pulls read the browser on their own tasks and answer through here, so
+    // a slow tab doesn't hold up this peer's other frames.
+    let (pulled_tx, mut pulled) =
+        mpsc::unbounded_channel::<(String, Result<GrantMessage, String>)>();
+    loop {
+        tokio::select! {
+            grant = feeds.grants.recv() => match grant {
+                Ok(grant) => {
+                    let encrypt = grant.encrypted || secret.is_some();
+                    send_grant(&mut ws, peer, &grant, encrypt, session_key.as_ref(), gzip).await
+                }
+                Err(e) if feed_closed(peer, "grants", &e) => break,
+                Err(_) => {}
+            },
+            revoke = feeds.revokes.recv() => match revoke {
+                Ok(revoke) => {
+                    let text = tagged("Revoke", serde_json::to_value(&revoke).unwrap());
+                    info!(tab_id = %revoke.tab_id, cookies = revoke.cookies.len(), "Broadcasting revoke");
+                    trace!("Revoke frame: {}", text);
+                    if ws.send(Message::Text(text.into())).await.is_ok() {
+                        record_delivery(AuditOp::Revoke, &revoke.op_id, &revoke.tab_id, &revoke.url, peer, revoke.cookies.len());
+                    }
+                }
+                Err(e) if feed_closed(peer, "revokes", &e) => break,
+                Err(_) => {}
+            },
+            batch = feeds.revoke_batches.recv() => match batch {
+                Ok(batch) => {
+                    let text = tagged("RevokeBatch", serde_json::to_value(&batch).unwrap());
+                    info!("Broadcasting revoke batch for {} tab(s)", batch.tabs.len());
+                    if ws.send(Message::Text(text.into())).await.is_ok() {
+                        for revoke in &batch.tabs {
+                            record_delivery(AuditOp::Revoke, &revoke.op_id, &revoke.tab_id, &revoke.url, peer, revoke.cookies.len());
+                        }
+                    }
+                }
+                Err(e) if feed_closed(peer, "revoke batches", &e) => break,
+                Err(_) => {}
+            },
+            navigate = feeds.navigations.recv() => match navigate {
+                Ok(navigate) => {
+                    let encrypt = navigate.encrypted || secret.is_some();
+                    send_navigate(&mut ws, &navigate, encrypt, session_key.as_ref()).await
+                }
+                Err(e) if feed_closed(peer, "navigations", &e) => break,
+                Err(_) => {}
+            },
+            Some((url, grant)) = pulled.recv() => match grant {
+                Ok(grant) => {
+                    info!(tab_id = %grant.tab_id, "Peer pulled {}", url);
+                    let encrypt = grant.encrypted || secret.is_some();
+                    send_grant(&mut ws, peer, &grant, encrypt, session_key.as_ref(), gzip).await
+                }
+                Err(reason) => {
+                    warn!("Refused request for {}: {}", url, reason);
+                    let refused = json!({ "url": url, "reason": reason });
+                    let _ = ws.send(Message::Text(tagged("RequestRefused", refused).into())).await;
+                }
+            },
+            _ = feeds.shutdown.changed() => break,
+            msg = ws.next() => {
+                match msg {
+                    None => {
+                        info!("Client disconnected");
+                        return;
+                    }
+                    Some(Ok(Message::Text(text))) => {
+                        let Ok(v) = serde_json::from_str::<Value>(&text) else {
+                            continue;
+                        };
+                        if let Err(e) = frame_version(&v) {
+                            warn!("Ignoring {} frame: {}", v["type"], e);
+                            continue;
+                        }
+                        if v["type"] == "Hello" {
+                            gzip = accepts_gzip(&v);
+                            if let Some(public_key) = v["public_key"].as_str() {
+                                match handshake.finish(public_key, secret) {
+                                    Ok(key) => session_key = Some(key),
+                                    Err(e) => warn!("Key exchange failed: {}", e),
                                 }
-                                msg = ws.next() => {
-                                    if msg.is_none() {
-                                        println!("Client disconnected");
-                                        break;
-                                    }
+                            }
+                        } else if v["type"] == "RevokeAck" {
+                            match serde_json::from_value::<RevokeAck>(v) {
+                                Ok(ack) => {
+                                    info!(
+                                        tab_id = %ack.tab_id,
+                                        "Peer deleted {} cookie(s)", ack.deleted
+                                    );
+                                    let _ = acks.send(ack);
                                 }
+                                Err(e) => warn!("Bad revoke ack: {}", e),
                             }
+                        } else if v["type"] == "RequestTab" {
+                            let url = v["url"].as_str().unwrap_or_default().to_string();
+                            let (pull, pulled_tx) = (pull.cloned(), pulled_tx.clone());
+                            tokio::spawn(async move {
+                                let _ = pulled_tx.send((url, pull_grant(pull, v).await));
+                            });
                         }
-                    });
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    let close = CloseFrame {
+        code: CloseCode::Away,
+        reason: "server shutting down".into(),
+    };
+    let _ = ws.send(Message::Close(Some(close))).await;
+}
+
+// Build the grant answering a peer's `RequestTab`: the one tab on the
+// requested URL or origin (see `headless::resolve_target`), narrowed to the
+// allowlist.
+async fn pull_grant(pull: Option<PullOptions>, request: Value) -> Result<GrantMessage, String> {
+    let pull = pull.ok_or("this server doesn't share tabs on request")?;
+    let request: RequestTab =
+        serde_json::from_value(request).map_err(|e| format!("bad request: {}", e))?;
+    let browser = pull.browser.as_ref();
+    let target = crate::headless::ShareTarget::Url(request.url);
+    let tab = crate::headless::resolve_target(browser, &target)
+        .await
+        .map_err(|e| e.to_string())?;
+    let cookies = browser
+        .get_cookies_for_tab(&tab)
+        .await
+        .map_err(|e| format!("could not read cookies: {}", e))?;
+    let cookies = crate::chrome::filter_cookies(cookies, &pull.share_domains);
+    Ok(grant_for_tab(browser, &tab, cookies, false, false, pull.encrypted).await)
+}
+
+/// Version of the frame format this build speaks. Bump it when a change
+/// would make older peers misread frames, not for new optional fields.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// What every frame carries next to its payload's own fields. Frames stay
+/// flat JSON objects so peers from before `version` existed can still read
+/// them; a frame without one is version 1.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Envelope<T> {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default = "first_version")]
+    pub version: u8,
+    #[serde(flatten)]
+    pub payload: T,
+}
+
+fn first_version() -> u8 {
+    1
+}
+
+fn tagged(kind: &str, msg: Value) -> String {
+    let envelope = Envelope {
+        kind: kind.to_string(),
+        version: PROTOCOL_VERSION,
+        payload: msg,
+    };
+    serde_json::to_string(&envelope).unwrap()
+}
+
+// The version a received frame was written for, or why it can't be read.
+fn frame_version(v: &Value) -> Result<u8, String> {
+    let version = match v.get("version") {
+        None => first_version(),
+        Some(version) => version
+            .as_u64()
+            .and_then(|n| u8::try_from(n).ok())
+            .ok_or_else(|| format!("bad protocol version {}", version))?,
+    };
+    if version > PROTOCOL_VERSION {
+        return Err(format!(
+            "it is protocol version {}, newer than this build's {}; upgrade ShareKaro",
+            version, PROTOCOL_VERSION
+        ));
+    }
+    Ok(version)
+}
+
+/// Frames at least this long go out gzipped to peers that accept it.
+pub const COMPRESS_THRESHOLD: usize = 8 * 1024;
+
+/// What a receiver lists under `compression` in its Hello.
+const GZIP: &str = "gzip";
+
+// Inflated frames are capped well above any real grant, so a hostile peer
+// can't make us allocate without bound.
+const MAX_INFLATED: u64 = 64 << 20;
+
+fn accepts_gzip(hello: &Value) -> bool {
+    hello["compression"]
+        .as_array()
+        .is_some_and(|list| list.iter().any(|c| c == GZIP))
+}
+
+// `text` as it goes on the wire. Long frames to a peer that accepts gzip are
+// sent as a binary frame: a `{"type":"Compressed","encoding":"gzip"}` header
+// line, then the gzipped text. Peers that never asked keep getting text.
+fn outgoing_frame(text: String, gzip: bool) -> Message {
+    if !gzip || text.len() < COMPRESS_THRESHOLD {
+        return Message::Text(text.into());
+    }
+    let mut frame = json!({ "type": "Compressed", "encoding": GZIP })
+        .to_string()
+        .into_bytes();
+    frame.push(b'\n');
+    let mut encoder = GzEncoder::new(frame, Compression::default());
+    match encoder
+        .write_all(text.as_bytes())
+        .and_then(|()| encoder.finish())
+    {
+        Ok(frame) => Message::Binary(frame.into()),
+        Err(e) => {
+            warn!("Failed to compress frame, sending it as text: {}", e);
+            Message::Text(text.into())
+        }
+    }
+}
+
+// The text inside a binary frame from `outgoing_frame`. Anything else is an
+// error rather than a guess at what the bytes mean.
+fn inflate_frame(frame: &[u8]) -> Result<String, String> {
+    let newline = frame
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or("binary frame without a header")?;
+    let header: Value = serde_json::from_slice(&frame[..newline])
+        .map_err(|e| format!("bad binary frame header: {}", e))?;
+    if header["type"] != "Compressed" {
+        return Err(format!("unexpected binary frame {}", header["type"]));
+    }
+    if header["encoding"] != GZIP {
+        return Err(format!("unsupported encoding {}", header["encoding"]));
+    }
+    let mut text = String::new();
+    GzDecoder::new(&frame[newline + 1..])
+        .take(MAX_INFLATED)
+        .read_to_string(&mut text)
+        .map_err(|e| format!("could not inflate frame: {}", e))?;
+    Ok(text)
+}
+
+async fn send_grant(
+    ws: &mut ServerSocket,
+    peer: SocketAddr,
+    grant: &GrantMessage,
+    encrypt: bool,
+    session_key: Option<&SessionKey>,
+    gzip: bool,
+) {
+    let mut msg = serde_json::to_value(grant).unwrap();
+    if encrypt {
+        let Some(key) = session_key else {
+            warn!(%peer, "Peer has no session key; not sending encrypted grant");
+            return;
+        };
+        match seal_envelope(key, &msg) {
+            Ok(sealed) => msg = sealed,
+            Err(e) => {
+                error!("Failed to encrypt grant: {}", e);
+                return;
+            }
+        }
+    }
+    let text = tagged("Grant", msg);
+    // Cookie values only ever go out at trace level.
+    info!(
+        tab_id = %grant.tab_id,
+        cookies = grant.cookies.len(),
+        encrypted = encrypt,
+        "Broadcasting grant"
+    );
+    if !encrypt {
+        trace!("Grant frame: {}", text);
+    }
+    if ws.send(outgoing_frame(text, gzip)).await.is_ok() {
+        record_delivery(
+            AuditOp::Grant,
+            &grant.op_id,
+            &grant.tab_id,
+            &grant.url,
+            peer,
+            grant.cookies.len(),
+        );
+    }
+}
+
+async fn send_navigate(
+    ws: &mut ServerSocket,
+    navigate: &NavigateMessage,
+    encrypt: bool,
+    session_key: Option<&SessionKey>,
+) {
+    let mut msg = serde_json::to_value(navigate).unwrap();
+    if encrypt {
+        let Some(key) = session_key else {
+            return;
+        };
+        match seal_envelope(key, &msg) {
+            Ok(sealed) => msg = sealed,
+            Err(e) => {
+                error!("Failed to encrypt navigation: {}", e);
+                return;
+            }
+        }
+    }
+    debug!("Mirroring tab {} to {}", navigate.tab_id, navigate.url);
+    let _ = ws.send(Message::Text(tagged("Navigate", msg).into())).await;
+}
+
+fn record_delivery(
+    op: AuditOp,
+    op_id: &str,
+    tab_id: &str,
+    url: &str,
+    peer: SocketAddr,
+    cookie_count: usize,
+) {
+    let record = AuditRecord {
+        ts: Utc::now(),
+        op,
+        op_id: op_id.to_string(),
+        tab_id: tab_id.to_string(),
+        url: url.to_string(),
+        peer: peer.to_string(),
+        cookie_count,
+    };
+    if let Err(e) = crate::audit::append(&record) {
+        error!("Failed to write audit record: {}", e);
+    }
+}
+
+/// Turn a user-entered peer (`host:port` or a full `ws://`/`wss://` URL)
+/// into the URL `connect_client` dials.
+pub fn peer_url(input: &str) -> String {
+    let input = input.trim();
+    if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("ws://{}", input)
+    }
+}
+
+type PeerSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn open_peer_socket(
+    url: &str,
+    proxy: Option<&str>,
+    pin_cert: Option<&str>,
+) -> Result<PeerSocket, Box<dyn Error + Send + Sync>> {
+    let target = Url::parse(url)?;
+    // Without a pin, wss:// checks the certificate against the usual roots.
+    let connector = match pin_cert {
+        Some(pin) => Some(Connector::Rustls(
+            crate::tls::pinned_client_config(pin).map_err(|e| e.to_string())?,
+        )),
+        None => None,
+    };
+    let proxy = proxy
+        .map(str::to_string)
+        .or_else(|| crate::proxy::proxy_from_env(&target));
+
+    match proxy {
+        None => Ok(connect_async_tls_with_config(url, None, false, connector)
+            .await?
+            .0),
+        Some(proxy) => {
+            let host = target.host_str().ok_or("peer URL has no host")?;
+            let port = target
+                .port_or_known_default()
+                .ok_or("peer URL has no port")?;
+            info!("Tunnelling through proxy {}", proxy);
+            let stream = crate::proxy::connect_via_proxy(&proxy, host, port).await?;
+            Ok(client_async_tls_with_config(url, stream, None, connector)
+                .await?
+                .0)
+        }
+    }
+}
+
+fn seal_envelope(key: &SessionKey, v: &Value) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    let (nonce, payload) = key.seal(v.to_string().as_bytes())?;
+    Ok(json!({ "encrypted": true, "nonce": nonce, "payload": payload }))
+}
+
+fn open_envelope(
+    key: Option<&SessionKey>,
+    v: &Value,
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    let key = key.ok_or("encrypted grant arrived before key exchange")?;
+    let nonce = v["nonce"].as_str().ok_or("missing nonce")?;
+    let payload = v["payload"].as_str().ok_or("missing payload")?;
+    Ok(serde_json::from_slice(&key.open(nonce, payload)?)?)
+}
+
+/// A grant this instance received and opened as a local tab.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReceivedShare {
+    pub remote_tab_id: String,
+    pub local_tab_id: String,
+    pub url: String,
+    pub from: String,
+    pub cookie_count: usize,
+    pub revoked: bool,
+    pub source: Option<GrantSource>,
+}
+
+/// A received grant held until the user picks which of its cookies to
+/// import.
+pub struct PendingGrant {
+    pub grant: GrantMessage,
+    pub from: String,
+}
+
+/// Open `grant` in a new tab, or the focused one if it asks for that, and
+/// record it as received.
+pub async fn import_grant(
+    cdp: &CdpEndpoint,
+    state: &Mutex<ReceiverState>,
+    grant: GrantMessage,
+    from: String,
+) {
+    let GrantMessage {
+        tab_id,
+        url,
+        cookies,
+        indexed_db,
+        storage,
+        open_in_current,
+        source,
+        ..
+    } = grant;
+    // A re-share of a tab we already opened updates that tab in place. If it
+    // has since been closed, fall through and open a new one.
+    let existing = state.lock().unwrap().reusable_tab(&tab_id, &from);
+    if let Some(local_id) = existing {
+        match crate::chrome::navigate_tab(cdp, &local_id, &url, &cookies).await {
+            Ok(report) => {
+                info!(%tab_id, "Updated tab {} in place: {}", local_id, report);
+                replay_storage(cdp, &local_id, &indexed_db, &storage).await;
+                let mut state = state.lock().unwrap();
+                if let Some(share) = state
+                    .received
+                    .iter_mut()
+                    .rev()
+                    .find(|r| r.local_tab_id == local_id)
+                {
+                    share.url = url;
+                    share.cookie_count = cookies.len();
+                    share.revoked = false;
+                    share.source = source;
+                }
+                state.save_received();
+                return;
+            }
+            Err(e) => info!("Tab {} is gone ({}); opening a new one", local_id, e),
+        }
+    }
+    let current = if open_in_current {
+        let opened = match crate::chrome::get_active_tab(cdp).await {
+            Ok(tab) => crate::chrome::navigate_tab(cdp, &tab.id, &url, &cookies)
+                .await
+                .map(|report| {
+                    info!(%tab_id, "Opened {} in the current tab {}: {}", url, tab.id, report);
+                    tab.id
+                }),
+            Err(e) => Err(e),
+        };
+        opened
+            .inspect_err(|e| info!("Not using the current tab ({}); opening a new one", e))
+            .ok()
+    } else {
+        None
+    };
+    let local_id = match current {
+        Some(id) => id,
+        None => {
+            let background = state.lock().unwrap().open_in_background;
+            info!(%tab_id, cookies = cookies.len(), "Importing {}", url);
+            match crate::chrome::import_and_open_with_cookies_from_memory(
+                cdp, &cookies, &url, background,
+            )
+            .await
+            {
+                Ok((id, report)) => {
+                    match &source {
+                        Some(source) => info!(%tab_id, %source, "Imported grant: {}", report),
+                        None => info!(%tab_id, "Imported grant: {}", report),
+                    }
+                    id
                 }
                 Err(e) => {
-                    eprintln!("Error accepting connection: {}", e);
+                    error!(%tab_id, "Not importing grant: {}", e);
+                    return;
                 }
             }
         }
+    };
+    replay_storage(cdp, &local_id, &indexed_db, &storage).await;
+    let mut state = state.lock().unwrap();
+    state.received.push(ReceivedShare {
+        remote_tab_id: tab_id,
+        local_tab_id: local_id,
+        url,
+        from,
+        cookie_count: cookies.len(),
+        revoked: false,
+        source,
     });
-
-    (grant_tx, revoke_tx)
+    state.save_received();
 }
 
-pub async fn connect_client(
-    addr: SocketAddr,
-    remote_to_local: Arc<Mutex<HashMap<String, String>>>,
+// localStorage goes last, since replaying it reloads the tab.
+async fn replay_storage(
+    cdp: &CdpEndpoint,
+    local_id: &str,
+    indexed_db: &[crate::chrome::IndexedDbEntry],
+    storage: &HashMap<String, String>,
 ) {
-    let url = format!("ws://{}", addr);
-    println!("Connecting to {}", url);
+    if !indexed_db.is_empty() {
+        match crate::chrome::replay_indexed_db(cdp, local_id, indexed_db).await {
+            Ok(n) => info!("Replayed {} IndexedDB records", n),
+            Err(e) => warn!("IndexedDB replay failed: {}", e),
+        }
+    }
+    if !storage.is_empty() {
+        match crate::chrome::replay_local_storage(cdp, local_id, storage).await {
+            Ok(n) => info!("Replayed {} localStorage items", n),
+            Err(e) => warn!("localStorage replay failed: {}", e),
+        }
+    }
+}
+
+/// Receiver-side bookkeeping shared between `connect_client` and the GUI.
+#[derive(Default)]
+pub struct ReceiverState {
+    pub received: Vec<ReceivedShare>,
+    /// Open received shares without taking focus from the current tab.
+    pub open_in_background: bool,
+    /// Queue grants in `pending` for the user instead of importing them.
+    pub approve_grants: bool,
+    pub pending: Vec<PendingGrant>,
+    /// Pre-shared secret mixed into the session key; grants that aren't
+    /// encrypted under it are refused.
+    pub secret: Option<String>,
+    /// SHA-256 fingerprint of the only certificate a `wss://` peer may
+    /// present, for self-signed servers.
+    pub pin_cert: Option<String>,
+    /// Token to present to servers started with `--auth-token`.
+    pub auth_token: Option<String>,
+    /// Ping interval and reconnect backoff for `connect_client`.
+    pub reconnect: crate::chrome::ReconnectPolicy,
+    /// Once a revoke's cookies are deleted, also close the tab its grant
+    /// opened and forget the share.
+    pub close_on_revoke: bool,
+    pub link: PeerLink,
+    /// File `received` is written to whenever it changes; see `persist_at`.
+    pub persist_to: Option<PathBuf>,
+    /// Feeds `request_tab`s to the running `connect_client`, which sets it.
+    pub requests: Option<mpsc::UnboundedSender<RequestTab>>,
+    /// Why the peer turned down the latest `request_tab`, if it did.
+    pub refused: Option<String>,
+}
+
+/// Where received shares are remembered between runs.
+pub fn received_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("sharekaro").join("received.json"))
+}
 
-    let (mut ws, _) = match connect_async(&url).await {
-        Ok(pair) => {
-            println!("Connected to server at {}", url);
-            pair
+impl ReceiverState {
+    /// Pick up the shares an earlier run remembered at `path`, so revokes for
+    /// them still find their local tab, and keep remembering them there.
+    /// Shares whose local tab isn't in `open_tabs` any more are dropped; with
+    /// no tab list (the browser wasn't reachable) they are all kept.
+    pub fn persist_at(&mut self, path: PathBuf, open_tabs: Option<&[crate::chrome::ChromeTab]>) {
+        let mut restored: Vec<ReceivedShare> = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        if let Some(open) = open_tabs {
+            restored.retain(|share| open.iter().any(|t| t.id == share.local_tab_id));
         }
-        Err(e) => {
-            eprintln!("Failed to connect to {}: {}", url, e);
+        info!(
+            "Restored {} received share(s) from {}",
+            restored.len(),
+            path.display()
+        );
+        restored.append(&mut self.received);
+        self.received = restored;
+        self.persist_to = Some(path);
+        self.save_received();
+    }
+
+    /// Ask the peer `connect_client` is receiving from for its tab on `url`.
+    /// The grant that comes back is handled like any other.
+    pub fn request_tab(&mut self, url: &str) -> Result<(), String> {
+        let url = crate::chrome::normalize_url(url)?;
+        let requests = self.requests.as_ref().ok_or("not receiving from a peer")?;
+        requests
+            .send(RequestTab { url })
+            .map_err(|_| "not receiving from a peer")?;
+        self.refused = None;
+        Ok(())
+    }
+
+    fn save_received(&self) {
+        let Some(path) = &self.persist_to else {
             return;
+        };
+        let saved = serde_json::to_vec_pretty(&self.received)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                crate::chrome::write_atomically(path, &json).map_err(|e| e.to_string())
+            });
+        if let Err(e) = saved {
+            warn!(
+                "Failed to save received shares to {}: {}",
+                path.display(),
+                e
+            );
         }
+    }
+
+    fn local_tab_id(&self, remote_id: &str) -> Option<String> {
+        self.received
+            .iter()
+            .rev()
+            .find(|r| r.remote_tab_id == remote_id)
+            .map(|r| r.local_tab_id.clone())
+    }
+
+    // The tab opened for an earlier grant of `remote_id` from the same sender.
+    fn reusable_tab(&self, remote_id: &str, from: &str) -> Option<String> {
+        self.received
+            .iter()
+            .rev()
+            .find(|r| r.remote_tab_id == remote_id && r.from == from)
+            .map(|r| r.local_tab_id.clone())
+    }
+
+    fn forget_tab(&mut self, local_id: &str) {
+        self.received.retain(|r| r.local_tab_id != local_id);
+        self.save_received();
+    }
+
+    // Record a revoke once the browser has applied it, dropping the share
+    // instead when its tab was closed.
+    fn revoked(&mut self, remote_id: &str, local_id: &str, closed: bool) {
+        for share in self
+            .received
+            .iter_mut()
+            .filter(|r| r.remote_tab_id == remote_id)
+        {
+            share.revoked = true;
+        }
+        if closed {
+            self.forget_tab(local_id);
+        } else {
+            self.save_received();
+        }
+    }
+}
+
+// Revokes for tabs we never received are applied to the tab with the same
+// id, which is what older senders expect. The flag says whether to close the
+// tab afterwards, which is only ever done to a tab a grant opened; the URL is
+// the one that grant was for, the only site a whole-site revoke may clear.
+fn revoke_target(state: &Mutex<ReceiverState>, remote_id: &str) -> (String, bool, Option<String>) {
+    let guard = state.lock().unwrap();
+    let granted_url = guard
+        .received
+        .iter()
+        .rev()
+        .find(|r| r.remote_tab_id == remote_id)
+        .map(|r| r.url.clone());
+    match guard.local_tab_id(remote_id) {
+        Some(local_id) => (local_id, guard.close_on_revoke, granted_url),
+        None => (remote_id.to_string(), false, None),
+    }
+}
+
+// The URL a whole-site revoke clears: the one the tab was granted for, and
+// only if the peer is asking about that same origin. Otherwise a peer could
+// wipe any site's cookies and storage in our browser.
+fn site_wide_url<'a>(granted: Option<&'a str>, asked: &str) -> Result<&'a str, String> {
+    let Some(granted) = granted else {
+        return Err(format!(
+            "refusing to revoke everything for {}: no grant opened this tab",
+            asked
+        ));
+    };
+    let origin = |url: &str| Url::parse(url).ok().map(|u| u.origin());
+    match (origin(granted), origin(asked)) {
+        (Some(granted_origin), Some(asked_origin)) if granted_origin == asked_origin => Ok(granted),
+        _ => Err(format!(
+            "refusing to revoke everything for {}: the tab was granted for {}",
+            asked, granted
+        )),
+    }
+}
+
+static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+
+/// Random id this process puts in its Hello, so a receiver pointed at its own
+/// server can tell and back off.
+pub fn instance_id() -> &'static str {
+    INSTANCE_ID.get_or_init(|| Handshake::new().public_key())
+}
+
+async fn revoke_in_tab(
+    cdp: &CdpEndpoint,
+    local_id: &str,
+    revoke: &RevokeMessage,
+    granted_url: Option<&str>,
+    close: bool,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let deleted = if revoke.whole_site {
+        let url = site_wide_url(granted_url, &revoke.url)?;
+        info!("Revoking everything for {} in tab {}", url, local_id);
+        crate::chrome::revoke_all_for_url(cdp, local_id, url, true).await?
+    } else {
+        info!(
+            "Revoking {} cookie(s) in tab {}",
+            revoke.cookies.len(),
+            local_id
+        );
+        let cookie_tuples: Vec<(&str, &str, &str)> = revoke
+            .cookies
+            .iter()
+            .map(|c| (c.name.as_str(), c.domain.as_str(), c.path.as_str()))
+            .collect();
+        crate::chrome::revoke_cookies(cdp, local_id, &cookie_tuples).await?
+    };
+    if close {
+        match crate::chrome::close_tab(cdp, local_id).await {
+            Ok(()) => info!("Closed revoked tab {}", local_id),
+            Err(e) => warn!("Could not close revoked tab {}: {}", local_id, e),
+        }
+    }
+    Ok(deleted)
+}
+
+/// Apply every revoke in `batch`, a few tabs at a time, and return how many
+/// tabs failed.
+pub async fn revoke_batch(
+    cdp: &CdpEndpoint,
+    state: &Mutex<ReceiverState>,
+    batch: RevokeBatchMessage,
+) -> usize {
+    const CONCURRENCY: usize = 4;
+    let failed = std::sync::atomic::AtomicUsize::new(0);
+    futures::stream::iter(batch.tabs)
+        .for_each_concurrent(CONCURRENCY, |revoke| {
+            let (local_id, close, granted_url) = revoke_target(state, &revoke.tab_id);
+            let failed = &failed;
+            async move {
+                let result = revoke_in_tab(cdp, &local_id, &revoke, granted_url.as_deref(), close)
+                    .await
+                    .map_err(|e| format!("tab {}: {}", local_id, e));
+                match result {
+                    Ok(_) => state
+                        .lock()
+                        .unwrap()
+                        .revoked(&revoke.tab_id, &local_id, close),
+                    Err(e) => {
+                        error!("Error revoking cookies in {}", e);
+                        failed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                }
+            }
+        })
+        .await;
+    failed.into_inner()
+}
+
+/// State of a receiver's connection to the peer it receives from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum PeerLink {
+    #[default]
+    Connecting,
+    Connected,
+    Reconnecting {
+        attempt: u32,
+        error: String,
+    },
+}
+
+// Why a receive session ended: `Fatal` ends `connect_client`, `Lost` is
+// retried once the peer has been reached at least once.
+enum SessionEnd {
+    Fatal(Box<dyn Error + Send + Sync>),
+    Lost(Box<dyn Error + Send + Sync>),
+}
+
+impl<E: Into<Box<dyn Error + Send + Sync>>> From<E> for SessionEnd {
+    fn from(e: E) -> Self {
+        SessionEnd::Lost(e.into())
+    }
+}
+
+/// Receive shares from the server at `url` until an error retrying won't
+/// fix: the server is this instance, it refuses us, or it was never reachable
+/// in the first place. A connection that drops or stops answering pings is
+/// reopened with backoff per `ReceiverState::reconnect`; `state` carries the
+/// received shares across reconnects.
+#[tracing::instrument(name = "receiver", skip_all, fields(%url))]
+pub async fn connect_client(
+    cdp: CdpEndpoint,
+    url: String,
+    proxy: Option<String>,
+    state: Arc<Mutex<ReceiverState>>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let policy = state.lock().unwrap().reconnect.clone();
+    let (request_tx, mut requests) = mpsc::unbounded_channel();
+    state.lock().unwrap().requests = Some(request_tx);
+    let mut reached = false;
+    let mut attempt = 0;
+    loop {
+        let on_connected = || {
+            reached = true;
+            attempt = 0;
+            state.lock().unwrap().link = PeerLink::Connected;
+        };
+        let session = receive_session(
+            &cdp,
+            &url,
+            proxy.as_deref(),
+            &state,
+            &policy,
+            &mut requests,
+            on_connected,
+        );
+        let error = match session.await {
+            SessionEnd::Fatal(e) => return Err(e),
+            SessionEnd::Lost(e) if !reached => return Err(e),
+            SessionEnd::Lost(e) => e.to_string(),
+        };
+        attempt += 1;
+        let delay = policy.backoff(attempt);
+        warn!("Connection lost ({}); reconnecting in {:?}", error, delay);
+        state.lock().unwrap().link = PeerLink::Reconnecting { attempt, error };
+        tokio::time::sleep(delay).await;
+    }
+}
+
+async fn receive_session(
+    cdp: &CdpEndpoint,
+    url: &str,
+    proxy: Option<&str>,
+    state: &Arc<Mutex<ReceiverState>>,
+    policy: &crate::chrome::ReconnectPolicy,
+    requests: &mut mpsc::UnboundedReceiver<RequestTab>,
+    on_connected: impl FnOnce(),
+) -> SessionEnd {
+    info!("Connecting");
+
+    let (pin_cert, auth_token) = {
+        let state = state.lock().unwrap();
+        (state.pin_cert.clone(), state.auth_token.clone())
+    };
+    let mut ws = match open_peer_socket(url, proxy, pin_cert.as_deref()).await {
+        Ok(ws) => ws,
+        Err(e) => return format!("failed to connect to {}: {}", url, e).into(),
     };
+    if let Some(token) = auth_token {
+        let auth = tagged("Auth", json!({ "token": token }));
+        if let Err(e) = ws.send(Message::Text(auth.into())).await {
+            return e.into();
+        }
+    }
+    info!("Connected to server");
+    let peer = url.to_string();
+
+    let handshake = Handshake::new();
+    let mut session_key: Option<SessionKey> = None;
+    let secret = state.lock().unwrap().secret.clone();
 
-    while let Some(Ok(Message::Text(text))) = ws.next().await {
-        println!("Received: {}", text);
+    // A ping goes out every keepalive period; if nothing at all has come
+    // back by the next one, the connection is treated as dead.
+    let mut heartbeat = tokio::time::interval(policy.keepalive);
+    heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    heartbeat.tick().await;
+    let mut awaiting_pong = false;
+    let mut on_connected = Some(on_connected);
+
+    loop {
+        let msg = tokio::select! {
+            msg = ws.next() => msg,
+            _ = heartbeat.tick() => {
+                if awaiting_pong {
+                    return "no reply to keepalive ping".into();
+                }
+                if let Err(e) = ws.send(Message::Ping(Vec::new().into())).await {
+                    return e.into();
+                }
+                awaiting_pong = true;
+                continue;
+            }
+            // Held until the server's Hello, so a secret-keyed reply can be
+            // encrypted for us.
+            Some(request) = requests.recv(), if on_connected.is_none() => {
+                let frame = tagged("RequestTab", serde_json::to_value(&request).unwrap());
+                if let Err(e) = ws.send(Message::Text(frame.into())).await {
+                    return e.into();
+                }
+                info!("Requested {}", request.url);
+                continue;
+            }
+        };
+        let text = match msg {
+            Some(Ok(msg)) => {
+                awaiting_pong = false;
+                match msg {
+                    Message::Text(text) => text,
+                    Message::Binary(frame) => match inflate_frame(&frame) {
+                        Ok(text) => text.into(),
+                        Err(e) => {
+                            warn!("Dropping binary frame: {}", e);
+                            continue;
+                        }
+                    },
+                    Message::Close(Some(close)) if close.code == CloseCode::Policy => {
+                        return SessionEnd::Fatal(
+                            format!("{} refused the connection: {}", url, close.reason).into(),
+                        );
+                    }
+                    Message::Close(_) => return "server closed the connection".into(),
+                    _ => continue,
+                }
+            }
+            Some(Err(e)) => return e.into(),
+            None => return "connection dropped".into(),
+        };
+        trace!("Received: {}", text);
 
         let v: Value = match serde_json::from_str(&text) {
             Ok(v) => v,
             Err(e) => {
-                eprintln!("Invalid JSON received: {}", e);
+                warn!("Invalid JSON received: {}", e);
                 continue;
             }
         };
+        if let Err(e) = frame_version(&v) {
+            warn!("Ignoring {} frame: {}", v["type"], e);
+            continue;
+        }
 
         match v.get("type").and_then(|t| t.as_str()) {
+            Some("Hello") => {
+                if v["instance"] == instance_id() {
+                    let _ = ws.close(None).await;
+                    return SessionEnd::Fatal(
+                        format!("{} is this instance's own server", url).into(),
+                    );
+                }
+                // Only a real Hello counts as having reached the peer, so
+                // refusing our own server above is never retried.
+                if let Some(on_connected) = on_connected.take() {
+                    on_connected();
+                }
+                let Some(public_key) = v["public_key"].as_str() else {
+                    warn!("Hello without a public key");
+                    continue;
+                };
+                match handshake.finish(public_key, secret.as_deref()) {
+                    Ok(key) => {
+                        session_key = Some(key);
+                        let hello = tagged(
+                            "Hello",
+                            json!({
+                                "public_key": handshake.public_key(),
+                                "compression": [GZIP],
+                            }),
+                        );
+                        let _ = ws.send(Message::Text(hello.into())).await;
+                    }
+                    Err(e) => warn!("Key exchange failed: {}", e),
+                }
+            }
             Some("Grant") => {
+                let v = if v["encrypted"] == true {
+                    match open_envelope(session_key.as_ref(), &v) {
+                        Ok(v) => v,
+                        Err(e) if secret.is_some() => {
+                            error!(
+                                "Failed to decrypt grant: {}; does it use the same --secret?",
+                                e
+                            );
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("Failed to decrypt grant: {}", e);
+                            continue;
+                        }
+                    }
+                } else if secret.is_some() {
+                    warn!("Refusing unencrypted grant: a --secret is set");
+                    continue;
+                } else {
+                    v
+                };
                 let grant: GrantMessage = match serde_json::from_value(v.clone()) {
                     Ok(g) => g,
                     Err(e) => {
-                        eprintln!("Failed to parse grant message: {}", e);
+                        warn!("Failed to parse grant message: {}", e);
                         continue;
                     }
                 };
-                let cookies = grant.cookies.clone();
-                let url = grant.url.clone();
-                let tab_id = grant.tab_id.clone();
-                let map = Arc::clone(&remote_to_local);
-
-                tokio::task::spawn_blocking(move || {
-                    println!("Importing URL with cookies: {}", url);
-                    if let Ok(local_id) =
-                        crate::chrome::import_and_open_with_cookies_from_memory(&cookies, &url)
+                let from = peer.clone();
+                {
+                    let mut state = state.lock().unwrap();
+                    if state.approve_grants {
+                        info!("Grant for {} is waiting for approval", grant.url);
+                        state.pending.push(PendingGrant { grant, from });
+                        continue;
+                    }
+                }
+                let state = Arc::clone(state);
+                let cdp = cdp.clone();
+                tokio::spawn(async move { import_grant(&cdp, &state, grant, from).await });
+            }
+            Some("Navigate") => {
+                let v = if v["encrypted"] == true {
+                    match open_envelope(session_key.as_ref(), &v) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            error!("Failed to decrypt navigation: {}", e);
+                            continue;
+                        }
+                    }
+                } else if secret.is_some() && v["cookies"].as_array().is_some_and(|c| !c.is_empty())
+                {
+                    warn!("Refusing unencrypted navigation with cookies: a --secret is set");
+                    continue;
+                } else {
+                    v
+                };
+                let mut nav: NavigateMessage = match serde_json::from_value(v) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        warn!("Failed to parse navigate message: {}", e);
+                        continue;
+                    }
+                };
+                // Only follow tabs we opened from a grant; the sender's tab id
+                // means nothing in our own browser otherwise.
+                let (local_id, approve_grants) = {
+                    let state = state.lock().unwrap();
+                    (state.local_tab_id(&nav.tab_id), state.approve_grants)
+                };
+                let Some(local_id) = local_id else {
+                    debug!("Ignoring navigation for unmirrored tab {}", nav.tab_id);
+                    continue;
+                };
+                // Cookies only arrive through a grant the user has reviewed,
+                // so the tab follows along but keeps the ones it has.
+                if approve_grants && !nav.cookies.is_empty() {
+                    info!(
+                        "Dropping {} mirrored cookie(s) for {}: grants need approval",
+                        nav.cookies.len(),
+                        nav.url
+                    );
+                    nav.cookies.clear();
+                }
+                let cdp = cdp.clone();
+                tokio::spawn(async move {
+                    debug!("Following tab {} to {}", local_id, nav.url);
+                    match crate::chrome::navigate_tab(&cdp, &local_id, &nav.url, &nav.cookies).await
                     {
-                        let mut guard = map.lock().unwrap();
-                        guard.insert(tab_id.clone(), local_id);
+                        Ok(report) if !report.failed.is_empty() => {
+                            warn!("Followed navigation: {}", report)
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("Failed to follow navigation: {}", e),
                     }
                 });
             }
@@ -157,36 +1628,231 @@ pub async fn connect_client(
                 let revoke: RevokeMessage = match serde_json::from_value(v.clone()) {
                     Ok(r) => r,
                     Err(e) => {
-                        eprintln!("Failed to parse revoke message: {}", e);
+                        warn!("Failed to parse revoke message: {}", e);
                         continue;
                     }
                 };
-                let tab_id = revoke.tab_id.clone();
-                let local_id = {
-                    let guard = remote_to_local.lock().unwrap();
-                    guard.get(&tab_id).cloned().unwrap_or(tab_id.clone())
+                let (local_id, close, granted_url) = revoke_target(state, &revoke.tab_id);
+                let outcome =
+                    revoke_in_tab(cdp, &local_id, &revoke, granted_url.as_deref(), close).await;
+                let deleted = match outcome {
+                    Ok(deleted) => {
+                        info!(tab_id = %revoke.tab_id, "Revoked {} cookie(s)", deleted);
+                        state
+                            .lock()
+                            .unwrap()
+                            .revoked(&revoke.tab_id, &local_id, close);
+                        deleted
+                    }
+                    Err(e) => {
+                        error!(tab_id = %revoke.tab_id, "Error revoking cookies: {}", e);
+                        0
+                    }
                 };
-                let cookies = revoke.cookies.clone();
-
-                tokio::task::spawn_blocking(move || {
-                    println!("Revoking cookies for tab {}", local_id);
-                    let cookie_tuples: Vec<(&str, &str, &str)> = cookies
-                        .iter()
-                        .map(|c| (c.name.as_str(), c.domain.as_str(), c.path.as_str()))
-                        .collect();
-
-                    if let Err(e) = crate::chrome::revoke_cookies(&local_id, &cookie_tuples) {
-                        eprintln!("Error revoking cookies: {}", e);
-                    } else {
-                        println!("Cookies revoked successfully");
+                let ack = RevokeAck {
+                    tab_id: revoke.tab_id,
+                    deleted,
+                    op_id: revoke.op_id,
+                };
+                let ack = tagged("RevokeAck", serde_json::to_value(&ack).unwrap());
+                let _ = ws.send(Message::Text(ack.into())).await;
+            }
+            Some("RevokeBatch") => {
+                let batch: RevokeBatchMessage = match serde_json::from_value(v) {
+                    Ok(b) => b,
+                    Err(e) => {
+                        warn!("Failed to parse revoke batch: {}", e);
+                        continue;
+                    }
+                };
+                let cdp = cdp.clone();
+                let state = Arc::clone(state);
+                tokio::spawn(async move {
+                    let failed = revoke_batch(&cdp, &state, batch).await;
+                    if failed > 0 {
+                        error!("{} tab(s) in the revoke batch failed", failed);
                     }
                 });
             }
+            Some("RequestRefused") => {
+                let reason = format!(
+                    "{} refused {}: {}",
+                    peer,
+                    v["url"].as_str().unwrap_or_default(),
+                    v["reason"].as_str().unwrap_or("no reason given")
+                );
+                warn!("{}", reason);
+                state.lock().unwrap().refused = Some(reason);
+            }
             _ => {
-                eprintln!("Unknown message type: {:?}", v.get("type"));
+                warn!(
+                    "Ignoring unknown {} frame; the peer may be a newer version",
+                    v["type"]
+                );
+            }
+        }
+    }
+}
+
+/// Follow the main frame of `tab` and broadcast each navigation until the tab
+/// closes or the task is aborted. With `with_cookies`, the tab's cookies are
+/// re-read after every navigation and attached whenever they changed, minus
+/// any outside the `share_domains` allowlist.
+#[tracing::instrument(name = "mirror", skip_all, fields(tab_id = %tab.id))]
+pub async fn mirror_tab(
+    cdp: CdpEndpoint,
+    tab: crate::chrome::ChromeTab,
+    navigations: broadcast::Sender<NavigateMessage>,
+    with_cookies: bool,
+    share_domains: Vec<String>,
+    encrypted: bool,
+) {
+    let mut followed = match crate::chrome::follow_navigations(&cdp, &tab).await {
+        Ok(followed) => followed,
+        Err(e) => {
+            warn!("Lost the mirrored tab: {}", e);
+            return;
+        }
+    };
+    let mut last_url = tab.url.clone();
+    let mut last_cookies = None;
+    loop {
+        let url = match followed.next().await {
+            Ok(url) => url,
+            Err(e) => {
+                warn!("Lost the mirrored tab: {}", e);
+                break;
+            }
+        };
+        if url == last_url {
+            continue;
+        }
+        last_url = url.clone();
+
+        let mut cookies = Vec::new();
+        if with_cookies {
+            let tab = crate::chrome::ChromeTab {
+                url: url.clone(),
+                ..tab.clone()
+            };
+            match crate::chrome::get_cookies_for_tab(&cdp, &tab).await {
+                Ok(current) => {
+                    let current = crate::chrome::filter_cookies(current, &share_domains);
+                    let fingerprint = serde_json::to_string(&current).unwrap_or_default();
+                    if last_cookies.as_ref() != Some(&fingerprint) {
+                        last_cookies = Some(fingerprint);
+                        cookies = current;
+                    }
+                }
+                Err(e) => warn!("Failed to read cookies while mirroring: {}", e),
             }
         }
+
+        let _ = navigations.send(NavigateMessage {
+            tab_id: tab.id.clone(),
+            url,
+            cookies,
+            encrypted,
+        });
+    }
+    info!("Stopped mirroring");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grant_source_is_optional_on_the_wire() {
+        let legacy = json!({ "tab_id": "T1", "url": "https://example.com/", "cookies": [] });
+        let grant: GrantMessage = serde_json::from_value(legacy).unwrap();
+        assert_eq!(grant.source, None);
+        let sent = serde_json::to_value(&grant).unwrap();
+        assert!(sent.get("source_host").is_none());
+
+        let source = GrantSource {
+            source_host: "laptop".into(),
+            source_title: "Inbox".into(),
+            captured_at: "2024-05-06T07:08:09Z".parse().unwrap(),
+        };
+        let sent = serde_json::to_value(GrantMessage {
+            source: Some(source.clone()),
+            ..grant
+        })
+        .unwrap();
+        assert_eq!(sent["source_host"], "laptop");
+        assert_eq!(sent["captured_at"], "2024-05-06T07:08:09Z");
+        let received: GrantMessage = serde_json::from_value(sent).unwrap();
+        assert_eq!(received.source, Some(source));
     }
 
-    println!("WebSocket listener loop has ended");
+    #[test]
+    fn storage_only_goes_on_the_wire_when_captured() {
+        let legacy = json!({ "tab_id": "T1", "url": "https://example.com/", "cookies": [] });
+        let mut grant: GrantMessage = serde_json::from_value(legacy).unwrap();
+        assert!(grant.storage.is_empty());
+        assert!(
+            serde_json::to_value(&grant)
+                .unwrap()
+                .get("storage")
+                .is_none()
+        );
+
+        grant.storage.insert("token".into(), "abc".into());
+        let sent = serde_json::to_value(&grant).unwrap();
+        assert_eq!(sent["storage"], json!({ "token": "abc" }));
+    }
+
+    #[test]
+    fn large_frames_are_gzipped_only_for_peers_that_ask() {
+        let text = json!({ "type": "Grant", "url": "x".repeat(COMPRESS_THRESHOLD) }).to_string();
+        assert!(matches!(
+            outgoing_frame(text.clone(), false),
+            Message::Text(_)
+        ));
+        assert!(matches!(
+            outgoing_frame("{}".into(), true),
+            Message::Text(_)
+        ));
+
+        let Message::Binary(frame) = outgoing_frame(text.clone(), true) else {
+            panic!("expected a binary frame");
+        };
+        assert!(frame.len() < text.len());
+        assert_eq!(inflate_frame(&frame).unwrap(), text);
+        assert!(inflate_frame(b"{\"type\":\"Compressed\",\"encoding\":\"br\"}\n").is_err());
+        assert!(inflate_frame(b"no header").is_err());
+    }
+
+    #[test]
+    fn hello_advertises_gzip() {
+        assert!(accepts_gzip(
+            &json!({ "type": "Hello", "compression": ["gzip"] })
+        ));
+        assert!(!accepts_gzip(
+            &json!({ "type": "Hello", "public_key": "k" })
+        ));
+    }
+
+    #[test]
+    fn frames_carry_a_version_and_newer_ones_are_refused() {
+        let frame: Value =
+            serde_json::from_str(&tagged("RevokeAck", json!({ "deleted": 2 }))).unwrap();
+        assert_eq!(
+            frame,
+            json!({ "type": "RevokeAck", "version": 1, "deleted": 2 })
+        );
+        let envelope: Envelope<Value> = serde_json::from_value(frame.clone()).unwrap();
+        assert_eq!(envelope.payload, json!({ "deleted": 2 }));
+        assert_eq!(frame_version(&frame), Ok(PROTOCOL_VERSION));
+
+        let legacy = json!({ "type": "Grant", "url": "https://example.com/" });
+        assert_eq!(frame_version(&legacy), Ok(1));
+        let envelope: Envelope<Value> = serde_json::from_value(legacy).unwrap();
+        assert_eq!(envelope.version, 1);
+
+        let newer = json!({ "type": "Grant", "version": PROTOCOL_VERSION + 1 });
+        assert!(frame_version(&newer).unwrap_err().contains("upgrade"));
+        assert!(frame_version(&json!({ "version": "two" })).is_err());
+    }
 }
@@ -0,0 +1,351 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::io;
+use std::net::IpAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use url::Url;
+
+/// Pick the proxy for a peer URL from the usual environment variables.
+///
+/// `wss://` targets look at `HTTPS_PROXY`, `ws://` at `HTTP_PROXY`, and both
+/// fall back to `ALL_PROXY`. Hosts listed in `NO_PROXY` connect directly.
+pub fn proxy_from_env(target: &Url) -> Option<String> {
+    let keys: &[&str] = if target.scheme() == "wss" {
+        &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+    } else {
+        &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]
+    };
+    let proxy = keys
+        .iter()
+        .filter_map(|k| std::env::var(k).ok())
+        .find(|v| !v.is_empty())?;
+
+    let no_proxy = std::env::var("NO_PROXY")
+        .or_else(|_| std::env::var("no_proxy"))
+        .unwrap_or_default();
+    match target.host_str() {
+        Some(host) if bypasses_proxy(host, &no_proxy) => None,
+        _ => Some(proxy),
+    }
+}
+
+fn bypasses_proxy(host: &str, no_proxy: &str) -> bool {
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.'))
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| entry == "*" || host == entry || host.ends_with(&format!(".{}", entry)))
+}
+
+/// Open a TCP stream to `host:port` tunnelled through `proxy`.
+///
+/// `http://` proxies are driven with `CONNECT`; `socks5://` and `socks5h://`
+/// use a SOCKS5 handshake. Credentials in the proxy URL are sent as basic auth
+/// or SOCKS5 username/password respectively.
+pub async fn connect_via_proxy(proxy: &str, host: &str, port: u16) -> io::Result<TcpStream> {
+    let proxy = Url::parse(proxy).map_err(|e| invalid(format!("bad proxy URL: {}", e)))?;
+    let proxy_host = proxy
+        .host_str()
+        .ok_or_else(|| invalid("proxy URL has no host".into()))?;
+
+    match proxy.scheme() {
+        "http" => {
+            let proxy_port = proxy.port().unwrap_or(8080);
+            let mut stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+            http_connect(&mut stream, &proxy, host, port).await?;
+            Ok(stream)
+        }
+        "socks5" | "socks5h" => {
+            let proxy_port = proxy.port().unwrap_or(1080);
+            let mut stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+            socks5_connect(&mut stream, &proxy, host, port).await?;
+            Ok(stream)
+        }
+        other => Err(invalid(format!("unsupported proxy scheme: {}", other))),
+    }
+}
+
+async fn http_connect(
+    stream: &mut TcpStream,
+    proxy: &Url,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let mut request = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n",
+        host = host,
+        port = port
+    );
+    if !proxy.username().is_empty() {
+        let credentials = format!("{}:{}", proxy.username(), proxy.password().unwrap_or(""));
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            BASE64.encode(credentials)
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so nothing past the header block is consumed; the
+    // rest of the stream belongs to the WebSocket handshake.
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > 8192 {
+            return Err(invalid("proxy response header too large".into()));
+        }
+        let mut byte = [0u8; 1];
+        if stream.read(&mut byte).await? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "proxy closed connection during CONNECT",
+            ));
+        }
+        head.push(byte[0]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let status_line = head.lines().next().unwrap_or("");
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "proxy refused CONNECT: {}",
+            status_line
+        ))),
+    }
+}
+
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    proxy: &Url,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let with_auth = !proxy.username().is_empty();
+    if with_auth {
+        stream.write_all(&[5, 2, 0, 2]).await?;
+    } else {
+        stream.write_all(&[5, 1, 0]).await?;
+    }
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    match reply {
+        [5, 0] => {}
+        [5, 2] if with_auth => {
+            let user = proxy.username().as_bytes();
+            let pass = proxy.password().unwrap_or("").as_bytes();
+            if user.len() > 255 || pass.len() > 255 {
+                return Err(invalid("SOCKS5 credentials too long".into()));
+            }
+            let mut auth = vec![1, user.len() as u8];
+            auth.extend_from_slice(user);
+            auth.push(pass.len() as u8);
+            auth.extend_from_slice(pass);
+            stream.write_all(&auth).await?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != 0 {
+                return Err(io::Error::other("SOCKS5 authentication failed"));
+            }
+        }
+        _ => return Err(io::Error::other("SOCKS5 proxy rejected auth methods")),
+    }
+
+    let mut request = vec![5, 1, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err(invalid("hostname too long for SOCKS5".into()));
+            }
+            request.push(3);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0 {
+        return Err(io::Error::other(format!(
+            "SOCKS5 connect failed with code {}",
+            head[1]
+        )));
+    }
+    let addr_len = match head[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        other => return Err(invalid(format!("bad SOCKS5 address type {}", other))),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::{Connector, client_async_tls_with_config};
+
+    #[test]
+    fn no_proxy_matches_suffixes() {
+        assert!(bypasses_proxy("peer.lan", "localhost, .lan"));
+        assert!(bypasses_proxy("10.0.0.2", "10.0.0.2"));
+        assert!(bypasses_proxy("anything", "*"));
+        assert!(!bypasses_proxy("example.com", "ample.com"));
+    }
+
+    // A ws:// server, or wss:// with the test certificate, that greets one
+    // client. Returns its address and the URL to reach it on.
+    async fn greeter(tls: bool) -> (SocketAddr, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = tls.then(|| TlsAcceptor::from(test_identity().config));
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            match acceptor {
+                Some(acceptor) => {
+                    let stream = acceptor.accept(stream).await.unwrap();
+                    let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    ws.send(Message::Text("hello".into())).await.unwrap();
+                }
+                None => {
+                    let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    ws.send(Message::Text("hello".into())).await.unwrap();
+                }
+            }
+        });
+        let scheme = if tls { "wss" } else { "ws" };
+        (addr, format!("{}://{}", scheme, addr))
+    }
+
+    fn test_identity() -> crate::tls::TlsIdentity {
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        crate::tls::load_identity(
+            &fixtures.join("tls-cert.pem"),
+            &fixtures.join("tls-key.pem"),
+        )
+        .unwrap()
+    }
+
+    // Each proxy relays its client to wherever it was asked to go, after
+    // checking the request names `expected`.
+    async fn http_proxy(expected: SocketAddr) -> String {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut client, _) = proxy.accept().await.unwrap();
+            let mut buf = vec![0u8; 1024];
+            let n = client.read(&mut buf).await.unwrap();
+            let request = format!("CONNECT {} HTTP/1.1\r\n", expected);
+            assert!(String::from_utf8_lossy(&buf[..n]).starts_with(&request));
+            client
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await
+                .unwrap();
+            let mut upstream = TcpStream::connect(expected).await.unwrap();
+            tokio::io::copy_bidirectional(&mut client, &mut upstream)
+                .await
+                .ok();
+        });
+        format!("http://{}", proxy_addr)
+    }
+
+    async fn socks5_proxy(expected: SocketAddr) -> String {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut client, _) = proxy.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            client.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            client.write_all(&[5, 0]).await.unwrap();
+
+            let mut request = [0u8; 10];
+            client.read_exact(&mut request).await.unwrap();
+            let IpAddr::V4(ip) = expected.ip() else {
+                unreachable!("the greeter listens on IPv4")
+            };
+            assert_eq!(request[..4], [5, 1, 0, 1]);
+            assert_eq!(request[4..8], ip.octets());
+            assert_eq!(request[8..], expected.port().to_be_bytes());
+            client
+                .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+            let mut upstream = TcpStream::connect(expected).await.unwrap();
+            tokio::io::copy_bidirectional(&mut client, &mut upstream)
+                .await
+                .ok();
+        });
+        format!("socks5://{}", proxy_addr)
+    }
+
+    async fn greeting_through(proxy: &str, addr: SocketAddr, url: &str) -> String {
+        let stream = connect_via_proxy(proxy, "127.0.0.1", addr.port())
+            .await
+            .unwrap();
+        let connector = url.starts_with("wss://").then(|| {
+            let pin = test_identity().fingerprint;
+            Connector::Rustls(crate::tls::pinned_client_config(&pin).unwrap())
+        });
+        let (mut ws, _) = client_async_tls_with_config(url, stream, None, connector)
+            .await
+            .unwrap();
+        let msg = ws.next().await.unwrap().unwrap();
+        msg.into_text().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn websocket_through_http_connect_proxy() {
+        for tls in [false, true] {
+            let (addr, url) = greeter(tls).await;
+            let proxy = http_proxy(addr).await;
+            assert_eq!(
+                greeting_through(&proxy, addr, &url).await,
+                "hello",
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn websocket_through_socks5_proxy() {
+        for tls in [false, true] {
+            let (addr, url) = greeter(tls).await;
+            let proxy = socks5_proxy(addr).await;
+            assert_eq!(
+                greeting_through(&proxy, addr, &url).await,
+                "hello",
+                "{}",
+                url
+            );
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ViewMode {
+    #[default]
+    Grid,
+    List,
+}
+
+/// A named address in the peer address book, e.g. `Laptop` at
+/// `192.168.1.5:9234`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct PeerEntry {
+    pub name: String,
+    pub addr: String,
+}
+
+/// GUI preferences remembered between runs, stored as JSON under the OS
+/// config directory. Unknown or missing fields fall back to their defaults so
+/// older files keep loading as settings are added.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub view_mode: ViewMode,
+    pub include_indexed_db: bool,
+    pub encrypt_grants: bool,
+    pub mirror_cookies: bool,
+    /// Overrides `chrome::DEFAULT_EXPORT_TEMPLATE` when set.
+    pub export_template: Option<String>,
+    /// Add a readable `expiresReadable` next to each exported `expires`.
+    pub annotate_expiry: bool,
+    pub export_format: crate::chrome::CookieFormat,
+    /// Show a screenshot of each tab on its grid card.
+    pub show_thumbnails: bool,
+    /// Open imported and received tabs without focusing them.
+    pub open_in_background: bool,
+    /// Ask before importing a received grant, and import only the cookies
+    /// picked.
+    pub approve_grants: bool,
+    /// System-wide key combination that shares the active tab, e.g.
+    /// `Ctrl+Shift+S`. Off unless set.
+    pub share_hotkey: Option<String>,
+    /// Saved peer addresses, shown with a connect button each.
+    pub peers: Vec<PeerEntry>,
+    /// Address last received from, highlighted in the address book.
+    pub last_peer: Option<String>,
+}
+
+impl Settings {
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("sharekaro").join("settings.json"))
+    }
+
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Add `addr` under `name`, replacing any entry already called that.
+    pub fn add_peer(&mut self, name: &str, addr: &str) {
+        let entry = PeerEntry {
+            name: name.trim().to_string(),
+            addr: addr.trim().to_string(),
+        };
+        match self.peers.iter_mut().find(|p| p.name == entry.name) {
+            Some(existing) => *existing = entry,
+            None => self.peers.push(entry),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = Self::path().ok_or("no config directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        crate::chrome::write_atomically(&path, serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saving_a_peer_under_an_existing_name_replaces_it() {
+        let mut settings = Settings::default();
+        settings.add_peer(" Laptop ", "192.168.1.5:9234");
+        settings.add_peer("Desktop", "192.168.1.9:9234");
+        settings.add_peer("Laptop", "192.168.1.7:9234 ");
+        assert_eq!(
+            settings.peers,
+            [
+                PeerEntry {
+                    name: "Laptop".into(),
+                    addr: "192.168.1.7:9234".into(),
+                },
+                PeerEntry {
+                    name: "Desktop".into(),
+                    addr: "192.168.1.9:9234".into(),
+                },
+            ]
+        );
+
+        // Files from before the address book still load.
+        let old: Settings = serde_json::from_str(r#"{"encrypt_grants":true}"#).unwrap();
+        assert!(old.peers.is_empty());
+    }
+}
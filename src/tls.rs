@@ -0,0 +1,163 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, ServerConfig, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A loaded certificate and key for serving `wss://`.
+#[derive(Clone)]
+pub struct TlsIdentity {
+    pub config: Arc<ServerConfig>,
+    /// SHA-256 of the leaf certificate, for receivers to pin.
+    pub fingerprint: String,
+}
+
+/// Load a PEM certificate chain and private key, e.g. a self-signed pair
+/// from `openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1`.
+pub fn load_identity(cert: &Path, key: &Path) -> Result<TlsIdentity, Box<dyn Error>> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("could not read certificates from {}: {}", cert.display(), e))?;
+    let leaf = certs
+        .first()
+        .ok_or_else(|| format!("{} holds no certificate", cert.display()))?;
+    let fingerprint = fingerprint(leaf);
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("could not read a private key from {}: {}", key.display(), e))?;
+    let config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsIdentity {
+        config: Arc::new(config),
+        fingerprint,
+    })
+}
+
+/// SHA-256 of a DER certificate as colon-separated hex, the way
+/// `openssl x509 -fingerprint -sha256` prints it.
+pub fn fingerprint(cert: &CertificateDer<'_>) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Client settings that trust exactly the server certificate with SHA-256
+/// `pin`, self-signed or not, and ignore the host name it was issued for.
+pub fn pinned_client_config(pin: &str) -> Result<Arc<ClientConfig>, Box<dyn Error>> {
+    let pin = normalize_fingerprint(pin)?;
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PinnedCert {
+            pin,
+            provider: provider(),
+        }))
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+// Accept `AB:CD:..`, `abcd..` and anything in between.
+fn normalize_fingerprint(pin: &str) -> Result<String, String> {
+    let hex: String = pin
+        .chars()
+        .filter(|c| *c != ':')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{:?} is not a SHA-256 fingerprint", pin));
+    }
+    let pairs: Vec<&str> = (0..64).step_by(2).map(|i| &hex[i..i + 2]).collect();
+    Ok(pairs.join(":"))
+}
+
+#[derive(Debug)]
+struct PinnedCert {
+    pin: String,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let actual = fingerprint(end_entity);
+        if actual == self.pin {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!(
+                "server certificate {} is not the pinned {}",
+                actual, self.pin
+            )))
+        }
+    }
+
+    // The pin only replaces the chain check; the server still has to prove
+    // it holds the certificate's key.
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprints_are_normalized_for_pinning() {
+        let colons = "dd:7b:ed:40:17:51:1b:38:d2:db:31:b4:da:87:cb:3a:bf:aa:6f:16:25:ad:e9:c5:99:32:0d:fe:5a:eb:27:26";
+        let bare = colons.replace(':', "");
+        assert_eq!(
+            normalize_fingerprint(colons).unwrap(),
+            normalize_fingerprint(&bare).unwrap()
+        );
+        assert!(normalize_fingerprint(colons).unwrap().starts_with("DD:7B:"));
+        assert!(normalize_fingerprint("DD:7B").is_err());
+        assert!(normalize_fingerprint(&"zz".repeat(32)).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::FakeCdp;
+use sharekaro::cdp::Cdp;
+use sharekaro::chrome::{CdpEndpoint, Cookie, CookieFormat, parse_netscape};
+
+fn cookie(domain: &str, name: &str, value: &str) -> Cookie {
+    serde_json::from_value(serde_json::json!({
+        "domain": domain,
+        "name": name,
+        "path": "/",
+        "value": value,
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn cookies_round_trip_without_opening_a_tab() {
+    let fake = FakeCdp::start(&[("T1", "https://app.example.com/")]);
+    let cdp = Cdp::connect_to(fake.endpoint.clone()).await.unwrap();
+
+    let report = cdp
+        .set_cookies(
+            "app.example.com",
+            &[
+                cookie("app.example.com", "sid", "s3cret"),
+                cookie("", "theme", "dark"),
+                cookie("app.example.com", "bad", "reject-me"),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.verified, 2);
+    assert_eq!(report.failed.len(), 1);
+    assert!(report.failed[0].0.starts_with("bad "));
+    assert_eq!(fake.tab_ids(), ["T1"]);
+
+    let tabs = cdp.list_tabs().await.unwrap();
+    assert_eq!(tabs.len(), 1);
+    let names: Vec<String> = cdp
+        .get_cookies(&tabs[0])
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|c| c.name)
+        .collect();
+    assert_eq!(names, ["sid", "theme"]);
+
+    let exported = cdp
+        .export_cookies(&tabs[0], CookieFormat::Netscape, false)
+        .await
+        .unwrap();
+    let exported = parse_netscape(&exported);
+    assert_eq!(exported[1].domain, "app.example.com");
+    assert_eq!(exported[1].value, "dark");
+}
+
+#[tokio::test]
+async fn connecting_to_nothing_fails_up_front() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    assert!(
+        Cdp::connect_to(CdpEndpoint::new("127.0.0.1", port))
+            .await
+            .is_err()
+    );
+}
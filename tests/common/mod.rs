@@ -0,0 +1,272 @@
+//! A stand-in for Chrome's DevTools endpoint: the `/json` HTTP listing,
+//! `/json/new`, `/json/version`, and a WebSocket per tab plus one for the
+//! browser (recorded under the id `browser`) that records every command and keeps
+//! one cookie jar shared by all tabs, like a single browser profile.
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{Value, json};
+use sharekaro::chrome::CdpEndpoint;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::{self, Message};
+
+pub struct FakeCdp {
+    pub endpoint: CdpEndpoint,
+    /// `(tab id, command)` for every CDP command received, in arrival order.
+    pub commands: Receiver<(String, Value)>,
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    port: u16,
+    tabs: Vec<Value>,
+    cookies: Vec<Value>,
+    opened: usize,
+    commands: Sender<(String, Value)>,
+}
+
+impl State {
+    fn tab(&self, id: &str, url: &str) -> Value {
+        json!({
+            "id": id,
+            "title": id,
+            "url": url,
+            "webSocketDebuggerUrl": format!("ws://127.0.0.1:{}/devtools/page/{}", self.port, id),
+        })
+    }
+}
+
+impl FakeCdp {
+    /// Serve a browser with one page per `(id, url)`.
+    pub fn start(tabs: &[(&str, &str)]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, commands) = channel();
+        let mut state = State {
+            port,
+            tabs: Vec::new(),
+            cookies: Vec::new(),
+            opened: 0,
+            commands: tx,
+        };
+        state.tabs = tabs.iter().map(|(id, url)| state.tab(id, url)).collect();
+        let state = Arc::new(Mutex::new(state));
+
+        let server_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let state = Arc::clone(&server_state);
+                thread::spawn(move || handle(stream, &state));
+            }
+        });
+        FakeCdp {
+            endpoint: CdpEndpoint::new("127.0.0.1", port),
+            commands,
+            state,
+        }
+    }
+
+    /// List `target` in `/json` as given, e.g. a service worker or a page
+    /// whose socket URL names another host.
+    pub fn add_target(&self, target: Value) {
+        self.state.lock().unwrap().tabs.push(target);
+    }
+
+    /// Ids of every page currently listed, including ones opened via `/json/new`.
+    pub fn tab_ids(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .tabs
+            .iter()
+            .map(|t| t["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    /// `(name, value)` of every cookie in the jar.
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        let state = self.state.lock().unwrap();
+        state
+            .cookies
+            .iter()
+            .map(|c| {
+                (
+                    c["name"].as_str().unwrap_or_default().to_string(),
+                    c["value"].as_str().unwrap_or_default().to_string(),
+                )
+            })
+            .collect()
+    }
+}
+
+fn handle(stream: TcpStream, state: &Mutex<State>) {
+    let mut head = [0u8; 512];
+    let n = stream.peek(&mut head).unwrap_or(0);
+    if String::from_utf8_lossy(&head[..n]).contains("/devtools/") {
+        serve_page(stream, state);
+    } else {
+        serve_http(stream, state);
+    }
+}
+
+fn serve_http(mut stream: TcpStream, state: &Mutex<State>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    let _ = reader.read_line(&mut request_line);
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok() && line != "\r\n" && !line.is_empty() {
+        line.clear();
+    }
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+
+    let body = {
+        let mut state = state.lock().unwrap();
+        if let Some(url) = target.strip_prefix("/json/new?") {
+            state.opened += 1;
+            let tab = state.tab(&format!("NEW{}", state.opened), url);
+            state.tabs.push(tab.clone());
+            tab.to_string()
+        } else if target == "/json/version" {
+            json!({
+                "Browser": "FakeCdp/1.0",
+                "webSocketDebuggerUrl":
+                    format!("ws://127.0.0.1:{}/devtools/browser/browser", state.port),
+            })
+            .to_string()
+        } else {
+            Value::Array(state.tabs.clone()).to_string()
+        }
+    };
+    let _ = write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+}
+
+fn serve_page(stream: TcpStream, state: &Mutex<State>) {
+    let mut tab_id = String::new();
+    #[allow(clippy::result_large_err)]
+    let callback = |req: &Request, resp: Response| {
+        tab_id = req
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or("")
+            .to_string();
+        Ok(resp)
+    };
+    let Ok(mut ws) = tungstenite::accept_hdr(stream, callback) else {
+        return;
+    };
+    while let Ok(msg) = ws.read() {
+        let Ok(cmd) = serde_json::from_str::<Value>(msg.to_text().unwrap_or_default()) else {
+            continue;
+        };
+        let result = {
+            let mut state = state.lock().unwrap();
+            let _ = state.commands.send((tab_id.clone(), cmd.clone()));
+            apply(&mut state, &cmd)
+        };
+        let mut reply = match result {
+            Ok(result) => json!({ "id": cmd["id"], "result": result }),
+            Err(message) => json!({
+                "id": cmd["id"],
+                "error": { "code": -32601, "message": message },
+            }),
+        };
+        if let Some(session_id) = cmd.get("sessionId") {
+            reply["sessionId"] = session_id.clone();
+        }
+        if ws.send(Message::Text(reply.to_string().into())).is_err() {
+            break;
+        }
+    }
+}
+
+/// A 2x1 PNG, one red pixel and one blue.
+const TWO_PIXEL_PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAIAAAABCAYAAAD0In+KAAAADklEQVR4nGP4z8AAQv8BD/kD/YURmXYAAAAASUVORK5CYII=";
+
+// Like Chrome, a cookie set by `url` alone is host-only on its host.
+fn cookie_from_params(params: &Value) -> Value {
+    let mut cookie = params.clone();
+    if cookie.get("domain").is_none()
+        && let Some(host) = cookie["url"]
+            .as_str()
+            .and_then(|u| url::Url::parse(u).ok())
+            .and_then(|u| u.host_str().map(str::to_string))
+    {
+        cookie["domain"] = json!(host);
+    }
+    cookie
+}
+
+/// The command's result, or an error message for methods Chrome would not
+/// know (any `Fake.fail*`) or cookies it refuses (`Storage.setCookies` with
+/// the value `reject-me`). `Network.setCookie` answers `success: false` for
+/// such a cookie instead, as Chrome does.
+fn apply(state: &mut State, cmd: &Value) -> Result<Value, String> {
+    let params = &cmd["params"];
+    let same = |c: &Value| {
+        c["name"] == params["name"]
+            && c["domain"] == params["domain"]
+            && c["path"] == params["path"]
+    };
+    let method = cmd["method"].as_str().unwrap_or_default();
+    if method.starts_with("Fake.fail") {
+        return Err(format!("'{}' wasn't found", method));
+    }
+    Ok(match method {
+        "Network.setCookie" if params["value"] == "reject-me" => json!({ "success": false }),
+        "Network.setCookie" => {
+            state.cookies.retain(|c| !same(c));
+            state.cookies.push(cookie_from_params(params));
+            json!({ "success": true })
+        }
+        "Storage.setCookies" => {
+            for params in params["cookies"].as_array().into_iter().flatten() {
+                if params["value"] == "reject-me" {
+                    return Err("Sanitizing cookie failed".into());
+                }
+                let cookie = cookie_from_params(params);
+                state.cookies.retain(|c| {
+                    c["name"] != cookie["name"]
+                        || c["domain"] != cookie["domain"]
+                        || c["path"] != cookie["path"]
+                });
+                state.cookies.push(cookie);
+            }
+            json!({})
+        }
+        "Network.deleteCookies" => {
+            state.cookies.retain(|c| !same(c));
+            json!({})
+        }
+        "Network.getCookies" | "Network.getAllCookies" | "Storage.getCookies" => {
+            json!({ "cookies": state.cookies })
+        }
+        "Target.createTarget" => {
+            state.opened += 1;
+            let id = format!("NEW{}", state.opened);
+            let tab = state.tab(&id, params["url"].as_str().unwrap_or_default());
+            state.tabs.push(tab);
+            json!({ "targetId": id })
+        }
+        "Page.captureScreenshot" => json!({ "data": TWO_PIXEL_PNG }),
+        "Target.closeTarget" => {
+            state.tabs.retain(|t| t["id"] != params["targetId"]);
+            json!({ "success": true })
+        }
+        "Target.attachToTarget" => {
+            json!({ "sessionId": format!("S-{}", params["targetId"].as_str().unwrap_or_default()) })
+        }
+        _ => json!({}),
+    })
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+# Stand-in for Chrome in tests: record the arguments in the profile dir and
+# stay alive until killed. The test serves the CDP endpoint itself.
+for arg in "$@"; do
+    case "$arg" in
+        --user-data-dir=*) profile="${arg#--user-data-dir=}" ;;
+    esac
+done
+# Write then rename so the test never reads a half-written file.
+echo "$@" > "$profile/launch-args.tmp"
+mv "$profile/launch-args.tmp" "$profile/launch-args"
+exec sleep 30
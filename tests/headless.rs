@@ -0,0 +1,62 @@
+mod common;
+
+use common::FakeCdp;
+use sharekaro::browser::ChromeBrowser;
+use sharekaro::headless::{ShareTarget, resolve_target};
+
+#[tokio::test]
+async fn share_target_picks_a_tab_by_index_or_url() {
+    let fake = FakeCdp::start(&[
+        ("T1", "https://example.com/"),
+        ("T2", "https://mail.example.org/inbox"),
+    ]);
+    let chrome = ChromeBrowser::new(fake.endpoint.clone());
+
+    let tab = resolve_target(&chrome, &ShareTarget::Index(1))
+        .await
+        .unwrap();
+    assert_eq!(tab.id, "T2");
+
+    let tab = resolve_target(&chrome, &ShareTarget::Url("mail.example.org".into()))
+        .await
+        .unwrap();
+    assert_eq!(tab.id, "T2");
+
+    let err = resolve_target(&chrome, &ShareTarget::Index(5))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("has 2 open"), "{}", err);
+    let err = resolve_target(&chrome, &ShareTarget::Url("elsewhere.net".into()))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("https://elsewhere.net"), "{}", err);
+}
+
+#[tokio::test]
+async fn share_target_urls_match_exactly_never_by_prefix() {
+    let fake = FakeCdp::start(&[
+        ("T1", "https://app.example.com/"),
+        ("T2", "https://app.example.com/settings"),
+        ("T3", "https://mail.example.org/inbox"),
+    ]);
+    let chrome = ChromeBrowser::new(fake.endpoint.clone());
+    let pick = async |url: &str| resolve_target(&chrome, &ShareTarget::Url(url.into())).await;
+
+    assert!(pick("https://a").await.is_err());
+    assert!(pick("https://mail.example.org/in").await.is_err());
+    assert_eq!(
+        pick("https://app.example.com/settings").await.unwrap().id,
+        "T2"
+    );
+    // The root is both a full URL and an origin; the tab on exactly it wins.
+    assert_eq!(pick("app.example.com").await.unwrap().id, "T1");
+
+    fake.add_target(serde_json::json!({
+        "id": "T4",
+        "type": "page",
+        "title": "T4",
+        "url": "https://mail.example.org/sent",
+    }));
+    let err = pick("mail.example.org").await.unwrap_err();
+    assert!(err.to_string().contains("more than one tab"), "{}", err);
+}
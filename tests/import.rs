@@ -0,0 +1,293 @@
+mod common;
+
+use common::FakeCdp;
+use serde_json::Value;
+use sharekaro::chrome::{
+    Cookie, CookieFormat, export_cookies_for_tab, import_and_open_with_cookies_from_memory,
+    import_to_n, navigate_tab, preview_import, resolve_tab, universal_cookie_loader,
+};
+use sharekaro::network::{GrantMessage, ReceiverState, import_grant};
+use std::sync::Mutex;
+
+fn cookie(name: &str, value: &str) -> Cookie {
+    serde_json::from_value(serde_json::json!({
+        "domain": "app.example.com",
+        "name": name,
+        "path": "/",
+        "value": value,
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn one_cookie_set_is_imported_into_several_tabs() {
+    let fake = FakeCdp::start(&[]);
+    let cookies = [cookie("sid", "s3cret"), cookie("theme", "dark")];
+
+    let (ids, report) = import_to_n(
+        &fake.endpoint,
+        &cookies,
+        "https://app.example.com/",
+        3,
+        false,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(ids, ["NEW1", "NEW2", "NEW3"]);
+    assert_eq!(fake.tab_ids(), ids);
+    assert_eq!(report.verified, 2);
+    assert!(report.failed.is_empty());
+
+    // Cookies go through a session attached to the first tab, which is
+    // detached again afterwards.
+    let commands: Vec<_> = fake.commands.try_iter().map(|(_, cmd)| cmd).collect();
+    let set_in: Vec<&str> = commands
+        .iter()
+        .filter(|cmd| cmd["method"] == "Network.setCookie")
+        .map(|cmd| cmd["sessionId"].as_str().unwrap())
+        .collect();
+    assert_eq!(set_in, ["S-NEW1", "S-NEW1"]);
+    let detached: Vec<&Value> = commands
+        .iter()
+        .filter(|cmd| cmd["method"] == "Target.detachFromTarget")
+        .map(|cmd| &cmd["params"]["sessionId"])
+        .collect();
+    assert_eq!(detached, [&serde_json::json!("S-NEW1")]);
+    assert_eq!(fake.cookies().len(), 2);
+}
+
+#[test]
+fn previews_show_cookies_as_an_import_would_set_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("export.json");
+    let export = serde_json::json!([
+        {
+            "domain": ".example.com", "name": "sid", "path": "/", "value": "v",
+            "sameSite": "no_restriction", "secure": false, "expires": -1, "session": true,
+        },
+        {
+            "domain": ".example.com", "name": "theme", "path": "/", "value": "dark",
+            "sameSite": "unspecified", "expires": 1900000000.0,
+        },
+    ]);
+    std::fs::write(&file, export.to_string()).unwrap();
+
+    let preview = preview_import(&file).unwrap();
+    assert_eq!(preview[0].sameSite.as_deref(), Some("None"));
+    assert_eq!(preview[0].secure, Some(true));
+    assert_eq!(preview[0].expires, None);
+    assert_eq!(preview[1].sameSite, None);
+    assert_eq!(preview[1].expires, Some(1900000000.0));
+
+    std::fs::write(&file, "not cookies").unwrap();
+    assert!(preview_import(&file).is_err());
+}
+
+#[tokio::test]
+async fn zero_tabs_is_an_error() {
+    let fake = FakeCdp::start(&[]);
+    assert!(
+        import_to_n(&fake.endpoint, &[], "https://app.example.com/", 0, false)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn background_imports_do_not_take_focus() {
+    let fake = FakeCdp::start(&[("T1", "https://mail.example.com/")]);
+    let cookies = [cookie("sid", "s3cret")];
+
+    let (ids, report) = import_to_n(
+        &fake.endpoint,
+        &cookies,
+        "https://app.example.com/",
+        2,
+        true,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(ids, ["NEW1", "NEW2"]);
+    assert_eq!(report.verified, 1);
+    let created: Vec<_> = fake
+        .commands
+        .try_iter()
+        .filter(|(_, cmd)| cmd["method"] == "Target.createTarget")
+        .collect();
+    assert_eq!(created.len(), 2);
+    for (target, cmd) in created {
+        assert_eq!(target, "browser");
+        assert_eq!(cmd["params"]["background"], true);
+        assert_eq!(cmd["params"]["url"], "https://app.example.com/");
+    }
+}
+
+#[tokio::test]
+async fn partitioned_cookies_keep_their_partition_key_through_export_and_import() {
+    let fake = FakeCdp::start(&[("T1", "https://embed.example.com/")]);
+    let partition = serde_json::json!({
+        "topLevelSite": "https://host.example",
+        "hasCrossSiteAncestor": true,
+    });
+    let chips: Cookie = serde_json::from_value(serde_json::json!({
+        "domain": "embed.example.com",
+        "name": "__Host-login",
+        "path": "/",
+        "value": "v",
+        "secure": true,
+        "partitionKey": partition,
+    }))
+    .unwrap();
+
+    import_and_open_with_cookies_from_memory(&fake.endpoint, &[chips], "embed.example.com", false)
+        .await
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let tab = resolve_tab(&fake.endpoint, "T1").await.unwrap();
+    let file = export_cookies_for_tab(
+        &fake.endpoint,
+        &tab,
+        dir.path(),
+        "cookies_{id}.{ext}",
+        CookieFormat::Json,
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!(file, dir.path().join("cookies_T1.json"));
+    let exported = universal_cookie_loader(&file).unwrap();
+    assert_eq!(exported[0].extra["partitionKey"], partition);
+
+    import_and_open_with_cookies_from_memory(&fake.endpoint, &exported, "embed.example.com", false)
+        .await
+        .unwrap();
+    let keys: Vec<_> = fake
+        .commands
+        .try_iter()
+        .filter(|(_, cmd)| cmd["method"] == "Network.setCookie")
+        .map(|(_, cmd)| cmd["params"]["partitionKey"].clone())
+        .collect();
+    assert_eq!(keys, [partition.clone(), partition]);
+}
+
+#[tokio::test]
+async fn open_in_current_reuses_the_focused_tab() {
+    let fake = FakeCdp::start(&[("T1", "https://old.example.com/")]);
+    let state = Mutex::new(ReceiverState::default());
+    let grant = GrantMessage {
+        tab_id: "R1".into(),
+        url: "https://app.example.com/".into(),
+        cookies: vec![cookie("sid", "one")],
+        indexed_db: Vec::new(),
+        storage: Default::default(),
+        encrypted: false,
+        open_in_current: true,
+        op_id: String::new(),
+        source: None,
+    };
+    import_grant(&fake.endpoint, &state, grant, "ws://sender:9234".into()).await;
+
+    assert_eq!(fake.tab_ids(), ["T1"]);
+    let navigated: Vec<_> = fake
+        .commands
+        .try_iter()
+        .filter(|(_, cmd)| cmd["method"] == "Page.navigate")
+        .map(|(_, cmd)| cmd["params"]["url"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(navigated, ["https://app.example.com/"]);
+    assert_eq!(fake.cookies(), [("sid".to_string(), "one".to_string())]);
+    assert_eq!(state.lock().unwrap().received[0].local_tab_id, "T1");
+}
+
+#[tokio::test]
+async fn regranting_a_tab_updates_the_tab_it_opened() {
+    let fake = FakeCdp::start(&[]);
+    let state = Mutex::new(ReceiverState::default());
+    let grant = |url: &str, value: &str| GrantMessage {
+        tab_id: "R1".into(),
+        url: url.into(),
+        cookies: vec![cookie("sid", value)],
+        indexed_db: Vec::new(),
+        storage: Default::default(),
+        encrypted: false,
+        open_in_current: false,
+        op_id: String::new(),
+        source: None,
+    };
+    let from = "ws://sender:9234".to_string();
+
+    import_grant(
+        &fake.endpoint,
+        &state,
+        grant("https://app.example.com/", "one"),
+        from.clone(),
+    )
+    .await;
+    import_grant(
+        &fake.endpoint,
+        &state,
+        grant("https://app.example.com/inbox", "two"),
+        from.clone(),
+    )
+    .await;
+    assert_eq!(fake.tab_ids(), ["NEW1"]);
+    // The first grant navigates the tab it just opened, the second reuses it.
+    let navigated: Vec<_> = fake
+        .commands
+        .try_iter()
+        .filter(|(_, cmd)| cmd["method"] == "Page.navigate")
+        .map(|(_, cmd)| {
+            let session = cmd["sessionId"].as_str().unwrap().to_string();
+            (session, cmd["params"]["url"].as_str().unwrap().to_string())
+        })
+        .collect();
+    assert_eq!(navigated.len(), 2);
+    assert_eq!(
+        navigated[1],
+        (
+            "S-NEW1".to_string(),
+            "https://app.example.com/inbox".to_string()
+        )
+    );
+    assert_eq!(fake.cookies(), [("sid".to_string(), "two".to_string())]);
+    {
+        let state = state.lock().unwrap();
+        assert_eq!(state.received.len(), 1);
+        assert_eq!(state.received[0].url, "https://app.example.com/inbox");
+    }
+
+    // The same tab id from another sender is a different share.
+    import_grant(
+        &fake.endpoint,
+        &state,
+        grant("https://app.example.com/", "three"),
+        "ws://other:9234".into(),
+    )
+    .await;
+    assert_eq!(fake.tab_ids(), ["NEW1", "NEW2"]);
+}
+
+#[tokio::test]
+async fn navigating_a_tab_skips_cookies_chrome_rejects() {
+    let fake = FakeCdp::start(&[("T1", "https://old.example.com/")]);
+    let report = navigate_tab(
+        &fake.endpoint,
+        "T1",
+        "https://app.example.com/",
+        &[cookie("bad", "reject-me"), cookie("sid", "one")],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(report.verified, 1);
+    assert_eq!(report.failed.len(), 1);
+    assert!(report.failed[0].0.starts_with("bad "));
+    assert_eq!(fake.cookies(), [("sid".to_string(), "one".to_string())]);
+    assert!(
+        fake.commands
+            .try_iter()
+            .any(|(_, cmd)| cmd["method"] == "Page.navigate")
+    );
+}
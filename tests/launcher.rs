@@ -0,0 +1,45 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::FakeCdp;
+use sharekaro::chrome::{ChromeHandle, fetch_tabs, launch_browser, launch_chrome_with_cdp};
+
+#[tokio::test]
+async fn launch_then_fetch_tabs_with_a_stub_browser() {
+    let fake = FakeCdp::start(&[("T1", "https://example.com/")]);
+    let cdp = fake.endpoint.clone();
+
+    let stub = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/fake-browser.sh");
+    let extra = ["--headless=new".to_string(), "--disable-gpu".to_string()];
+    let (mut child, profile) = launch_browser(&stub, None, &cdp, &extra).unwrap();
+
+    let args_file = profile.path().join("launch-args");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !args_file.exists() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+    }
+    let args = std::fs::read_to_string(&args_file).unwrap();
+    assert!(args.contains(&format!("--remote-debugging-port={}", cdp.port)));
+    assert!(args.trim_end().ends_with("--headless=new --disable-gpu"));
+
+    let tabs = fetch_tabs(&cdp).await.unwrap();
+    assert_eq!(tabs.len(), 1);
+    assert_eq!(tabs[0].url, "https://example.com/");
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[tokio::test]
+async fn a_browser_already_on_the_port_is_attached_to_not_respawned() {
+    let fake = FakeCdp::start(&[("T1", "https://example.com/")]);
+    let handle = launch_chrome_with_cdp(None, &fake.endpoint, &[])
+        .await
+        .unwrap();
+    assert!(matches!(handle, ChromeHandle::Attached));
+}
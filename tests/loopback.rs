@@ -0,0 +1,28 @@
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+use sharekaro::chrome::CdpEndpoint;
+use sharekaro::network::{ReceiverState, ServerOptions, connect_client, spawn_server};
+
+#[tokio::test]
+async fn receiver_refuses_its_own_server() {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let addr = format!("127.0.0.1:{}", port);
+    let _server = spawn_server(addr.parse().unwrap(), ServerOptions::default())
+        .await
+        .unwrap();
+
+    let result = connect_client(
+        CdpEndpoint::default(),
+        format!("ws://{}", addr),
+        None,
+        Arc::new(Mutex::new(ReceiverState::default())),
+    )
+    .await;
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("own server"), "{}", err);
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::FakeCdp;
+use futures::SinkExt;
+use serde_json::{Value, json};
+use sharekaro::network::{ReceivedShare, ReceiverState, connect_client};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{WebSocketStream, accept_async};
+
+type Peer = WebSocketStream<tokio::net::TcpStream>;
+
+// A sender that skips the key exchange, so nothing it sends is encrypted.
+async fn plain_sender(state: &Arc<Mutex<ReceiverState>>, fake: &FakeCdp) -> Peer {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(connect_client(
+        fake.endpoint.clone(),
+        url,
+        None,
+        Arc::clone(state),
+    ));
+    let (stream, _) = tokio::time::timeout(Duration::from_secs(3), listener.accept())
+        .await
+        .expect("receiver did not connect")
+        .unwrap();
+    let mut ws = accept_async(stream).await.unwrap();
+    send(
+        &mut ws,
+        json!({ "type": "Hello", "instance": "some-other-server" }),
+    )
+    .await;
+    ws
+}
+
+async fn send(ws: &mut Peer, frame: Value) {
+    ws.send(Message::Text(frame.to_string().into()))
+        .await
+        .unwrap();
+}
+
+fn received(remote: &str, local: &str, url: &str) -> ReceivedShare {
+    ReceivedShare {
+        remote_tab_id: remote.into(),
+        local_tab_id: local.into(),
+        url: url.into(),
+        from: "ws://sender:9234".into(),
+        cookie_count: 1,
+        revoked: false,
+        source: None,
+    }
+}
+
+// The methods the fake saw up to and including the first `until`.
+fn methods_until(fake: &FakeCdp, until: &str) -> Vec<String> {
+    let mut seen = Vec::new();
+    while let Ok((_, cmd)) = fake.commands.recv_timeout(Duration::from_secs(3)) {
+        let method = cmd["method"].as_str().unwrap_or_default().to_string();
+        let done = method == until;
+        seen.push(method);
+        if done {
+            return seen;
+        }
+    }
+    panic!("no {} after {:?}", until, seen);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unencrypted_navigations_with_cookies_are_refused_under_a_secret() {
+    let fake = FakeCdp::start(&[("L1", "https://app.example.com/")]);
+    let state = Arc::new(Mutex::new(ReceiverState {
+        received: vec![received("R1", "L1", "https://app.example.com/")],
+        secret: Some("correct horse battery staple".into()),
+        ..ReceiverState::default()
+    }));
+    let mut sender = plain_sender(&state, &fake).await;
+
+    let cookie = json!({ "name": "sid", "value": "v", "domain": "app.example.com", "path": "/" });
+    send(
+        &mut sender,
+        json!({
+            "type": "Navigate",
+            "tab_id": "R1",
+            "url": "https://app.example.com/inbox",
+            "cookies": [cookie],
+        }),
+    )
+    .await;
+    // Without cookies there is nothing to protect, so this one is followed,
+    // and shows the first was handled and dropped rather than still queued.
+    send(
+        &mut sender,
+        json!({ "type": "Navigate", "tab_id": "R1", "url": "https://app.example.com/sent" }),
+    )
+    .await;
+
+    // Navigations run off the receive loop, so give a wrongly accepted first
+    // one time to land as well.
+    let mut methods = methods_until(&fake, "Page.navigate");
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    methods.extend(
+        fake.commands
+            .try_iter()
+            .map(|(_, cmd)| cmd["method"].as_str().unwrap_or_default().to_string()),
+    );
+    assert!(
+        !methods.iter().any(|m| m == "Network.setCookie"),
+        "{:?}",
+        methods
+    );
+    assert!(fake.cookies().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mirrored_cookies_are_dropped_while_grants_need_approval() {
+    let fake = FakeCdp::start(&[("L1", "https://app.example.com/")]);
+    let state = Arc::new(Mutex::new(ReceiverState {
+        received: vec![received("R1", "L1", "https://app.example.com/")],
+        approve_grants: true,
+        ..ReceiverState::default()
+    }));
+    let mut sender = plain_sender(&state, &fake).await;
+
+    let cookie = json!({ "name": "sid", "value": "v", "domain": "app.example.com", "path": "/" });
+    send(
+        &mut sender,
+        json!({
+            "type": "Navigate",
+            "tab_id": "R1",
+            "url": "https://app.example.com/inbox",
+            "cookies": [cookie],
+        }),
+    )
+    .await;
+
+    // Cookies would be set before the navigation, in the same task.
+    let methods = methods_until(&fake, "Page.navigate");
+    assert!(
+        !methods.iter().any(|m| m == "Network.setCookie"),
+        "{:?}",
+        methods
+    );
+    assert!(fake.cookies().is_empty());
+    assert!(state.lock().unwrap().pending.is_empty());
+}
@@ -0,0 +1,61 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::SinkExt;
+use sharekaro::chrome::{CdpEndpoint, ReconnectPolicy};
+use sharekaro::network::{PeerLink, ReceiverState, connect_client};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{WebSocketStream, accept_async};
+
+async fn accept(listener: &TcpListener) -> WebSocketStream<tokio::net::TcpStream> {
+    let (stream, _) = tokio::time::timeout(Duration::from_secs(3), listener.accept())
+        .await
+        .expect("receiver did not reconnect")
+        .unwrap();
+    accept_async(stream).await.unwrap()
+}
+
+async fn hello(ws: &mut WebSocketStream<tokio::net::TcpStream>) {
+    let hello = r#"{"type":"Hello","instance":"some-other-server"}"#;
+    ws.send(Message::Text(hello.into())).await.unwrap();
+}
+
+#[tokio::test]
+async fn receivers_reconnect_after_drops_and_missed_pings() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let state = Arc::new(Mutex::new(ReceiverState {
+        reconnect: ReconnectPolicy {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+            keepalive: Duration::from_millis(100),
+        },
+        ..ReceiverState::default()
+    }));
+    let receiver = tokio::spawn(connect_client(
+        CdpEndpoint::default(),
+        url,
+        None,
+        Arc::clone(&state),
+    ));
+
+    // The server goes away outright.
+    let mut first = accept(&listener).await;
+    hello(&mut first).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(state.lock().unwrap().link, PeerLink::Connected);
+    drop(first);
+
+    // This one stays open but never reads, so the receiver's pings go
+    // unanswered.
+    let mut silent = accept(&listener).await;
+    assert!(matches!(
+        state.lock().unwrap().link,
+        PeerLink::Reconnecting { attempt: 1, .. }
+    ));
+    hello(&mut silent).await;
+    accept(&listener).await;
+    assert!(!receiver.is_finished());
+    receiver.abort();
+}
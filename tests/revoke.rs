@@ -0,0 +1,256 @@
+mod common;
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use common::FakeCdp;
+use sharekaro::chrome::{Cookie, fetch_tabs, revoke_cookies, set_cookies};
+use sharekaro::network::{
+    ReceivedShare, ReceiverState, RevokeBatchMessage, RevokeCookie, RevokeMessage, revoke_batch,
+};
+
+fn revoke(tab_id: &str, names: &[&str]) -> RevokeMessage {
+    RevokeMessage {
+        tab_id: tab_id.into(),
+        url: format!("https://{}.example.com/", tab_id),
+        cookies: names
+            .iter()
+            .map(|name| RevokeCookie {
+                name: name.to_string(),
+                domain: "example.com".into(),
+                path: "/".into(),
+            })
+            .collect(),
+        whole_site: false,
+        op_id: String::new(),
+    }
+}
+
+fn received(remote: &str, local: &str) -> ReceivedShare {
+    ReceivedShare {
+        remote_tab_id: remote.into(),
+        local_tab_id: local.into(),
+        url: String::new(),
+        from: "ws://sender:9234".into(),
+        cookie_count: 1,
+        revoked: false,
+        source: None,
+    }
+}
+
+#[tokio::test]
+async fn batch_revoke_deletes_cookies_in_every_listed_tab() {
+    let fake = FakeCdp::start(&[
+        ("L1", "https://a.example.com/"),
+        ("L2", "https://b.example.com/"),
+        ("L3", "https://c.example.com/"),
+    ]);
+    // Two tabs were opened from grants, one is addressed by its own id.
+    let state = Mutex::new(ReceiverState {
+        received: vec![received("R1", "L1"), received("R2", "L2")],
+        ..ReceiverState::default()
+    });
+    let batch = RevokeBatchMessage {
+        tabs: vec![
+            revoke("R1", &["sid", "csrf"]),
+            revoke("R2", &["sid"]),
+            revoke("L3", &["token"]),
+        ],
+    };
+
+    let failed = revoke_batch(&fake.endpoint, &state, batch).await;
+    assert_eq!(failed, 0);
+
+    // revoke_cookies waits for each reply, and the fake records a command
+    // before replying, so everything is already queued.
+    let deleted: HashSet<(String, String)> = fake
+        .commands
+        .try_iter()
+        .filter(|(_, cmd)| cmd["method"] == "Network.deleteCookies")
+        .map(|(tab, cmd)| (tab, cmd["params"]["name"].as_str().unwrap().to_string()))
+        .collect();
+    let expected: HashSet<(String, String)> = [
+        ("L1", "sid"),
+        ("L1", "csrf"),
+        ("L2", "sid"),
+        ("L3", "token"),
+    ]
+    .iter()
+    .map(|(t, n)| (t.to_string(), n.to_string()))
+    .collect();
+    assert_eq!(deleted, expected);
+    assert!(state.lock().unwrap().received.iter().all(|r| r.revoked));
+}
+
+#[tokio::test]
+async fn revoke_counts_the_deletes_chrome_confirmed() {
+    let fake = FakeCdp::start(&[("T1", "https://a.example.com/")]);
+    let deleted = revoke_cookies(
+        &fake.endpoint,
+        "T1",
+        &[("sid", "example.com", "/"), ("csrf", "example.com", "/")],
+    )
+    .await
+    .unwrap();
+    assert_eq!(deleted, 2);
+}
+
+#[tokio::test]
+async fn whole_site_revokes_delete_every_cookie_and_clear_site_data() {
+    let fake = FakeCdp::start(&[("L1", "https://a.example.com/app")]);
+    let cookies: Vec<Cookie> = ["sid", "csrf", "theme"]
+        .iter()
+        .map(|name| {
+            serde_json::from_value(serde_json::json!({
+                "name": name, "domain": "a.example.com", "path": "/", "value": "v",
+            }))
+            .unwrap()
+        })
+        .collect();
+    set_cookies(&fake.endpoint, "a.example.com", &cookies)
+        .await
+        .unwrap();
+    let state = Mutex::new(ReceiverState {
+        received: vec![ReceivedShare {
+            url: "https://a.example.com/app".into(),
+            ..received("R1", "L1")
+        }],
+        ..ReceiverState::default()
+    });
+
+    // The sender only knew about one of the three.
+    let batch = RevokeBatchMessage {
+        tabs: vec![RevokeMessage {
+            url: "https://a.example.com/app".into(),
+            whole_site: true,
+            ..revoke("R1", &["sid"])
+        }],
+    };
+    assert_eq!(revoke_batch(&fake.endpoint, &state, batch).await, 0);
+    assert!(fake.cookies().is_empty());
+    let cleared: Vec<_> = fake
+        .commands
+        .try_iter()
+        .filter(|(_, cmd)| cmd["method"] == "Storage.clearDataForOrigin")
+        .map(|(_, cmd)| cmd["params"]["origin"].clone())
+        .collect();
+    assert_eq!(cleared, ["https://a.example.com"]);
+}
+
+#[tokio::test]
+async fn whole_site_revokes_only_clear_the_granted_site() {
+    let fake = FakeCdp::start(&[
+        ("L1", "https://a.example.com/app"),
+        ("L2", "https://bank.example.net/"),
+    ]);
+    let cookie: Cookie = serde_json::from_value(serde_json::json!({
+        "name": "sid", "domain": "bank.example.net", "path": "/", "value": "v",
+    }))
+    .unwrap();
+    set_cookies(&fake.endpoint, "bank.example.net", &[cookie])
+        .await
+        .unwrap();
+    let state = Mutex::new(ReceiverState {
+        received: vec![ReceivedShare {
+            url: "https://a.example.com/app".into(),
+            ..received("R1", "L1")
+        }],
+        ..ReceiverState::default()
+    });
+
+    // One names another site for a tab it did grant, one a tab it never did.
+    let batch = RevokeBatchMessage {
+        tabs: vec![
+            RevokeMessage {
+                url: "https://bank.example.net/".into(),
+                whole_site: true,
+                ..revoke("R1", &[])
+            },
+            RevokeMessage {
+                url: "https://bank.example.net/".into(),
+                whole_site: true,
+                ..revoke("L2", &[])
+            },
+        ],
+    };
+    assert_eq!(revoke_batch(&fake.endpoint, &state, batch).await, 2);
+    assert_eq!(fake.cookies().len(), 1);
+    assert!(
+        !fake
+            .commands
+            .try_iter()
+            .any(|(_, cmd)| cmd["method"] == "Storage.clearDataForOrigin")
+    );
+}
+
+#[tokio::test]
+async fn revokes_after_a_restart_find_the_tabs_opened_before_it() {
+    let fake = FakeCdp::start(&[("L1", "https://a.example.com/")]);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("received.json");
+    // What the previous run left behind; its second tab has since closed.
+    let saved = vec![received("R1", "L1"), received("R2", "GONE")];
+    std::fs::write(&path, serde_json::to_string(&saved).unwrap()).unwrap();
+
+    let mut restarted = ReceiverState::default();
+    let open = fetch_tabs(&fake.endpoint).await.unwrap();
+    restarted.persist_at(path.clone(), Some(&open));
+    assert_eq!(restarted.received.len(), 1);
+    let state = Mutex::new(restarted);
+
+    let batch = RevokeBatchMessage {
+        tabs: vec![revoke("R1", &["sid"])],
+    };
+    assert_eq!(revoke_batch(&fake.endpoint, &state, batch).await, 0);
+    let revoked_in: Vec<String> = fake
+        .commands
+        .try_iter()
+        .filter(|(_, cmd)| cmd["method"] == "Network.deleteCookies")
+        .map(|(tab, _)| tab)
+        .collect();
+    assert_eq!(revoked_in, ["L1"]);
+
+    let on_disk: Vec<ReceivedShare> =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(on_disk.len(), 1);
+    assert!(on_disk[0].revoked);
+}
+
+#[tokio::test]
+async fn close_on_revoke_closes_only_tabs_a_grant_opened() {
+    let fake = FakeCdp::start(&[
+        ("L1", "https://a.example.com/"),
+        ("L2", "https://b.example.com/"),
+    ]);
+    let state = Mutex::new(ReceiverState {
+        received: vec![received("R1", "L1")],
+        close_on_revoke: true,
+        ..ReceiverState::default()
+    });
+    let batch = RevokeBatchMessage {
+        tabs: vec![revoke("R1", &["sid"]), revoke("L2", &["sid"])],
+    };
+
+    assert_eq!(revoke_batch(&fake.endpoint, &state, batch).await, 0);
+    // L2 was addressed by its own id, so it is the user's and stays open.
+    assert_eq!(fake.tab_ids(), ["L2"]);
+    assert!(state.lock().unwrap().received.is_empty());
+}
+
+#[tokio::test]
+async fn a_failed_revoke_leaves_the_share_unrevoked() {
+    let fake = FakeCdp::start(&[("L1", "https://a.example.com/")]);
+    // The tab this share opened has since closed, so the delete can't happen.
+    let state = Mutex::new(ReceiverState {
+        received: vec![received("R1", "L1"), received("R2", "GONE")],
+        ..ReceiverState::default()
+    });
+    let batch = RevokeBatchMessage {
+        tabs: vec![revoke("R1", &["sid"]), revoke("R2", &["sid"])],
+    };
+
+    assert_eq!(revoke_batch(&fake.endpoint, &state, batch).await, 1);
+    let state = state.lock().unwrap();
+    assert!(state.received[0].revoked);
+    assert!(!state.received[1].revoked);
+}
@@ -0,0 +1,233 @@
+mod common;
+
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::FakeCdp;
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use sharekaro::browser::ChromeBrowser;
+use sharekaro::network::{GrantMessage, PullOptions, RevokeAck, ServerOptions, spawn_server};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[tokio::test]
+async fn dropping_the_app_side_senders_winds_the_server_down() {
+    let addr = format!("127.0.0.1:{}", free_port());
+    let broadcasts = spawn_server(addr.parse().unwrap(), ServerOptions::default())
+        .await
+        .unwrap();
+
+    let (mut peer, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+    let hello = peer.next().await.unwrap().unwrap();
+    assert!(hello.to_text().unwrap().contains("Hello"));
+
+    drop(broadcasts);
+
+    let frame = tokio::time::timeout(Duration::from_secs(2), peer.next())
+        .await
+        .expect("peer was not closed")
+        .unwrap()
+        .unwrap();
+    match frame {
+        Message::Close(Some(close)) => assert_eq!(close.reason, "server shutting down"),
+        other => panic!("expected a close frame, got {:?}", other),
+    }
+
+    // The accept loop has exited and released the port.
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while TcpListener::bind(&addr).is_err() {
+        assert!(Instant::now() < deadline, "listener still bound");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn servers_in_one_process_are_independent() {
+    let work = spawn_server("127.0.0.1:0".parse().unwrap(), ServerOptions::default())
+        .await
+        .unwrap();
+    let home = spawn_server("127.0.0.1:0".parse().unwrap(), ServerOptions::default())
+        .await
+        .unwrap();
+    assert_ne!(work.addr(), home.addr());
+
+    let (mut work_peer, _) = connect_async(format!("ws://{}", work.addr()))
+        .await
+        .unwrap();
+    let (mut home_peer, _) = connect_async(format!("ws://{}", home.addr()))
+        .await
+        .unwrap();
+    for peer in [&mut work_peer, &mut home_peer] {
+        let hello = peer.next().await.unwrap().unwrap();
+        assert!(hello.to_text().unwrap().contains("Hello"));
+    }
+    assert_eq!(work.peers().len(), 1);
+    assert_eq!(home.peers().len(), 1);
+
+    let grant = GrantMessage {
+        tab_id: "T1".into(),
+        url: "https://work.example.com/".into(),
+        cookies: Vec::new(),
+        indexed_db: Vec::new(),
+        storage: Default::default(),
+        encrypted: false,
+        open_in_current: false,
+        op_id: String::new(),
+        source: None,
+    };
+    assert_eq!(work.grants.send(grant).ok(), Some(1));
+
+    let frame = tokio::time::timeout(Duration::from_secs(2), work_peer.next())
+        .await
+        .expect("work peer got nothing")
+        .unwrap()
+        .unwrap();
+    assert!(frame.to_text().unwrap().contains("work.example.com"));
+    let nothing = tokio::time::timeout(Duration::from_millis(200), home_peer.next()).await;
+    assert!(nothing.is_err(), "grant leaked to the other server");
+
+    home.shutdown(Duration::from_secs(1)).await;
+    assert!(matches!(
+        home_peer.next().await,
+        Some(Ok(Message::Close(_)))
+    ));
+    assert_eq!(work.peers().len(), 1);
+}
+
+#[tokio::test]
+async fn peers_without_the_auth_token_get_nothing() {
+    let server = spawn_server(
+        "127.0.0.1:0".parse().unwrap(),
+        ServerOptions {
+            auth_token: Some("letmein".into()),
+            ..ServerOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    let url = format!("ws://{}", server.addr());
+
+    for token in ["wrong", "letmei"] {
+        let (mut peer, _) = connect_async(&url).await.unwrap();
+        let auth = format!(r#"{{"type":"Auth","token":"{}"}}"#, token);
+        peer.send(Message::Text(auth.into())).await.unwrap();
+        match peer.next().await {
+            Some(Ok(Message::Close(Some(close)))) => {
+                assert_eq!(close.reason, "authentication failed")
+            }
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+    }
+    assert!(server.peers().is_empty());
+
+    let (mut peer, _) = connect_async(&url).await.unwrap();
+    let auth = r#"{"type":"Auth","token":"letmein"}"#;
+    peer.send(Message::Text(auth.into())).await.unwrap();
+    let hello = peer.next().await.unwrap().unwrap();
+    assert!(hello.to_text().unwrap().contains("Hello"));
+    assert_eq!(server.peers().len(), 1);
+}
+
+#[tokio::test]
+async fn revoke_acks_from_peers_reach_the_app() {
+    let server = spawn_server("127.0.0.1:0".parse().unwrap(), ServerOptions::default())
+        .await
+        .unwrap();
+    let mut acks = server.revoke_acks.subscribe();
+
+    let (mut peer, _) = connect_async(format!("ws://{}", server.addr()))
+        .await
+        .unwrap();
+    peer.next().await.unwrap().unwrap();
+    let ack = r#"{"type":"RevokeAck","tab_id":"T1","deleted":3,"op_id":"op-1"}"#;
+    peer.send(Message::Text(ack.into())).await.unwrap();
+
+    let got = tokio::time::timeout(Duration::from_secs(2), acks.recv())
+        .await
+        .expect("no ack arrived")
+        .unwrap();
+    assert_eq!(
+        got,
+        RevokeAck {
+            tab_id: "T1".into(),
+            deleted: 3,
+            op_id: "op-1".into(),
+        }
+    );
+}
+
+#[tokio::test]
+async fn peers_pull_tabs_by_url_when_the_server_allows_it() {
+    let fake = FakeCdp::start(&[("T1", "https://app.example.com/inbox")]);
+    let pulling = spawn_server(
+        "127.0.0.1:0".parse().unwrap(),
+        ServerOptions {
+            pull: Some(PullOptions {
+                browser: Arc::new(ChromeBrowser::new(fake.endpoint.clone())),
+                share_domains: Vec::new(),
+                encrypted: false,
+            }),
+            ..ServerOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    let push_only = spawn_server("127.0.0.1:0".parse().unwrap(), ServerOptions::default())
+        .await
+        .unwrap();
+
+    async fn request(addr: std::net::SocketAddr, url: &str) -> Value {
+        let (mut peer, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+        peer.next().await.unwrap().unwrap();
+        let request = serde_json::json!({ "type": "RequestTab", "url": url });
+        peer.send(Message::Text(request.to_string().into()))
+            .await
+            .unwrap();
+        let reply = tokio::time::timeout(Duration::from_secs(5), peer.next())
+            .await
+            .expect("no reply to the request")
+            .unwrap()
+            .unwrap();
+        serde_json::from_str(reply.to_text().unwrap()).unwrap()
+    }
+
+    let grant = request(pulling.addr(), "app.example.com").await;
+    assert_eq!(grant["type"], "Grant");
+    assert_eq!(grant["tab_id"], "T1");
+    assert_eq!(grant["url"], "https://app.example.com/inbox");
+
+    let refused = request(pulling.addr(), "https://other.example.com/").await;
+    assert_eq!(refused["type"], "RequestRefused");
+    assert!(refused["reason"].as_str().unwrap().contains("no open tab"));
+
+    let refused = request(push_only.addr(), "https://app.example.com/").await;
+    assert_eq!(refused["type"], "RequestRefused");
+}
+
+#[tokio::test]
+async fn a_silent_connection_does_not_hold_up_the_next_peer() {
+    let server = spawn_server("127.0.0.1:0".parse().unwrap(), ServerOptions::default())
+        .await
+        .unwrap();
+    let _silent = tokio::net::TcpStream::connect(server.addr()).await.unwrap();
+
+    let (mut peer, _) = tokio::time::timeout(
+        Duration::from_secs(2),
+        connect_async(format!("ws://{}", server.addr())),
+    )
+    .await
+    .expect("accept loop is stuck on the silent connection")
+    .unwrap();
+    let hello = peer.next().await.unwrap().unwrap();
+    assert!(hello.to_text().unwrap().contains("Hello"));
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::FakeCdp;
+use serde_json::json;
+use sharekaro::chrome::{
+    CdpEndpoint, CdpError, CdpSession, capture_screenshot, cdp_get_json, print_tabs_once,
+    resolve_tab,
+};
+
+#[tokio::test]
+async fn raw_commands_are_correlated_by_id() {
+    let fake = FakeCdp::start(&[("T1", "https://example.com/")]);
+    let tab = resolve_tab(&fake.endpoint, "T1").await.unwrap();
+    let mut page = CdpSession::page(&fake.endpoint, &tab).await.unwrap();
+
+    page.send_raw(
+        "Network.setCookie",
+        json!({ "name": "sid", "value": "v", "domain": "example.com", "path": "/" }),
+    )
+    .await
+    .unwrap();
+    let stored = page
+        .send_raw("Network.getAllCookies", json!({}))
+        .await
+        .unwrap();
+    assert_eq!(stored["cookies"][0]["name"], "sid");
+
+    let err = page
+        .send_raw("Fake.failPlease", json!({}))
+        .await
+        .unwrap_err();
+    let err = err.downcast_ref::<CdpError>().expect("a CdpError");
+    assert_eq!(err.method, "Fake.failPlease");
+    assert_eq!(err.code, -32601);
+
+    let ids: Vec<_> = fake
+        .commands
+        .try_iter()
+        .map(|(_, cmd)| cmd["id"].clone())
+        .collect();
+    assert_eq!(ids, [json!(1), json!(2), json!(3)]);
+}
+
+#[tokio::test]
+async fn browser_sessions_route_commands_to_a_target() {
+    let fake = FakeCdp::start(&[("T1", "https://example.com/")]);
+    let mut browser = CdpSession::browser(&fake.endpoint).await.unwrap();
+
+    let session = browser.attach("T1").await.unwrap();
+    assert_eq!(session, "S-T1");
+    browser
+        .send_to(
+            &session,
+            "Emulation.setDeviceMetricsOverride",
+            json!({ "width": 400 }),
+        )
+        .await
+        .unwrap();
+
+    let (target, cmd) = fake.commands.try_iter().last().unwrap();
+    assert_eq!(target, "browser");
+    assert_eq!(cmd["sessionId"], "S-T1");
+    assert_eq!(cmd["method"], "Emulation.setDeviceMetricsOverride");
+}
+
+#[tokio::test]
+async fn unreachable_endpoint_is_an_error_not_a_panic() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let cdp = CdpEndpoint::new("127.0.0.1", port);
+
+    let err = cdp_get_json(&cdp, "/json").await.unwrap_err().to_string();
+    assert!(err.contains(&format!("127.0.0.1:{}/json", port)), "{}", err);
+    assert!(print_tabs_once(&cdp).await.is_err());
+    assert!(CdpSession::browser(&cdp).await.is_err());
+}
+
+#[tokio::test]
+async fn screenshots_come_back_as_png_bytes() {
+    let fake = FakeCdp::start(&[("T1", "https://example.com/")]);
+    let tab = resolve_tab(&fake.endpoint, "T1").await.unwrap();
+    let png = capture_screenshot(&fake.endpoint, &tab).await.unwrap();
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+}
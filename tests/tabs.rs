@@ -0,0 +1,68 @@
+mod common;
+
+use common::FakeCdp;
+use serde_json::json;
+use sharekaro::chrome::{CdpEndpoint, fetch_tabs, get_active_tab, get_cookies_for_tab};
+
+#[tokio::test]
+async fn listing_keeps_user_tabs_and_points_their_sockets_at_the_endpoint() {
+    let fake = FakeCdp::start(&[("T1", "https://app.example.com/")]);
+    fake.add_target(json!({
+        "id": "SW",
+        "type": "service_worker",
+        "title": "sw.js",
+        "url": "https://app.example.com/sw.js",
+    }));
+    fake.add_target(json!({
+        "id": "DT",
+        "type": "page",
+        "title": "DevTools",
+        "url": "devtools://devtools/bundled/inspector.html",
+    }));
+    // Chrome behind a tunnel reports its own idea of the host.
+    fake.add_target(json!({
+        "id": "T2",
+        "type": "page",
+        "title": "Docs",
+        "url": "https://docs.example.com/",
+        "webSocketDebuggerUrl": "ws://localhost:9222/devtools/page/T2",
+    }));
+
+    let tabs = fetch_tabs(&fake.endpoint).await.unwrap();
+    let ids: Vec<_> = tabs.iter().map(|t| t.id.as_str()).collect();
+    assert_eq!(ids, ["T1", "T2"]);
+    assert_eq!(
+        tabs[1].webSocketDebuggerUrl.as_deref(),
+        Some(format!("ws://127.0.0.1:{}/devtools/page/T2", fake.endpoint.port).as_str())
+    );
+    assert_eq!(get_active_tab(&fake.endpoint).await.unwrap().id, "T1");
+
+    let everything = CdpEndpoint {
+        all_targets: true,
+        ..fake.endpoint.clone()
+    };
+    assert_eq!(fetch_tabs(&everything).await.unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn cookies_are_read_through_the_tabs_session() {
+    let fake = FakeCdp::start(&[("T1", "https://app.example.com/")]);
+    let tab = fetch_tabs(&fake.endpoint).await.unwrap().remove(0);
+    assert!(
+        get_cookies_for_tab(&fake.endpoint, &tab)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let asked: Vec<_> = fake
+        .commands
+        .try_iter()
+        .filter(|(_, cmd)| cmd["method"] == "Network.getCookies")
+        .map(|(_, cmd)| (cmd["sessionId"].clone(), cmd["params"]["urls"].clone()))
+        .collect();
+    assert_eq!(
+        asked,
+        [(json!("S-T1"), json!(["https://app.example.com/"]))]
+    );
+}
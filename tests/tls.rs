@@ -0,0 +1,58 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use sharekaro::chrome::CdpEndpoint;
+use sharekaro::network::{ReceiverState, ServerOptions, connect_client, spawn_server};
+use sharekaro::tls::load_identity;
+
+// Connect to our own server, which the receiver refuses right after the
+// Hello: reaching that error means the TLS handshake and upgrade succeeded.
+async fn receive(url: String, pin_cert: Option<String>) -> String {
+    let state = ReceiverState {
+        pin_cert,
+        ..ReceiverState::default()
+    };
+    connect_client(
+        CdpEndpoint::default(),
+        url,
+        None,
+        Arc::new(Mutex::new(state)),
+    )
+    .await
+    .unwrap_err()
+    .to_string()
+}
+
+#[tokio::test]
+async fn wss_peers_are_checked_against_the_pinned_certificate() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let identity = load_identity(
+        &fixtures.join("tls-cert.pem"),
+        &fixtures.join("tls-key.pem"),
+    )
+    .unwrap();
+    let server = spawn_server(
+        "127.0.0.1:0".parse().unwrap(),
+        ServerOptions {
+            tls: Some(identity.config),
+            ..ServerOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    let wss = format!("wss://{}", server.addr());
+
+    let err = receive(wss.clone(), Some(identity.fingerprint.to_lowercase())).await;
+    assert!(err.contains("own server"), "{}", err);
+
+    let wrong = "00:".repeat(31) + "00";
+    let err = receive(wss.clone(), Some(wrong)).await;
+    assert!(err.contains("failed to connect"), "{}", err);
+
+    // Self-signed, so the usual roots don't vouch for it.
+    let err = receive(wss, None).await;
+    assert!(err.contains("failed to connect"), "{}", err);
+
+    let err = receive(format!("ws://{}", server.addr()), None).await;
+    assert!(err.contains("failed to connect"), "{}", err);
+}